@@ -6,7 +6,7 @@ use anyhow::{bail, Result};
 use convert_case::{Case, Casing};
 use indexmap::IndexMap;
 use multimap::MultiMap;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fmt::Write;
@@ -105,6 +105,10 @@ struct I2cDevice {
     /// device is removable
     #[serde(default)]
     removable: bool,
+
+    /// device uses SMBus packet error checking
+    #[serde(default)]
+    pec: bool,
 }
 
 #[derive(Clone, Debug, Deserialize)]
@@ -159,6 +163,58 @@ struct I2cSensors {
 
     #[serde(default)]
     speed: usize,
+
+    #[serde(default)]
+    energy: usize,
+
+    /// default alarm thresholds, by sensor kind (e.g., "temperature")
+    #[serde(default)]
+    thresholds: BTreeMap<String, I2cThresholds>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct I2cThresholds {
+    lower_critical: Option<f32>,
+    lower_warning: Option<f32>,
+    upper_warning: Option<f32>,
+    upper_critical: Option<f32>,
+
+    /// amount by which a value must recede past a threshold to clear it
+    #[serde(default)]
+    hysteresis: f32,
+}
+
+impl I2cThresholds {
+    fn validate(&self) -> Result<()> {
+        let lower = self.lower_warning.or(self.lower_critical);
+        let upper = self.upper_warning.or(self.upper_critical);
+
+        if !(self.hysteresis >= 0.0) {
+            bail!("hysteresis must be non-negative");
+        }
+
+        match (self.lower_critical, self.lower_warning) {
+            (Some(c), Some(w)) if c > w => {
+                bail!("lower critical threshold exceeds lower warning")
+            }
+            _ => {}
+        }
+
+        match (self.upper_warning, self.upper_critical) {
+            (Some(w), Some(c)) if w > c => {
+                bail!("upper warning threshold exceeds upper critical")
+            }
+            _ => {}
+        }
+
+        match (lower, upper) {
+            (Some(l), Some(u)) if l >= u => {
+                bail!("lower thresholds must be below upper thresholds")
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 #[derive(Copy, Clone, PartialEq)]
@@ -169,6 +225,9 @@ pub enum Disposition {
     /// controller is a target
     Target,
 
+    /// controller is a target, and devices are used as sensors
+    TargetSensors,
+
     /// devices are used (i.e., controller is not used), but not as sensors
     Devices,
 
@@ -176,6 +235,12 @@ pub enum Disposition {
     Sensors,
 }
 
+impl Disposition {
+    fn is_target(&self) -> bool {
+        matches!(self, Disposition::Target | Disposition::TargetSensors)
+    }
+}
+
 #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
 enum Sensor {
     Temperature,
@@ -183,6 +248,27 @@ enum Sensor {
     Current,
     Voltage,
     Speed,
+    Energy,
+}
+
+///
+/// Returns the sensors that a part provides, by kind, if the part is one whose
+/// sensors are fixed.  A device may be configured with fewer sensors of a
+/// kind than its part provides, but not more.  (PMBus devices, whose sensors
+/// are per rail, are not included here.)
+///
+fn part_sensors(device: &str) -> Option<&'static [(Sensor, usize)]> {
+    use Sensor::*;
+
+    match device {
+        "adt7420" | "lm75" | "max6634" | "mcp9808" | "pct2075" | "sbtsi"
+        | "tmp116" | "tmp117" | "tse2004av" => Some(&[(Temperature, 1)]),
+        "tmp451" => Some(&[(Temperature, 2)]),
+        "ina226" => Some(&[(Current, 1), (Voltage, 1), (Power, 1)]),
+        "emc2305" => Some(&[(Speed, 5)]),
+        "max31790" => Some(&[(Speed, 6)]),
+        _ => None,
+    }
 }
 
 impl std::fmt::Display for Sensor {
@@ -196,11 +282,56 @@ impl std::fmt::Display for Sensor {
                 Sensor::Current => "CURRENT",
                 Sensor::Voltage => "VOLTAGE",
                 Sensor::Speed => "SPEED",
+                Sensor::Energy => "ENERGY",
             }
         )
     }
 }
 
+impl Sensor {
+    /// name of the corresponding `task_sensor_api::SensorKind` variant
+    fn variant(&self) -> &'static str {
+        match self {
+            Sensor::Temperature => "Temperature",
+            Sensor::Power => "Power",
+            Sensor::Current => "Current",
+            Sensor::Voltage => "Voltage",
+            Sensor::Speed => "Speed",
+            Sensor::Energy => "Energy",
+        }
+    }
+
+    /// unit of measurement, as named in `userlib::units`
+    fn unit(&self) -> &'static str {
+        match self {
+            Sensor::Temperature => "Celsius",
+            Sensor::Power => "Watts",
+            Sensor::Current => "Amperes",
+            Sensor::Voltage => "Volts",
+            Sensor::Speed => "Rpm",
+            Sensor::Energy => "Joules",
+        }
+    }
+}
+
+///
+/// Metadata describing a single sensor, as emitted into the generated
+/// `sensors::METADATA` table and (as JSON) into the build archive.
+///
+#[derive(Clone, Debug, Serialize)]
+struct SensorMetadata {
+    #[serde(skip)]
+    sensor: Sensor,
+    id: usize,
+    name: Option<String>,
+    kind: String,
+    unit: &'static str,
+    device: String,
+    description: String,
+    bus: String,
+    refdes: Option<String>,
+}
+
 struct ConfigGenerator {
     /// output that we're building
     output: String,
@@ -233,6 +364,10 @@ impl ConfigGenerator {
             }
         };
 
+        Self::from_config(i2c, disposition)
+    }
+
+    fn from_config(i2c: I2cConfig, disposition: Disposition) -> Self {
         let mut controllers = vec![];
         let mut buses = HashMap::new();
         let mut ports = IndexMap::new();
@@ -261,7 +396,7 @@ impl ConfigGenerator {
                 ports.insert((c.controller, p.clone()), index);
             }
 
-            if c.target != (disposition == Disposition::Target) {
+            if c.target != disposition.is_target() {
                 continue;
             }
 
@@ -330,7 +465,9 @@ impl ConfigGenerator {
                 panic!("illegal disposition for controller generation");
             }
 
-            Disposition::Initiator | Disposition::Target => {}
+            Disposition::Initiator
+            | Disposition::Target
+            | Disposition::TargetSensors => {}
         }
 
         writeln!(
@@ -399,7 +536,9 @@ impl ConfigGenerator {
                 panic!("illegal disposition for pin generation");
             }
 
-            Disposition::Initiator | Disposition::Target => {}
+            Disposition::Initiator
+            | Disposition::Target
+            | Disposition::TargetSensors => {}
         }
 
         for c in &self.controllers {
@@ -475,7 +614,7 @@ impl ConfigGenerator {
     }
 
     pub fn generate_muxes(&mut self) -> Result<()> {
-        if self.disposition == Disposition::Target {
+        if self.disposition.is_target() {
             panic!("cannot generate muxes when configured as target");
         }
 
@@ -633,12 +772,13 @@ impl ConfigGenerator {
                 PortIndex({port}),
                 {segment},
                 0x{address:x}
-            )"##,
+            ){pec}"##,
             description = d.description,
             controller = controller,
             port = port,
             segment = "None",
             address = d.address,
+            pec = if d.pec { ".with_pec()" } else { "" },
         )
     }
 
@@ -784,6 +924,35 @@ impl ConfigGenerator {
 
             let out = self.generate_device(device);
             writeln!(&mut self.output, "({}, {})\n        }}", out, index)?;
+
+            //
+            // A device with more than one rail must be paged to address any
+            // one of them; a device with a single rail is left unpaged, as
+            // it may not implement PAGE at all.
+            //
+            let nrails = device
+                .pmbus
+                .as_ref()
+                .and_then(|p| p.rails.as_ref())
+                .map_or(0, |r| r.len());
+
+            let page = if nrails > 1 {
+                format!("Some({})", index)
+            } else {
+                "None".to_string()
+            };
+
+            write!(
+                &mut self.output,
+                r##"
+        /// PMBus page of the {} rail, if its device must be paged
+        #[allow(dead_code)]
+        pub const {}_PAGE: Option<u8> = {};
+"##,
+                rail,
+                rail.to_uppercase(),
+                page
+            )?;
         }
 
         writeln!(&mut self.output, "    }}")?;
@@ -845,11 +1014,19 @@ impl ConfigGenerator {
         let mut bykind = MultiMap::new();
 
         let mut sensors = vec![];
+        let mut thresholds = vec![];
+        let metadata = self.sensor_metadata();
 
-        let mut add_sensor = |kind, d: &I2cDevice, idx: usize| {
+        let mut add_sensor = |kind: Sensor, d: &I2cDevice, idx: usize| {
             let id = sensors.len();
             sensors.push(kind);
 
+            thresholds.push(d.sensors.as_ref().and_then(|s| {
+                s.thresholds
+                    .get(&format!("{}", kind).to_lowercase())
+                    .cloned()
+            }));
+
             let name: Option<String> = if let Some(pmbus) = &d.pmbus {
                 if let Some(rails) = &pmbus.rails {
                     if idx < rails.len() {
@@ -885,6 +1062,53 @@ impl ConfigGenerator {
 
         for d in &self.devices {
             if let Some(s) = &d.sensors {
+                for (kind, t) in &s.thresholds {
+                    let count = match kind.as_str() {
+                        "temperature" => s.temperature,
+                        "power" => s.power,
+                        "current" => s.current,
+                        "voltage" => s.voltage,
+                        "speed" => s.speed,
+                        "energy" => s.energy,
+                        _ => bail!("{:?}: unknown sensor kind {}", d, kind),
+                    };
+
+                    if count == 0 {
+                        bail!("{:?}: thresholds for absent {} sensor", d, kind);
+                    }
+
+                    if let Err(err) = t.validate() {
+                        bail!("{:?}: bad {} thresholds: {}", d, kind, err);
+                    }
+                }
+
+                if let Some(provided) = part_sensors(&d.device) {
+                    for (kind, count) in [
+                        (Sensor::Temperature, s.temperature),
+                        (Sensor::Power, s.power),
+                        (Sensor::Current, s.current),
+                        (Sensor::Voltage, s.voltage),
+                        (Sensor::Speed, s.speed),
+                        (Sensor::Energy, s.energy),
+                    ] {
+                        let max = provided
+                            .iter()
+                            .find(|(k, _)| *k == kind)
+                            .map_or(0, |(_, max)| *max);
+
+                        if count > max {
+                            bail!(
+                                "{:?}: {} has {} {} sensor(s), not {}",
+                                d,
+                                d.device,
+                                max,
+                                format!("{}", kind).to_lowercase(),
+                                count
+                            );
+                        }
+                    }
+                }
+
                 for i in 0..s.temperature {
                     add_sensor(Sensor::Temperature, &d, i);
                 }
@@ -904,6 +1128,10 @@ impl ConfigGenerator {
                 for i in 0..s.speed {
                     add_sensor(Sensor::Speed, &d, i);
                 }
+
+                for i in 0..s.energy {
+                    add_sensor(Sensor::Energy, &d, i);
+                }
             }
         }
 
@@ -943,10 +1171,143 @@ impl ConfigGenerator {
             self.emit_sensor(device, &label, ids)?;
         }
 
+        self.emit_thresholds(&thresholds)?;
+        self.emit_metadata(&metadata)?;
+
         writeln!(&mut self.output, "\n    }}")?;
         Ok(())
     }
 
+    ///
+    /// Returns metadata for each sensor, indexed by `SensorId`.  This must
+    /// walk devices and sensors in the same order as `generate_sensors`.
+    ///
+    fn sensor_metadata(&self) -> Vec<SensorMetadata> {
+        let mut metadata = vec![];
+
+        for d in &self.devices {
+            let s = match &d.sensors {
+                Some(s) => s,
+                None => continue,
+            };
+
+            let bus = match (&d.bus, d.controller, &d.port) {
+                (Some(bus), _, _) => bus.clone(),
+                (None, Some(c), Some(p)) => {
+                    format!("i2c{}_{}", c, p.to_case(Case::Snake))
+                }
+                (None, Some(c), None) => format!("i2c{}", c),
+                (None, None, _) => unreachable!(),
+            };
+
+            let rails = d.pmbus.as_ref().and_then(|p| p.rails.as_ref());
+
+            for (kind, count) in [
+                (Sensor::Temperature, s.temperature),
+                (Sensor::Power, s.power),
+                (Sensor::Current, s.current),
+                (Sensor::Voltage, s.voltage),
+                (Sensor::Speed, s.speed),
+                (Sensor::Energy, s.energy),
+            ] {
+                for i in 0..count {
+                    let name = match rails {
+                        Some(rails) => rails.get(i).cloned(),
+                        None => d.name.clone(),
+                    };
+
+                    metadata.push(SensorMetadata {
+                        sensor: kind,
+                        id: metadata.len(),
+                        name,
+                        kind: format!("{}", kind).to_lowercase(),
+                        unit: kind.unit(),
+                        device: d.device.clone(),
+                        description: d.description.clone(),
+                        bus: bus.clone(),
+                        refdes: d.refdes.clone(),
+                    });
+                }
+            }
+        }
+
+        metadata
+    }
+
+    fn emit_metadata(&mut self, metadata: &[SensorMetadata]) -> Result<()> {
+        writeln!(
+            &mut self.output,
+            r##"
+        #[allow(dead_code)]
+        pub const METADATA: [task_sensor_api::SensorMetadata; NUM_SENSORS] = ["##
+        )?;
+
+        for m in metadata {
+            writeln!(
+                &mut self.output,
+                r##"            task_sensor_api::SensorMetadata {{
+                name: {:?},
+                kind: task_sensor_api::SensorKind::{},
+                device: {:?},
+                bus: {:?},
+                refdes: {:?},
+            }},"##,
+                m.name.as_deref().unwrap_or(""),
+                m.sensor.variant(),
+                m.device,
+                m.bus,
+                m.refdes.as_deref().unwrap_or(""),
+            )?;
+        }
+
+        writeln!(&mut self.output, "        ];")?;
+        Ok(())
+    }
+
+    fn emit_thresholds(
+        &mut self,
+        thresholds: &[Option<I2cThresholds>],
+    ) -> Result<()> {
+        writeln!(
+            &mut self.output,
+            r##"
+        #[allow(dead_code)]
+        pub const THRESHOLDS: [task_sensor_api::Thresholds; NUM_SENSORS] = ["##
+        )?;
+
+        let bound = |t: Option<f32>, default: &str| match t {
+            Some(t) => format!("{:?}", t),
+            None => default.to_string(),
+        };
+
+        for t in thresholds {
+            match t {
+                None => writeln!(
+                    &mut self.output,
+                    "            task_sensor_api::Thresholds::NONE,"
+                )?,
+                Some(t) => writeln!(
+                    &mut self.output,
+                    r##"            task_sensor_api::Thresholds {{
+                lower_critical: {},
+                lower_warning: {},
+                upper_warning: {},
+                upper_critical: {},
+                hysteresis: {:?},
+            }},"##,
+                    bound(t.lower_critical, "f32::NEG_INFINITY"),
+                    bound(t.lower_warning, "f32::NEG_INFINITY"),
+                    bound(t.upper_warning, "f32::INFINITY"),
+                    bound(t.upper_critical, "f32::INFINITY"),
+                    t.hysteresis,
+                )?,
+            }
+        }
+
+        writeln!(&mut self.output, "        ];")?;
+        Ok(())
+    }
+
     pub fn generate_ports(&mut self) -> Result<()> {
         writeln!(
             &mut self.output,
@@ -973,6 +1334,19 @@ impl ConfigGenerator {
     }
 }
 
+///
+/// Returns a JSON description of every sensor in the I2C configuration
+/// found in `app_config` (the app-wide configuration, as TOML), indexed by
+/// `SensorId`.  This is included in the build archive to allow tooling to
+/// label sensor readings.
+///
+pub fn sensors_json(app_config: &str) -> Result<String> {
+    let config: Config = toml::from_str(app_config)?;
+    let g = ConfigGenerator::from_config(config.i2c, Disposition::Sensors);
+
+    Ok(serde_json::to_string_pretty(&g.sensor_metadata())?)
+}
+
 pub fn codegen(disposition: Disposition) -> Result<()> {
     use std::io::Write;
 
@@ -985,7 +1359,7 @@ pub fn codegen(disposition: Disposition) -> Result<()> {
     g.generate_header()?;
 
     match disposition {
-        Disposition::Target => {
+        Disposition::Target | Disposition::TargetSensors => {
             let n = g.ncontrollers();
 
             if n != 1 {
@@ -1000,6 +1374,12 @@ pub fn codegen(disposition: Disposition) -> Result<()> {
             g.generate_controllers()?;
             g.generate_pins()?;
             g.generate_ports()?;
+
+            if disposition == Disposition::TargetSensors {
+                g.generate_devices()?;
+                g.generate_pmbus()?;
+                g.generate_sensors()?;
+            }
         }
 
         Disposition::Initiator => {
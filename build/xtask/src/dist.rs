@@ -584,7 +584,8 @@ pub fn package(
         This is a build archive containing firmware build artifacts.\n\n\
         - app.toml is the config file used to build the firmware.\n\
         - git-rev is the commit it was built from, with optional dirty flag.\n\
-        - info/ contains human-readable data like logs.\n\
+        - info/ contains human-readable data like logs, and descriptions\n  \
+          of components like sensors.\n\
         - elf/ contains ELF images for all firmware components.\n\
         - elf/tasks/ contains each task by name.\n\
         - elf/kernel is the kernel.\n\
@@ -618,6 +619,20 @@ pub fn package(
     )?;
     archive.copy(out.join("map.txt"), info_dir.join("map.txt"))?;
 
+    //
+    // If the image includes the sensor task, describe its sensors (as
+    // indexed by `SensorId`) to allow tooling to label readings.
+    //
+    if toml.tasks.values().any(|task| task.name == "task-sensor") {
+        if let Some(config) = &toml.config {
+            let config = toml::to_string(config)?;
+            archive.text(
+                info_dir.join("sensors.json"),
+                build_i2c::sensors_json(&config)?,
+            )?;
+        }
+    }
+
     let img_dir = PathBuf::from("img");
     archive.copy(out.join("combined.srec"), img_dir.join("combined.srec"))?;
     archive.copy(out.join("combined.elf"), img_dir.join("combined.elf"))?;
@@ -19,13 +19,45 @@
 //! - The segment on the multiplexer, if a multiplexer is specified
 //! - The address of the device itself
 //!
+//! # Packet error checking
+//!
+//! A device may additionally have SMBus packet error checking (PEC) enabled
+//! via [`I2cDevice::with_pec`].  A PEC byte (see [`pec_update`]) ends each
+//! transaction with the device, covering all of it:  the I2C server appends
+//! one to a transaction that only writes, and checks the one that the device
+//! sends at the end of a transaction that reads, reporting a mismatch as
+//! [`ResponseCode::BadChecksum`].  PEC is otherwise transparent to the
+//! caller:  it appears in neither the written nor the read buffers -- but,
+//! as it is sent on the bus, it counts against the I2C server's limit of 255
+//! bytes in either direction.
+//!
+//! # Testing
+//!
+//! When built for the host rather than for a Hubris target, there is no I2C
+//! server:  requests are instead served in-process by the simulated devices
+//! of [`mock`], against which drivers can be tested with `cargo test`.  The
+//! model of those devices, [`sim`], is shared with the mock I2C server task.
+//!
 
 #![no_std]
 
 use zerocopy::{AsBytes, FromBytes};
 
+#[cfg(target_os = "none")]
 use userlib::*;
 
+#[cfg(not(target_os = "none"))]
+pub mod mock;
+
+pub mod sim;
+
+#[cfg(not(target_os = "none"))]
+use mock::{sys_send, Lease, TaskId};
+#[cfg(not(target_os = "none"))]
+use num_derive::FromPrimitive;
+#[cfg(not(target_os = "none"))]
+use num_traits::FromPrimitive;
+
 #[derive(FromPrimitive, PartialEq)]
 pub enum Op {
     WriteRead = 1,
@@ -81,6 +113,8 @@ pub enum ResponseCode {
     BusLockedMux = 20,
     /// I2C controller appeared to be locked and was reset
     ControllerLocked = 21,
+    /// Packet error checking (PEC) byte read from device was incorrect
+    BadChecksum = 22,
 }
 
 ///
@@ -169,6 +203,8 @@ pub enum Segment {
 ///
 /// The 5-tuple that uniquely identifies an I2C device.  The multiplexer and
 /// the segment are optional, but if one is present, the other must be.
+/// Whether the device uses packet error checking is not part of its
+/// identity.
 ///
 #[derive(Copy, Clone, Debug)]
 pub struct I2cDevice {
@@ -177,9 +213,10 @@ pub struct I2cDevice {
     pub port: PortIndex,
     pub segment: Option<(Mux, Segment)>,
     pub address: u8,
+    pub pec: bool,
 }
 
-type I2cMessage = (u8, Controller, PortIndex, Option<(Mux, Segment)>);
+type I2cMessage = (u8, Controller, PortIndex, Option<(Mux, Segment)>, bool);
 
 pub trait Marshal<T> {
     fn marshal(&self) -> T;
@@ -188,8 +225,8 @@ pub trait Marshal<T> {
         Self: Sized;
 }
 
-impl Marshal<[u8; 4]> for I2cMessage {
-    fn marshal(&self) -> [u8; 4] {
+impl Marshal<[u8; 5]> for I2cMessage {
+    fn marshal(&self) -> [u8; 5] {
         [
             self.0,
             self.1 as u8,
@@ -200,9 +237,10 @@ impl Marshal<[u8; 4]> for I2cMessage {
                 }
                 None => 0,
             },
+            self.4 as u8,
         ]
     }
-    fn unmarshal(val: &[u8; 4]) -> Result<Self, ResponseCode> {
+    fn unmarshal(val: &[u8; 5]) -> Result<Self, ResponseCode> {
         Ok((
             val[0],
             Controller::from_u8(val[1]).ok_or(ResponseCode::BadController)?,
@@ -217,10 +255,34 @@ impl Marshal<[u8; 4]> for I2cMessage {
                         .ok_or(ResponseCode::BadSegment)?,
                 ))
             },
+            match val[4] {
+                0 => false,
+                1 => true,
+                _ => return Err(ResponseCode::BadArg),
+            },
         ))
     }
 }
 
+///
+/// Folds a byte into an SMBus packet error code (PEC):  a CRC-8 with the
+/// polynomial x^8 + x^2 + x + 1, calculated over every byte of the message
+/// (including each address byte) and with an initial value of 0.
+///
+pub fn pec_update(crc: u8, byte: u8) -> u8 {
+    let mut crc = crc ^ byte;
+
+    for _ in 0..8 {
+        crc = if crc & 0x80 != 0 {
+            (crc << 1) ^ 0x07
+        } else {
+            crc << 1
+        };
+    }
+
+    crc
+}
+
 impl core::fmt::Display for I2cDevice {
     fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
         let addr = self.address;
@@ -259,8 +321,19 @@ impl I2cDevice {
             port: port,
             segment: segment,
             address: address,
+            pec: false,
         }
     }
+
+    ///
+    /// Returns this device with SMBus packet error checking (PEC) enabled:
+    /// the I2C server will append a PEC byte to every transaction with the
+    /// device that only writes, and check the PEC byte that the device sends
+    /// at the end of every transaction that reads.
+    ///
+    pub fn with_pec(self) -> Self {
+        Self { pec: true, ..self }
+    }
 }
 
 impl From<ResponseCode> for u32 {
@@ -302,6 +375,7 @@ impl I2cDevice {
                 self.controller,
                 self.port,
                 self.segment,
+                self.pec,
             )),
             response.as_bytes_mut(),
             &[Lease::from(reg.as_bytes()), Lease::from(val.as_bytes_mut())],
@@ -335,6 +409,7 @@ impl I2cDevice {
                 self.controller,
                 self.port,
                 self.segment,
+                self.pec,
             )),
             response.as_bytes_mut(),
             &[Lease::from(reg.as_bytes()), Lease::from(buf)],
@@ -370,6 +445,7 @@ impl I2cDevice {
                 self.controller,
                 self.port,
                 self.segment,
+                self.pec,
             )),
             response.as_bytes_mut(),
             &[Lease::from(reg.as_bytes()), Lease::from(buf)],
@@ -405,6 +481,7 @@ impl I2cDevice {
                 self.controller,
                 self.port,
                 self.segment,
+                self.pec,
             )),
             response.as_bytes_mut(),
             &[Lease::from(&empty[0..0]), Lease::from(val.as_bytes_mut())],
@@ -435,6 +512,7 @@ impl I2cDevice {
                 self.controller,
                 self.port,
                 self.segment,
+                self.pec,
             )),
             response.as_bytes_mut(),
             &[Lease::from(&empty[0..0]), Lease::from(buf)],
@@ -464,6 +542,7 @@ impl I2cDevice {
                 self.controller,
                 self.port,
                 self.segment,
+                self.pec,
             )),
             response.as_bytes_mut(),
             &[Lease::from(buffer), Lease::from(&empty[0..0])],
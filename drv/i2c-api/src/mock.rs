@@ -0,0 +1,248 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Host test double for the I2C server
+//!
+//! Off target, there is no kernel to send to:  this module stands in for the
+//! parts of `userlib` that [`I2cDevice`] uses, and serves its requests from
+//! simulated devices in-process.  The simulated devices behave exactly as
+//! those of the mock I2C server task (see `test-i2c-mock-api` for their
+//! semantics), and [`I2cMock`] scripts them with the same interface as that
+//! task's client, so that a test case can be run either on the host or on
+//! target.
+//!
+//! Each thread has its own devices, so tests run in parallel by `cargo test`
+//! don't interfere with one another.
+
+extern crate std;
+
+use crate::sim::*;
+use crate::*;
+use core::marker::PhantomData;
+use std::cell::RefCell;
+
+pub use crate::sim::{MAX_REGISTER, MAX_VALUE, MAX_WRITE};
+
+/// The identifier of a task, which off target identifies nothing:  every
+/// request is served by the mock.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct TaskId(pub u16);
+
+/// A lease of memory to the I2C server for the duration of a request
+pub(crate) struct Lease<'a> {
+    base: *mut u8,
+    len: usize,
+    writable: bool,
+    _marker: PhantomData<&'a mut ()>,
+}
+
+impl<'a> From<&'a [u8]> for Lease<'a> {
+    fn from(buf: &'a [u8]) -> Self {
+        Self {
+            base: buf.as_ptr() as *mut u8,
+            len: buf.len(),
+            writable: false,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl<'a> From<&'a mut [u8]> for Lease<'a> {
+    fn from(buf: &'a mut [u8]) -> Self {
+        Self {
+            base: buf.as_mut_ptr(),
+            len: buf.len(),
+            writable: true,
+            _marker: PhantomData,
+        }
+    }
+}
+
+impl Lease<'_> {
+    fn read(&self) -> &[u8] {
+        // Safety: the lease was made from a slice that outlives it.
+        unsafe { core::slice::from_raw_parts(self.base, self.len) }
+    }
+
+    /// Writes `data` to the start of the lease, as the I2C server would.
+    fn write(&self, data: &[u8]) -> Result<(), ResponseCode> {
+        if data.len() > self.len || !(self.writable || data.is_empty()) {
+            return Err(ResponseCode::BadArg);
+        }
+
+        //
+        // Safety: the lease was made from a mutable slice that outlives it,
+        // and that is at least as long as `data`.
+        //
+        unsafe {
+            core::ptr::copy_nonoverlapping(data.as_ptr(), self.base, data.len())
+        };
+
+        Ok(())
+    }
+}
+
+std::thread_local! {
+    static MOCK: RefCell<Devices> = RefCell::default();
+}
+
+///
+/// Serves a request to the I2C server, validating it just as the I2C server
+/// does.
+///
+fn serve(
+    op: u16,
+    outgoing: &[u8],
+    leases: &[Lease<'_>],
+) -> Result<usize, ResponseCode> {
+    if op != Op::WriteRead as u16 && op != Op::WriteReadBlock as u16 {
+        return Err(ResponseCode::BadResponse);
+    }
+
+    let mut payload = [0u8; 5];
+
+    if outgoing.len() != payload.len() || leases.len() != 2 {
+        return Err(ResponseCode::BadArg);
+    }
+
+    payload.copy_from_slice(outgoing);
+
+    let block = op == Op::WriteReadBlock as u16;
+    let wbuf = leases[0].read();
+    let rlease = &leases[1];
+
+    //
+    // A transaction that only writes leases an empty buffer for the read,
+    // which needn't be writable.
+    //
+    if !rlease.writable && rlease.len != 0 {
+        return Err(ResponseCode::BadArg);
+    }
+
+    let (key, pec) =
+        check_transaction(&payload, block, wbuf.len(), rlease.len)?;
+
+    let mut rbuf = [0u8; MAX_TRANSFER];
+    let rbuf = &mut rbuf[..rlease.len];
+    let n = MOCK.with(|mock| {
+        mock.borrow_mut().write_read(key, pec, block, wbuf, rbuf)
+    })?;
+
+    rlease.write(&rbuf[..n])?;
+    Ok(n)
+}
+
+///
+/// Sends a request to the I2C server -- which off target is the mock --
+/// returning its response code and the length of its reply.
+///
+pub(crate) fn sys_send(
+    _task: TaskId,
+    op: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    leases: &[Lease<'_>],
+) -> (u32, usize) {
+    match serve(op, outgoing, leases) {
+        Ok(n) => {
+            let reply = n.to_ne_bytes();
+            incoming.copy_from_slice(&reply[..incoming.len()]);
+            (0, incoming.len())
+        }
+        Err(code) => (code.into(), 0),
+    }
+}
+
+///
+/// Scripts (and examines) the simulated devices of the current thread.
+/// Devices on any controller may be simulated; [`I2cMock::device`] returns
+/// one on [`Controller::Mock`], as on target.
+///
+#[derive(Copy, Clone, Debug, Default)]
+pub struct I2cMock;
+
+impl I2cMock {
+    pub fn new() -> Self {
+        Self
+    }
+
+    /// Returns a device on the mock controller at `address`.
+    pub fn device(&self, address: u8) -> I2cDevice {
+        I2cDevice::new(
+            TaskId::default(),
+            Controller::Mock,
+            PortIndex(0),
+            None,
+            address,
+        )
+    }
+
+    /// Forgets all devices, their registers and any injected errors.
+    pub fn reset(&self) {
+        MOCK.with(|mock| *mock.borrow_mut() = Devices::new());
+    }
+
+    ///
+    /// Sets register `reg` of `device` to `value`, creating the device if
+    /// needed.  The width of `reg` must match that of any other register of
+    /// the device.
+    ///
+    pub fn set_register(
+        &self,
+        device: &I2cDevice,
+        reg: &[u8],
+        value: &[u8],
+    ) -> Result<(), ResponseCode> {
+        MOCK.with(|mock| {
+            mock.borrow_mut()
+                .set_register(device_key(device), reg, value)
+        })
+    }
+
+    ///
+    /// Reads register `reg` of `device` into `buf`, returning the size of
+    /// its contents.
+    ///
+    pub fn get_register(
+        &self,
+        device: &I2cDevice,
+        reg: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        MOCK.with(|mock| {
+            mock.borrow().get_register(device_key(device), reg, buf)
+        })
+    }
+
+    ///
+    /// Reads the last write to `device` (register address included) into
+    /// `buf`, returning its size; a write of more than [`MAX_WRITE`] bytes is
+    /// truncated.
+    ///
+    pub fn get_last_write(
+        &self,
+        device: &I2cDevice,
+        buf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        MOCK.with(|mock| mock.borrow().get_last_write(device_key(device), buf))
+    }
+
+    ///
+    /// Fails the next `count` transactions with `device` with `code` (e.g.,
+    /// [`ResponseCode::NoDevice`] to simulate a NACK).  With
+    /// [`ResponseCode::BadChecksum`], it is instead the PEC byte sent by the
+    /// device in its next `count` reads with PEC that is corrupted.
+    ///
+    pub fn inject_error(
+        &self,
+        device: &I2cDevice,
+        code: ResponseCode,
+        count: u32,
+    ) -> Result<(), ResponseCode> {
+        MOCK.with(|mock| {
+            mock.borrow_mut()
+                .inject_error(device_key(device), code, count)
+        })
+    }
+}
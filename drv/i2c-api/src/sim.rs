@@ -0,0 +1,368 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Simulated I2C devices
+//!
+//! This is the model of the devices simulated by the mock I2C server task
+//! and, off target, by the in-process mock of `drv_i2c_api::mock`:  each
+//! serves requests from (and scripts) a [`Devices`], validating requests via
+//! [`check_transaction`].  See `test-i2c-mock-api` for the semantics of the
+//! simulated devices.
+//!
+//! The model is of fixed size, so that it needs no allocator:  at most
+//! [`MAX_DEVICES`] devices with [`MAX_REGISTERS`] registers among them may be
+//! simulated at once.
+
+use crate::*;
+use fixedmap::*;
+
+/// Largest register address
+pub const MAX_REGISTER: usize = 2;
+
+/// Largest contents of a register
+pub const MAX_VALUE: usize = 32;
+
+/// Largest write that is retained as a device's last write
+pub const MAX_WRITE: usize = MAX_REGISTER + MAX_VALUE;
+
+/// Largest transfer in either direction, as for the I2C server
+pub const MAX_TRANSFER: usize = 255;
+
+/// Number of devices that may be simulated
+pub const MAX_DEVICES: usize = 8;
+
+/// Number of registers that may be simulated, across all devices
+pub const MAX_REGISTERS: usize = 64;
+
+/// A device, as identified by its marshalled 5-tuple (without PEC)
+pub type DeviceKey = [u8; 4];
+
+/// A register of a device
+type RegisterKey = (DeviceKey, [u8; MAX_REGISTER]);
+
+#[derive(Copy, Clone)]
+struct Device {
+    /// Width of the device's register addresses
+    reg_len: usize,
+
+    /// The selected register
+    pointer: [u8; MAX_REGISTER],
+
+    /// Error with which to fail transactions, and how many to fail
+    error: Option<(ResponseCode, u32)>,
+
+    /// The last write to the device, and its size
+    last_write: [u8; MAX_WRITE],
+    last_len: usize,
+}
+
+#[derive(Copy, Clone)]
+struct Value {
+    len: usize,
+    bytes: [u8; MAX_VALUE],
+}
+
+/// Returns the key of `device`:  PEC is a property of its transactions
+/// rather than of the device.
+pub fn device_key(device: &I2cDevice) -> DeviceKey {
+    let msg: [u8; 5] = Marshal::marshal(&(
+        device.address,
+        device.controller,
+        device.port,
+        device.segment,
+        false,
+    ));
+
+    [msg[0], msg[1], msg[2], msg[3]]
+}
+
+///
+/// Checks a request to perform a transaction, given its marshalled payload
+/// and the sizes of its write and read buffers, just as the I2C server does
+/// (e.g., a transaction may not exceed 255 bytes in either direction).
+/// Returns the device, and whether the transaction is with PEC.
+///
+pub fn check_transaction(
+    payload: &[u8; 5],
+    block: bool,
+    wlen: usize,
+    rlen: usize,
+) -> Result<(DeviceKey, bool), ResponseCode> {
+    let (addr, _, _, _, pec): I2cMessage = Marshal::unmarshal(payload)?;
+
+    if ReservedAddress::from_u8(addr).is_some() {
+        return Err(ResponseCode::ReservedAddress);
+    }
+
+    if wlen == 0 && rlen == 0 {
+        return Err(ResponseCode::BadArg);
+    }
+
+    //
+    // As with the I2C server, any PEC byte counts against the limit:  it
+    // follows the write if there is no read, and otherwise the read.
+    //
+    let read = block || rlen > 0;
+
+    if wlen + (pec && !read) as usize > MAX_TRANSFER
+        || rlen + (pec && read) as usize > MAX_TRANSFER
+    {
+        return Err(ResponseCode::BadArg);
+    }
+
+    Ok(([payload[0], payload[1], payload[2], payload[3]], pec))
+}
+
+fn register_key(
+    key: DeviceKey,
+    reg: &[u8],
+) -> Result<RegisterKey, ResponseCode> {
+    let mut addr = [0u8; MAX_REGISTER];
+
+    if reg.is_empty() || reg.len() > MAX_REGISTER {
+        return Err(ResponseCode::BadArg);
+    }
+
+    addr[..reg.len()].copy_from_slice(reg);
+    Ok((key, addr))
+}
+
+fn value(bytes: &[u8]) -> Result<Value, ResponseCode> {
+    let mut value = Value {
+        len: bytes.len(),
+        bytes: [0; MAX_VALUE],
+    };
+
+    value
+        .bytes
+        .get_mut(..bytes.len())
+        .ok_or(ResponseCode::BadArg)?
+        .copy_from_slice(bytes);
+
+    Ok(value)
+}
+
+/// Copies as much of `data` into `buf` as it will hold, returning the size
+/// of `data`.
+fn copy_out(data: &[u8], buf: &mut [u8]) -> usize {
+    let n = data.len().min(buf.len());
+    buf[..n].copy_from_slice(&data[..n]);
+    data.len()
+}
+
+/// Folds `bytes` into an SMBus packet error code.
+fn fold_pec(crc: u8, bytes: &[u8]) -> u8 {
+    bytes.iter().fold(crc, |crc, &byte| pec_update(crc, byte))
+}
+
+/// The simulated devices, and their registers
+pub struct Devices {
+    devices: FixedMap<DeviceKey, Device, MAX_DEVICES>,
+    registers: FixedMap<RegisterKey, Value, MAX_REGISTERS>,
+}
+
+impl Default for Devices {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Devices {
+    pub fn new() -> Self {
+        Self {
+            devices: FixedMap::new(),
+            registers: FixedMap::new(),
+        }
+    }
+
+    ///
+    /// Sets register `reg` of a device to `bytes`, creating the device if
+    /// needed.  The width of `reg` must match that of any other register of
+    /// the device.
+    ///
+    pub fn set_register(
+        &mut self,
+        key: DeviceKey,
+        reg: &[u8],
+        bytes: &[u8],
+    ) -> Result<(), ResponseCode> {
+        let device = match self.devices.get(key) {
+            Some(device) if device.reg_len != reg.len() => {
+                return Err(ResponseCode::BadArg);
+            }
+            Some(device) => device,
+            None => Device {
+                reg_len: reg.len(),
+                pointer: [0; MAX_REGISTER],
+                error: None,
+                last_write: [0; MAX_WRITE],
+                last_len: 0,
+            },
+        };
+
+        let rkey = register_key(key, reg)?;
+        self.registers.insert(rkey, value(bytes)?);
+        self.devices.insert(key, device);
+
+        Ok(())
+    }
+
+    ///
+    /// Reads register `reg` of a device into `buf`, returning the size of
+    /// its contents.
+    ///
+    pub fn get_register(
+        &self,
+        key: DeviceKey,
+        reg: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        let value = self
+            .registers
+            .get(register_key(key, reg)?)
+            .ok_or(ResponseCode::NoRegister)?;
+
+        Ok(copy_out(&value.bytes[..value.len], buf))
+    }
+
+    ///
+    /// Reads the last write to a device into `buf`, returning its size
+    /// (which is at most [`MAX_WRITE`]).
+    ///
+    pub fn get_last_write(
+        &self,
+        key: DeviceKey,
+        buf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        let device = self.devices.get(key).ok_or(ResponseCode::NoDevice)?;
+
+        Ok(copy_out(&device.last_write[..device.last_len], buf))
+    }
+
+    ///
+    /// Fails the next `count` transactions with a device with `code` -- or,
+    /// with [`ResponseCode::BadChecksum`], corrupts the PEC byte that the
+    /// device sends in its next `count` reads with PEC.
+    ///
+    pub fn inject_error(
+        &mut self,
+        key: DeviceKey,
+        code: ResponseCode,
+        count: u32,
+    ) -> Result<(), ResponseCode> {
+        let mut device = self.devices.get(key).ok_or(ResponseCode::NoDevice)?;
+
+        device.error = if count > 0 { Some((code, count)) } else { None };
+        self.devices.insert(key, device);
+
+        Ok(())
+    }
+
+    ///
+    /// Performs a transaction with a device:  the write of `wbuf` (if it is
+    /// non-empty), and then the read of the selected register into `rbuf`
+    /// (if it is non-empty, or if a block read), returning the number of
+    /// bytes read.  With `pec`, the PEC byte is appended to a transaction
+    /// that only writes, and is sent by the device (and checked) at the end
+    /// of one that reads.  For a block read, the register's contents are the
+    /// block; as with the I2C server, the byte count is not returned in
+    /// `rbuf`.
+    ///
+    pub fn write_read(
+        &mut self,
+        key: DeviceKey,
+        pec: bool,
+        block: bool,
+        wbuf: &[u8],
+        rbuf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        let addr = key[0];
+        let rpec = pec && (block || !rbuf.is_empty());
+        let mut corrupt = false;
+
+        let mut device = self.devices.get(key).ok_or(ResponseCode::NoDevice)?;
+
+        if let Some((code, count)) = device.error {
+            //
+            // An injected BadChecksum corrupts the PEC byte that the device
+            // sends rather than failing the transaction outright, and so
+            // only counts against transactions that read with PEC.
+            //
+            if code != ResponseCode::BadChecksum || rpec {
+                device.error = if count > 1 {
+                    Some((code, count - 1))
+                } else {
+                    None
+                };
+
+                if code != ResponseCode::BadChecksum {
+                    self.devices.insert(key, device);
+                    return Err(code);
+                }
+
+                corrupt = true;
+            }
+        }
+
+        let mut crc = 0;
+
+        if !wbuf.is_empty() {
+            crc = fold_pec(pec_update(crc, addr << 1), wbuf);
+
+            let n = wbuf.len().min(MAX_WRITE);
+            device.last_write[..n].copy_from_slice(&wbuf[..n]);
+            device.last_len = n;
+
+            if pec && !rpec && n < MAX_WRITE {
+                device.last_write[n] = crc;
+                device.last_len = n + 1;
+            }
+        }
+
+        if wbuf.len() >= device.reg_len {
+            let (reg, data) = wbuf.split_at(device.reg_len);
+            device.pointer = register_key(key, reg)?.1;
+
+            if !data.is_empty() && rbuf.is_empty() {
+                self.registers.insert((key, device.pointer), value(data)?);
+            }
+        }
+
+        self.devices.insert(key, device);
+
+        if rbuf.is_empty() {
+            return Ok(0);
+        }
+
+        let value = self
+            .registers
+            .get((key, device.pointer))
+            .ok_or(ResponseCode::NoRegister)?;
+
+        let n = value.len.min(rbuf.len());
+        rbuf[..n].copy_from_slice(&value.bytes[..n]);
+
+        if rpec {
+            //
+            // The device's PEC byte covers the read address and any byte
+            // count, as well as the data that it sends; we check it against
+            // the data received, as the I2C server would.
+            //
+            crc = pec_update(crc, (addr << 1) | 1);
+
+            if block {
+                crc = pec_update(crc, value.len as u8);
+            }
+
+            let sent = fold_pec(crc, &value.bytes[..n])
+                ^ if corrupt { 0xff } else { 0 };
+
+            if fold_pec(crc, &rbuf[..n]) != sent {
+                return Err(ResponseCode::BadChecksum);
+            }
+        }
+
+        Ok(n)
+    }
+}
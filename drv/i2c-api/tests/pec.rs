@@ -0,0 +1,46 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Known-answer tests of the SMBus packet error code
+
+#![cfg(not(target_os = "none"))]
+
+use drv_i2c_api::pec_update;
+
+fn pec(bytes: &[u8]) -> u8 {
+    bytes.iter().fold(0, |crc, &byte| pec_update(crc, byte))
+}
+
+#[test]
+fn check_value() {
+    // The check value of CRC-8/SMBUS
+    assert_eq!(pec(b"123456789"), 0xf4);
+}
+
+#[test]
+fn single_bytes() {
+    assert_eq!(pec(&[]), 0x00);
+    assert_eq!(pec(&[0x00]), 0x00);
+    assert_eq!(pec(&[0x01]), 0x07);
+    assert_eq!(pec(&[0x80]), 0x89);
+    assert_eq!(pec(&[0xff]), 0xf3);
+}
+
+#[test]
+fn transactions() {
+    // Write Byte of OPERATION (0x01) to 0x5a
+    assert_eq!(pec(&[0xb4, 0x01, 0x80]), 0xdd);
+
+    // Read Word of READ_VOUT (0x8b) from 0x5a, covering both addresses
+    assert_eq!(pec(&[0xb4, 0x8b, 0xb5, 0x34, 0x12]), 0x0c);
+
+    // Block Read from 0x5a, covering the byte count
+    assert_eq!(pec(&[0xb4, 0x99, 0xb5, 0x03, 0x41, 0x42, 0x43]), 0xdd);
+
+    //
+    // A PEC byte appended to the message that it covers results in a PEC
+    // of zero, as a receiver may check.
+    //
+    assert_eq!(pec(&[0xb4, 0x01, 0x80, 0xdd]), 0x00);
+}
@@ -4,12 +4,16 @@
 
 //! Driver for the ADM1272 hot-swap controller
 
-use crate::{CurrentSensor, TempSensor, VoltageSensor};
+use crate::{
+    CurrentSensor, EnergyCount, EnergySensor, InputCurrentSensor,
+    InputVoltageSensor, PmbusStatus, PowerSensor, StatusSensor, TempSensor,
+    VoltageSensor,
+};
 use drv_i2c_api::*;
 use num_traits::float::FloatCore;
 use pmbus::commands::*;
 use ringbuf::*;
-use userlib::units::*;
+use units::*;
 
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Error {
@@ -36,7 +40,6 @@ impl From<Error> for ResponseCode {
     }
 }
 
-#[allow(dead_code)]
 struct Coefficients {
     voltage: pmbus::Coefficients,
     current: pmbus::Coefficients,
@@ -234,12 +237,6 @@ impl Adm1272 {
         }
     }
 
-    pub fn read_vin(&mut self) -> Result<Volts, Error> {
-        self.enable_vin_sampling()?;
-        let vin = pmbus_read!(self.device, adm1272::READ_VIN)?;
-        Ok(Volts(vin.get(&self.load_coefficients()?.voltage)?.0))
-    }
-
     pub fn peak_iout(&mut self) -> Result<Amperes, Error> {
         let iout = pmbus_read!(self.device, adm1272::PEAK_IOUT)?;
         Ok(Amperes(iout.get(&self.load_coefficients()?.current)?.0))
@@ -268,3 +265,57 @@ impl VoltageSensor<Error> for Adm1272 {
         Ok(Volts(vout.get(&self.load_coefficients()?.voltage)?.0))
     }
 }
+
+impl InputVoltageSensor<Error> for Adm1272 {
+    fn read_vin(&mut self) -> Result<Volts, Error> {
+        self.enable_vin_sampling()?;
+        let vin = pmbus_read!(self.device, adm1272::READ_VIN)?;
+        Ok(Volts(vin.get(&self.load_coefficients()?.voltage)?.0))
+    }
+}
+
+impl InputCurrentSensor<Error> for Adm1272 {
+    ///
+    /// The sense resistor is in the input path, so the input current is the
+    /// current that we measure as IOUT.
+    ///
+    fn read_iin(&mut self) -> Result<Amperes, Error> {
+        self.read_iout()
+    }
+}
+
+impl PowerSensor<Error> for Adm1272 {
+    fn read_power(&mut self) -> Result<Watts, Error> {
+        Ok(self.read_vout()? * self.read_iout()?)
+    }
+}
+
+impl EnergySensor<Error> for Adm1272 {
+    fn read_ein(&mut self) -> Result<EnergyCount, Error> {
+        //
+        // Input power is only sampled (and therefore only accumulated) if
+        // input voltage is.
+        //
+        self.enable_vin_sampling()?;
+        pmbus_energy!(self.device, crate::energy::READ_EIN)
+    }
+
+    fn accumulated_power(&mut self, raw: f32) -> Result<Watts, Error> {
+        //
+        // The accumulator sums READ_PIN samples, which are in the direct
+        // format with the power coefficients.
+        //
+        let c = &self.load_coefficients()?.power;
+        Ok(Watts(crate::from_direct(raw, c)))
+    }
+}
+
+impl StatusSensor<Error> for Adm1272 {
+    fn read_status(&mut self) -> Result<PmbusStatus, Error> {
+        pmbus_status!(self.device)
+    }
+
+    fn clear_faults(&mut self) -> Result<(), Error> {
+        pmbus_clear_faults!(self.device)
+    }
+}
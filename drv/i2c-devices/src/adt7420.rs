@@ -6,7 +6,7 @@
 
 use crate::TempSensor;
 use drv_i2c_api::*;
-use userlib::units::*;
+use units::*;
 
 const ADT7420_ID: u8 = 0xcb;
 
@@ -0,0 +1,245 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for AT24-class EEPROMs
+//!
+//! This supports the AT24Cxx family (and its many second sources).  Reads
+//! may be of any length, and are split into transfers that the I2C server
+//! can accommodate.  Writes are split at page boundaries; after each page is
+//! written, we poll the device -- which does not acknowledge its address
+//! during its write cycle -- until the cycle completes.
+//!
+//! Parts of up to 2 KiB are addressed with a single byte, with any further
+//! address bits taken from the low bits of the device address; larger parts
+//! are addressed with two bytes.
+
+use drv_i2c_api::*;
+
+#[cfg(target_os = "none")]
+use userlib::hl;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum At24Part {
+    At24c01,
+    At24c02,
+    At24c04,
+    At24c08,
+    At24c16,
+    At24c32,
+    At24c64,
+    At24c128,
+    At24c256,
+    At24c512,
+}
+
+impl At24Part {
+    /// Returns the size of the part, in bytes.
+    pub const fn size(&self) -> usize {
+        match self {
+            At24Part::At24c01 => 128,
+            At24Part::At24c02 => 256,
+            At24Part::At24c04 => 512,
+            At24Part::At24c08 => 1024,
+            At24Part::At24c16 => 2048,
+            At24Part::At24c32 => 4096,
+            At24Part::At24c64 => 8192,
+            At24Part::At24c128 => 16384,
+            At24Part::At24c256 => 32768,
+            At24Part::At24c512 => 65536,
+        }
+    }
+
+    /// Returns the size of a page, in bytes.
+    pub const fn page_size(&self) -> usize {
+        match self {
+            At24Part::At24c01 | At24Part::At24c02 => 8,
+            At24Part::At24c04 | At24Part::At24c08 | At24Part::At24c16 => 16,
+            At24Part::At24c32 | At24Part::At24c64 => 32,
+            At24Part::At24c128 | At24Part::At24c256 => 64,
+            At24Part::At24c512 => 128,
+        }
+    }
+
+    const fn wide_address(&self) -> bool {
+        self.size() > 2048
+    }
+}
+
+/// Largest page of any part
+const MAX_PAGE: usize = 128;
+
+/// Largest read that we make in a single transfer
+const MAX_READ: usize = 128;
+
+/// Number of times that we poll for the completion of a write cycle, at
+/// intervals of a millisecond; the write cycle of most parts is at most 5 ms.
+const WRITE_POLLS: usize = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    BadRead { offset: usize, code: ResponseCode },
+    BadWrite { offset: usize, code: ResponseCode },
+    OutOfRange,
+    WriteTimeout { offset: usize },
+}
+
+impl From<Error> for ResponseCode {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::BadRead { code, .. } => code,
+            Error::BadWrite { code, .. } => code,
+            Error::OutOfRange => ResponseCode::BadArg,
+            Error::WriteTimeout { .. } => ResponseCode::NoDevice,
+        }
+    }
+}
+
+pub struct At24 {
+    device: I2cDevice,
+    part: At24Part,
+}
+
+impl core::fmt::Display for At24 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "at24: {}", &self.device)
+    }
+}
+
+impl At24 {
+    pub fn new(device: &I2cDevice, part: At24Part) -> Self {
+        Self {
+            device: *device,
+            part,
+        }
+    }
+
+    pub fn part(&self) -> At24Part {
+        self.part
+    }
+
+    ///
+    /// Returns the device to address for `offset`, the address bytes to send
+    /// it, and how many of those bytes are significant.
+    ///
+    fn address(&self, offset: usize) -> (I2cDevice, [u8; 2], usize) {
+        if self.part.wide_address() {
+            (self.device, (offset as u16).to_be_bytes(), 2)
+        } else {
+            let device = I2cDevice {
+                address: self.device.address | (offset >> 8) as u8,
+                ..self.device
+            };
+
+            (device, [offset as u8, 0], 1)
+        }
+    }
+
+    fn check_range(&self, offset: usize, len: usize) -> Result<(), Error> {
+        match offset.checked_add(len) {
+            Some(end) if end <= self.part.size() => Ok(()),
+            _ => Err(Error::OutOfRange),
+        }
+    }
+
+    /// Reads `buf.len()` bytes at `offset`.
+    pub fn read(&self, offset: usize, buf: &mut [u8]) -> Result<(), Error> {
+        self.check_range(offset, buf.len())?;
+
+        let mut pos = 0;
+
+        while pos < buf.len() {
+            let offset = offset + pos;
+
+            //
+            // We don't let a transfer span a 256-byte block, as such a block
+            // may be a different device address.
+            //
+            let len = (buf.len() - pos).min(MAX_READ - offset % MAX_READ);
+            let chunk = &mut buf[pos..pos + len];
+            let (device, addr, nbytes) = self.address(offset);
+
+            let rval = if nbytes == 2 {
+                device.read_reg_into::<[u8; 2]>(addr, chunk)
+            } else {
+                device.read_reg_into::<u8>(addr[0], chunk)
+            };
+
+            match rval {
+                Ok(n) if n == len => {}
+                Ok(_) => {
+                    return Err(Error::BadRead {
+                        offset,
+                        code: ResponseCode::BadResponse,
+                    })
+                }
+                Err(code) => return Err(Error::BadRead { offset, code }),
+            }
+
+            pos += len;
+        }
+
+        Ok(())
+    }
+
+    /// Writes `data` at `offset`, waiting for the writes to complete.
+    pub fn write(&self, offset: usize, data: &[u8]) -> Result<(), Error> {
+        self.check_range(offset, data.len())?;
+
+        let page = self.part.page_size();
+        let mut pos = 0;
+
+        while pos < data.len() {
+            let offset = offset + pos;
+
+            //
+            // A write that spans the end of a page wraps around to its
+            // start, so we must not span one.
+            //
+            let len = (data.len() - pos).min(page - offset % page);
+            let (device, addr, nbytes) = self.address(offset);
+
+            let mut buf = [0u8; 2 + MAX_PAGE];
+            buf[..nbytes].copy_from_slice(&addr[..nbytes]);
+            buf[nbytes..nbytes + len].copy_from_slice(&data[pos..pos + len]);
+
+            if let Err(code) = device.write(&buf[..nbytes + len]) {
+                return Err(Error::BadWrite { offset, code });
+            }
+
+            self.wait_for_write(&device, &addr[..nbytes], offset)?;
+            pos += len;
+        }
+
+        Ok(())
+    }
+
+    ///
+    /// Waits for a write cycle to complete.  Until it does, the device will
+    /// not acknowledge its address; once it does, writing the address bytes
+    /// alone merely sets the device's address pointer.
+    ///
+    fn wait_for_write(
+        &self,
+        device: &I2cDevice,
+        addr: &[u8],
+        offset: usize,
+    ) -> Result<(), Error> {
+        for _ in 0..WRITE_POLLS {
+            //
+            // Off target, the device is simulated (see `drv_i2c_api::mock`)
+            // and there is no write cycle to wait for.
+            //
+            #[cfg(target_os = "none")]
+            hl::sleep_for(1);
+
+            match device.write(addr) {
+                Ok(()) => return Ok(()),
+                Err(ResponseCode::NoDevice) => {}
+                Err(code) => return Err(Error::BadWrite { offset, code }),
+            }
+        }
+
+        Err(Error::WriteTimeout { offset })
+    }
+}
@@ -3,83 +3,75 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 //! Driver for the BMR491 IBC
+//!
+//! The BMR491's telemetry is standard PMBus, so this is a thin veneer over
+//! [`PmbusDevice`] that exposes only the subset that we use:  we neither
+//! margin the IBC nor read its input current.
 
-use crate::{CurrentSensor, TempSensor, VoltageSensor};
+use crate::pmbus_device::PmbusDevice;
+use crate::{
+    CurrentSensor, InputVoltageSensor, PmbusStatus, PowerSensor, StatusSensor,
+    TempSensor, VoltageSensor,
+};
 use drv_i2c_api::*;
-use pmbus::commands::*;
-use userlib::units::*;
+use units::*;
 
-pub struct Bmr491 {
-    device: I2cDevice,
-    mode: Option<pmbus::VOutModeCommandData>,
-}
+pub use crate::pmbus_device::Error;
 
-#[derive(Debug)]
-pub enum Error {
-    BadRead { cmd: u8, code: ResponseCode },
-    BadWrite { cmd: u8, code: ResponseCode },
-    BadData { cmd: u8 },
-    InvalidData { err: pmbus::Error },
+pub struct Bmr491 {
+    pmbus: PmbusDevice,
 }
 
-impl From<pmbus::Error> for Error {
-    fn from(err: pmbus::Error) -> Self {
-        Error::InvalidData { err: err }
-    }
-}
-
-impl From<Error> for ResponseCode {
-    fn from(err: Error) -> Self {
-        match err {
-            Error::BadRead { code, .. } => code,
-            Error::BadWrite { code, .. } => code,
-            _ => panic!(),
-        }
+impl core::fmt::Display for Bmr491 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "bmr491: {}", &self.pmbus)
     }
 }
 
 impl Bmr491 {
     pub fn new(device: &I2cDevice, _rail: u8) -> Self {
         Bmr491 {
-            device: *device,
-            mode: None,
+            pmbus: PmbusDevice::new(device, None),
         }
     }
-
-    fn read_mode(&mut self) -> Result<pmbus::VOutModeCommandData, Error> {
-        Ok(match self.mode {
-            None => {
-                let mode = pmbus_read!(self.device, VOUT_MODE)?;
-                self.mode = Some(mode);
-                mode
-            }
-            Some(mode) => mode,
-        })
-    }
-
-    pub fn read_vout(&mut self) -> Result<Volts, Error> {
-        let vout = pmbus_read!(self.device, bmr491::READ_VOUT)?;
-        Ok(Volts(vout.get(self.read_mode()?)?.0))
-    }
 }
 
 impl TempSensor<Error> for Bmr491 {
     fn read_temperature(&mut self) -> Result<Celsius, Error> {
-        let temp = pmbus_read!(self.device, bmr491::READ_TEMPERATURE_1)?;
-        Ok(Celsius(temp.get()?.0))
+        self.pmbus.read_temperature()
     }
 }
 
 impl CurrentSensor<Error> for Bmr491 {
     fn read_iout(&mut self) -> Result<Amperes, Error> {
-        let iout = pmbus_read!(self.device, bmr491::READ_IOUT)?;
-        Ok(Amperes(iout.get()?.0))
+        self.pmbus.read_iout()
     }
 }
 
 impl VoltageSensor<Error> for Bmr491 {
     fn read_vout(&mut self) -> Result<Volts, Error> {
-        let vout = pmbus_read!(self.device, bmr491::READ_VOUT)?;
-        Ok(Volts(vout.get(self.read_mode()?)?.0))
+        self.pmbus.read_vout()
+    }
+}
+
+impl InputVoltageSensor<Error> for Bmr491 {
+    fn read_vin(&mut self) -> Result<Volts, Error> {
+        self.pmbus.read_vin()
+    }
+}
+
+impl PowerSensor<Error> for Bmr491 {
+    fn read_power(&mut self) -> Result<Watts, Error> {
+        self.pmbus.read_power()
+    }
+}
+
+impl StatusSensor<Error> for Bmr491 {
+    fn read_status(&mut self) -> Result<PmbusStatus, Error> {
+        self.pmbus.read_status()
+    }
+
+    fn clear_faults(&mut self) -> Result<(), Error> {
+        self.pmbus.clear_faults()
     }
 }
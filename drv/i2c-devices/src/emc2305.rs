@@ -0,0 +1,206 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the EMC2305 fan controller
+//!
+//! The EMC2305 controls five fans.  Each fan has a block of sixteen
+//! registers, starting at 0x30 for the first fan; we run each fan in direct
+//! setting mode (that is, with its drive set by us rather than by the part's
+//! RPM-based fan speed control algorithm).
+
+use drv_i2c_api::*;
+use units::*;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Register {
+    Configuration = 0x20,
+    FanStatus = 0x24,
+    FanStallStatus = 0x25,
+    FanSpinStatus = 0x26,
+    DriveFailStatus = 0x27,
+    FanInterruptEnable = 0x29,
+    PWMPolarityConfig = 0x2a,
+    PWMOutputConfig = 0x2b,
+    PWMBaseFrequency45 = 0x2c,
+    PWMBaseFrequency123 = 0x2d,
+    ProductId = 0xfd,
+    ManufacturerId = 0xfe,
+    Revision = 0xff,
+}
+
+/// Registers within a fan's block, as offsets from the start of the block
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum FanRegister {
+    Setting = 0x0,
+    PWMDivide = 0x1,
+    Configuration1 = 0x2,
+    Configuration2 = 0x3,
+    Gain = 0x5,
+    SpinUpConfig = 0x6,
+    MaxStep = 0x7,
+    MinimumDrive = 0x8,
+    ValidTachCount = 0x9,
+    DriveFailBandLow = 0xa,
+    DriveFailBandHigh = 0xb,
+    TachTargetLow = 0xc,
+    TachTargetHigh = 0xd,
+    TachReadingHigh = 0xe,
+    TachReadingLow = 0xf,
+}
+
+const FAN1_BASE: u8 = 0x30;
+const FAN_STRIDE: u8 = 0x10;
+
+/// Product ID of the EMC2305
+const PRODUCT_ID: u8 = 0x34;
+
+pub struct Emc2305 {
+    pub device: I2cDevice,
+}
+
+pub const MAX_FANS: u8 = 5;
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Fan(u8);
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PWMDuty(pub u8);
+
+/// The fan fault status bits, one for each fan.  A fault is indicated when a
+/// fan has stalled, has failed to spin up, or cannot be driven to its target
+/// speed; the bits are latched until read.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FanFaults(pub u8);
+
+impl FanFaults {
+    /// Returns true if `fan` indicates a fault
+    pub fn is_faulted(&self, fan: Fan) -> bool {
+        self.0 & (1 << fan.0) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
+impl From<u8> for Fan {
+    /// Fans are based on a 0-based index. This should *not* be the number
+    /// of the fan (the fan numbers have a 1-based index)
+    fn from(index: u8) -> Self {
+        if index >= MAX_FANS {
+            panic!();
+        } else {
+            Self(index)
+        }
+    }
+}
+
+impl Fan {
+    /// Returns the 0-based index of the fan
+    pub fn index(&self) -> u8 {
+        self.0
+    }
+
+    fn register(&self, reg: FanRegister) -> u8 {
+        FAN1_BASE + self.0 * FAN_STRIDE + reg as u8
+    }
+}
+
+fn read_reg8(device: &I2cDevice, register: u8) -> Result<u8, ResponseCode> {
+    device.read_reg::<u8, u8>(register)
+}
+
+fn write_reg8(
+    device: &I2cDevice,
+    register: u8,
+    val: u8,
+) -> Result<(), ResponseCode> {
+    device.write(&[register, val])
+}
+
+impl core::fmt::Display for Emc2305 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "emc2305: {}", &self.device)
+    }
+}
+
+impl Emc2305 {
+    pub fn new(device: &I2cDevice) -> Self {
+        Self { device: *device }
+    }
+
+    pub fn initialize(&self) -> Result<(), ResponseCode> {
+        let device = &self.device;
+
+        if read_reg8(device, Register::ProductId as u8)? != PRODUCT_ID {
+            return Err(ResponseCode::BadResponse);
+        }
+
+        for fan in 0..MAX_FANS {
+            write_reg8(
+                device,
+                Fan::from(fan).register(FanRegister::Setting),
+                0,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Determines the rotations per minute based on the tach count
+    pub fn fan_rpm(&self, fan: Fan) -> Result<Rpm, ResponseCode> {
+        let hi = read_reg8(
+            &self.device,
+            fan.register(FanRegister::TachReadingHigh),
+        )?;
+        let lo =
+            read_reg8(&self.device, fan.register(FanRegister::TachReadingLow))?;
+
+        //
+        // The tach reading is the number of cycles of the 32.768 kHz clock
+        // counted in a configurable number of edges of the tach.  With the
+        // defaults -- five edges (that is, two pulses, or one revolution of a
+        // two-pole fan) and a range multiplier (m) of 2 -- this works out
+        // to:
+        //
+        //               3,932,160 * m
+        //         RPM = -------------
+        //                   count
+        //
+        // The count is thirteen bits:  the high register holds its upper
+        // eight bits, and the top five bits of the low register its lower
+        // five.
+        //
+        let count = (u32::from(hi) << 5) | u32::from(lo >> 3);
+
+        const TACH_MAX_VALUE: u32 = 0b1_1111_1111_1111;
+        const M: u32 = 2;
+
+        if count == TACH_MAX_VALUE || count == 0 {
+            Ok(Rpm(0))
+        } else {
+            let rpm = (3_932_160 * M) / count;
+            Ok(Rpm(rpm.min(u32::from(u16::MAX)) as u16))
+        }
+    }
+
+    /// Returns (and clears) the fan fault status bits
+    pub fn fan_faults(&self) -> Result<FanFaults, ResponseCode> {
+        let stall = read_reg8(&self.device, Register::FanStallStatus as u8)?;
+        let spin = read_reg8(&self.device, Register::FanSpinStatus as u8)?;
+        let drive = read_reg8(&self.device, Register::DriveFailStatus as u8)?;
+
+        Ok(FanFaults((stall | spin | drive) & 0b1_1111))
+    }
+
+    /// Set the PWM duty cycle for a fan
+    pub fn set_pwm(&self, fan: Fan, pwm: PWMDuty) -> Result<(), ResponseCode> {
+        let perc = core::cmp::min(pwm.0, 100) as f32;
+
+        let val = ((perc / 100.0) * 255.0) as u8;
+        write_reg8(&self.device, fan.register(FanRegister::Setting), val)
+    }
+}
@@ -0,0 +1,104 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the INA226 current and power monitor
+//!
+//! The INA226 can compute current and power itself, but only once it has
+//! been calibrated for its shunt resistor.  Rather than writing (and relying
+//! on) that calibration, we compute current from the shunt voltage and the
+//! value of the shunt resistor, and power from current and bus voltage.
+
+use crate::{CurrentSensor, PowerSensor, VoltageSensor};
+use drv_i2c_api::*;
+use units::*;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Register {
+    Configuration = 0x00,
+    ShuntVoltage = 0x01,
+    BusVoltage = 0x02,
+    Power = 0x03,
+    Current = 0x04,
+    Calibration = 0x05,
+    MaskEnable = 0x06,
+    AlertLimit = 0x07,
+    ManufacturerId = 0xfe,
+    DieId = 0xff,
+}
+
+/// Value of the least significant bit of the shunt voltage, in volts
+const SHUNT_VOLTAGE_LSB: f32 = 2.5e-6;
+
+/// Value of the least significant bit of the bus voltage, in volts
+const BUS_VOLTAGE_LSB: f32 = 1.25e-3;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+    BadRead { reg: Register, code: ResponseCode },
+}
+
+impl From<Error> for ResponseCode {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::BadRead { code, .. } => code,
+        }
+    }
+}
+
+pub struct Ina226 {
+    device: I2cDevice,
+
+    /// Value of the shunt resistor, in ohms
+    rshunt: f32,
+}
+
+impl core::fmt::Display for Ina226 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "ina226: {}", &self.device)
+    }
+}
+
+impl Ina226 {
+    pub fn new(device: &I2cDevice, rshunt: Ohms) -> Self {
+        Self {
+            device: *device,
+            rshunt: rshunt.0,
+        }
+    }
+
+    fn read_reg(&self, reg: Register) -> Result<[u8; 2], Error> {
+        self.device
+            .read_reg::<u8, [u8; 2]>(reg as u8)
+            .map_err(|code| Error::BadRead { reg, code })
+    }
+
+    /// Reads the voltage across the shunt resistor.
+    pub fn read_shunt_voltage(&self) -> Result<Volts, Error> {
+        let raw = i16::from_be_bytes(self.read_reg(Register::ShuntVoltage)?);
+        Ok(Volts(f32::from(raw) * SHUNT_VOLTAGE_LSB))
+    }
+}
+
+impl CurrentSensor<Error> for Ina226 {
+    fn read_iout(&mut self) -> Result<Amperes, Error> {
+        Ok(Amperes(self.read_shunt_voltage()?.0 / self.rshunt))
+    }
+}
+
+impl VoltageSensor<Error> for Ina226 {
+    fn read_vout(&mut self) -> Result<Volts, Error> {
+        let raw = u16::from_be_bytes(self.read_reg(Register::BusVoltage)?);
+        Ok(Volts(f32::from(raw) * BUS_VOLTAGE_LSB))
+    }
+}
+
+impl PowerSensor<Error> for Ina226 {
+    fn read_power(&mut self) -> Result<Watts, Error> {
+        let iout = self.read_iout()?;
+        let vout = self.read_vout()?;
+
+        Ok(Watts(iout.0 * vout.0))
+    }
+}
@@ -8,19 +8,29 @@
 //!
 //! - [`adm1272`]: ADM1272 hot swap controller
 //! - [`adt7420`]: ADT7420 temperature sensor
+//! - [`at24`]: AT24-class EEPROM
 //! - [`ds2482`]: DS2482-100 1-wire initiator
+//! - [`emc2305`]: EMC2305 fan controller
+//! - [`ina226`]: INA226 current and power monitor
 //! - [`isl68224`]: ISL68224 power controller
+//! - [`lm75`]: LM75-compatible temperature sensor
 //! - [`max6634`]: MAX6634 temperature sensor
 //! - [`max31790`]: MAX31790 fan controller
 //! - [`mcp9808`]: MCP9808 temperature sensor
 //! - [`pct2075`]: PCT2075 temperature sensor
+//! - [`pmbus_device`]: generic PMBus device
 //! - [`raa229618`]: RAA229618 power controller
 //! - [`sbtsi`]: AMD SB-TSI temperature sensor
 //! - [`tmp116`]: TMP116 temperature sensor
+//! - [`tmp451`]: TMP451 remote and local temperature sensor
 //! - [`tps546b24a`]: TPS546B24A buck converter
+//! - [`tse2004av`]: TSE2004av DIMM temperature sensor
 
 #![no_std]
 
+use num_traits::float::FloatCore;
+use units::{Amperes, Celsius, Volts, Watts};
+
 macro_rules! pmbus_read {
     ($device:expr, $cmd:ident) => {
         match $cmd::CommandData::from_slice(&match $device
@@ -89,32 +99,439 @@ macro_rules! pmbus_write {
     }};
 }
 
+//
+// Raw command codes for the PMBus status registers.  These are read and
+// written as plain bytes and words (rather than via the `pmbus` crate's
+// command data) so that their handling is common across devices.
+//
+pub(crate) mod status {
+    pub const CLEAR_FAULTS: u8 = 0x03;
+    pub const STATUS_WORD: u8 = 0x79;
+    pub const STATUS_VOUT: u8 = 0x7a;
+    pub const STATUS_IOUT: u8 = 0x7b;
+    pub const STATUS_INPUT: u8 = 0x7c;
+    pub const STATUS_TEMPERATURE: u8 = 0x7d;
+}
+
+//
+// Raw command codes for the PMBus energy accumulators, which are likewise
+// handled in common across devices.
+//
+pub(crate) mod energy {
+    pub const READ_EIN: u8 = 0x86;
+    #[allow(dead_code)]
+    pub const READ_EOUT: u8 = 0x87;
+
+    /// Returns the direct format coefficients for a given command, via a
+    /// block write-block read process call.
+    pub const COEFFICIENTS: u8 = 0x30;
+}
+
+//
+// Raw command code for `OPERATION`, whose margin bits are likewise handled in
+// common across devices.
+//
+#[cfg(feature = "margining")]
+pub(crate) mod operation {
+    pub const OPERATION: u8 = 0x01;
+
+    /// Bits 5:4 select the margin state; bits 3:2 select the response to
+    /// faults while margined.
+    pub const MARGIN_MASK: u8 = 0b0011_1100;
+}
+
+//
+// Raw command codes for the DMA interface of Renesas digital multiphase
+// controllers (e.g., the RAA229618 and ISL68224), through which their
+// internal memory -- including the black box -- is read.
+//
+pub(crate) mod renesas {
+    /// Sets the address of subsequent DMA accesses.
+    pub const DMAADDR: u8 = 0xc7;
+
+    /// Accesses the word at the DMA address, then increments the address.
+    pub const DMASEQ: u8 = 0xc6;
+}
+
+//
+// Reads `STATUS_WORD` and the per-class status registers, yielding a
+// `PmbusStatus`; the caller must have selected the page (if any).
+//
+macro_rules! pmbus_status {
+    ($device:expr) => {{
+        let read_byte = |cmd: u8| match $device.read_reg::<u8, u8>(cmd) {
+            Ok(rval) => Ok(rval),
+            Err(code) => Err(Error::BadRead { cmd, code }),
+        };
+
+        let cmd = $crate::status::STATUS_WORD;
+
+        let word = match $device.read_reg::<u8, u16>(cmd) {
+            Ok(rval) => Ok(rval),
+            Err(code) => Err(Error::BadRead { cmd, code }),
+        }?;
+
+        Ok($crate::PmbusStatus {
+            word,
+            vout: read_byte($crate::status::STATUS_VOUT)?,
+            iout: read_byte($crate::status::STATUS_IOUT)?,
+            input: read_byte($crate::status::STATUS_INPUT)?,
+            temperature: read_byte($crate::status::STATUS_TEMPERATURE)?,
+        })
+    }};
+}
+
+//
+// Sends `CLEAR_FAULTS`; the caller must have selected the page (if any).
+//
+macro_rules! pmbus_clear_faults {
+    ($device:expr) => {{
+        let cmd = $crate::status::CLEAR_FAULTS;
+
+        match $device.write(&[cmd]) {
+            Err(code) => Err(Error::BadWrite { cmd, code }),
+            Ok(_) => Ok(()),
+        }
+    }};
+}
+
+//
+// Reads an energy accumulator (`READ_EIN` or `READ_EOUT`) via a block read,
+// yielding an `EnergyCount`; the caller must have selected the page (if any).
+//
+macro_rules! pmbus_energy {
+    ($device:expr, $cmd:expr) => {{
+        let cmd = $cmd;
+        let mut buf = [0u8; 6];
+
+        match $device.read_block::<u8>(cmd, &mut buf) {
+            Ok(n) if n == buf.len() => {
+                Ok($crate::EnergyCount::from_block(&buf))
+            }
+            Ok(_) => Err(Error::BadRead {
+                cmd,
+                code: ResponseCode::BadResponse,
+            }),
+            Err(code) => Err(Error::BadRead { cmd, code }),
+        }
+    }};
+}
+
+//
+// Reads the coefficients with which a device reports the result of reading
+// command `$cmd` in the direct format (e.g., `READ_EIN`), yielding a
+// `pmbus::Coefficients`.  The process call writes the command code and a
+// flag indicating that it is the coefficients for reading that we want; the
+// response is m (2 bytes), b (2 bytes) and R (1 byte), each two's
+// complement and little-endian.  A zero m is rejected as a bad response,
+// as it can't be used to convert a value.
+//
+macro_rules! pmbus_coefficients {
+    ($device:expr, $cmd:expr) => {{
+        let cmd = $crate::energy::COEFFICIENTS;
+        let mut buf = [0u8; 5];
+
+        match $device.read_block::<[u8; 4]>([cmd, 2, $cmd, 1], &mut buf) {
+            Ok(n) if n == buf.len() => {
+                let m = i16::from_le_bytes([buf[0], buf[1]]);
+
+                if m == 0 {
+                    Err(Error::BadRead {
+                        cmd,
+                        code: ResponseCode::BadResponse,
+                    })
+                } else {
+                    Ok(pmbus::Coefficients {
+                        m: m.into(),
+                        b: i16::from_le_bytes([buf[2], buf[3]]).into(),
+                        R: (buf[4] as i8).into(),
+                    })
+                }
+            }
+            Ok(_) => Err(Error::BadRead {
+                cmd,
+                code: ResponseCode::BadResponse,
+            }),
+            Err(code) => Err(Error::BadRead { cmd, code }),
+        }
+    }};
+}
+
+///
+/// Converts `raw`, a value in the PMBus direct format (or the mean of such
+/// values), into real world units with coefficients `c`.
+///
+pub(crate) fn from_direct(raw: f32, c: &pmbus::Coefficients) -> f32 {
+    (raw * 10.0f32.powi(-(c.R as i32)) - c.b as f32) / c.m as f32
+}
+
+//
+// Sets the margin bits of `OPERATION` to those for the given `Margin`,
+// leaving its other bits alone; the caller must have selected the page (if
+// any).
+//
+#[cfg(feature = "margining")]
+macro_rules! pmbus_set_margin {
+    ($device:expr, $margin:expr) => {{
+        let cmd = $crate::operation::OPERATION;
+
+        let operation = match $device.read_reg::<u8, u8>(cmd) {
+            Ok(rval) => Ok(rval),
+            Err(code) => Err(Error::BadRead { cmd, code }),
+        }?;
+
+        let operation = (operation & !$crate::operation::MARGIN_MASK)
+            | $margin.operation_bits();
+
+        match $device.write(&[cmd, operation]) {
+            Err(code) => Err(Error::BadWrite { cmd, code }),
+            Ok(_) => Ok(()),
+        }
+    }};
+}
+
+//
+// Reads the black box of a Renesas controller via DMA from address `$addr`,
+// yielding a `Blackbox`.  The address depends on the part and its firmware,
+// so it is supplied by the caller rather than assumed here.
+//
+macro_rules! renesas_blackbox {
+    ($device:expr, $addr:expr) => {{
+        let cmd = $crate::renesas::DMAADDR;
+        let addr = u16::to_le_bytes($addr);
+
+        match $device.write(&[cmd, addr[0], addr[1]]) {
+            Err(code) => Err(Error::BadWrite { cmd, code }),
+            Ok(_) => Ok(()),
+        }?;
+
+        let cmd = $crate::renesas::DMASEQ;
+        let mut blackbox = $crate::Blackbox::EMPTY;
+
+        for word in blackbox.words.iter_mut() {
+            *word = match $device.read_reg::<u8, u32>(cmd) {
+                Ok(rval) => Ok(rval),
+                Err(code) => Err(Error::BadRead { cmd, code }),
+            }?;
+        }
+
+        Ok(blackbox)
+    }};
+}
+
+///
+/// The contents of a PMBus device's status registers:  `STATUS_WORD`, and
+/// the per-class registers whose summaries it contains.  Each field is the
+/// raw register, as defined by the PMBus specification.
+///
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct PmbusStatus {
+    pub word: u16,
+    pub vout: u8,
+    pub iout: u8,
+    pub input: u8,
+    pub temperature: u8,
+}
+
+///
+/// A reading of a PMBus energy accumulator (`READ_EIN` or `READ_EOUT`):  the
+/// sum of the device's raw power samples, and the number of samples summed.
+/// Both wrap; use [`EnergyCount::average_since`] to take the difference
+/// between two readings.
+///
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct EnergyCount {
+    /// Sum of raw power samples, modulo [`EnergyCount::ENERGY_MODULUS`]
+    pub energy: u32,
+
+    /// Number of samples, modulo [`EnergyCount::SAMPLES_MODULUS`]
+    pub samples: u32,
+}
+
+impl EnergyCount {
+    /// The accumulator itself is 15 bits, rolling over after 0x7fff.
+    pub const ACCUMULATOR_MODULUS: u32 = 0x8000;
+
+    /// Each rollover of the accumulator increments an 8-bit rollover count,
+    /// which together with the accumulator makes for a 23-bit sum.
+    pub const ENERGY_MODULUS: u32 = Self::ACCUMULATOR_MODULUS << 8;
+
+    /// The sample count is 24 bits.
+    pub const SAMPLES_MODULUS: u32 = 1 << 24;
+
+    ///
+    /// Decodes the payload of the block read:  the accumulator (2 bytes),
+    /// the rollover count (1 byte) and the sample count (3 bytes), each
+    /// little-endian.
+    ///
+    pub fn from_block(buf: &[u8; 6]) -> Self {
+        let accumulator = u32::from(u16::from_le_bytes([buf[0], buf[1]]));
+        let rollover = u32::from(buf[2]);
+
+        Self {
+            energy: (rollover * Self::ACCUMULATOR_MODULUS + accumulator)
+                % Self::ENERGY_MODULUS,
+            samples: u32::from_le_bytes([buf[3], buf[4], buf[5], 0]),
+        }
+    }
+
+    ///
+    /// Returns the mean raw power sample between `prev` and this reading, or
+    /// `None` if no samples were taken.  Because both moduli are powers of
+    /// two, wrapping subtraction accounts for any rollover -- provided that
+    /// neither sum has wrapped all the way around between the readings,
+    /// which bounds how infrequently the accumulator may be read.
+    ///
+    pub fn average_since(&self, prev: &EnergyCount) -> Option<f32> {
+        let energy =
+            self.energy.wrapping_sub(prev.energy) % Self::ENERGY_MODULUS;
+        let samples =
+            self.samples.wrapping_sub(prev.samples) % Self::SAMPLES_MODULUS;
+
+        if samples == 0 {
+            None
+        } else {
+            Some(energy as f32 / samples as f32)
+        }
+    }
+}
+
+///
+/// The margin state of a voltage regulator:  either regulating to its
+/// nominal output voltage, or to its `VOUT_MARGIN_LOW` or `VOUT_MARGIN_HIGH`
+/// target.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Margin {
+    Nominal,
+    Low,
+    High,
+}
+
+impl Margin {
+    ///
+    /// Returns the margin bits of `OPERATION` for this state.  When margined,
+    /// the device acts on any faults (rather than ignoring them), lest
+    /// margining mask a real problem.
+    ///
+    #[cfg(feature = "margining")]
+    pub(crate) fn operation_bits(&self) -> u8 {
+        match self {
+            Margin::Nominal => 0,
+            Margin::Low => 0b0001_1000,
+            Margin::High => 0b0010_1000,
+        }
+    }
+}
+
+/// Number of 32-bit words in a [`Blackbox`]
+pub const BLACKBOX_WORDS: usize = 32;
+
+///
+/// The contents of a device's black box:  its record of its state at its
+/// most recent fault, which it retains across power cycles.  The words are
+/// raw, as their layout is specific to the part and its firmware.
+///
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Blackbox {
+    pub words: [u32; BLACKBOX_WORDS],
+}
+
+impl Blackbox {
+    pub const EMPTY: Self = Self {
+        words: [0; BLACKBOX_WORDS],
+    };
+
+    /// Returns true if nothing has been recorded.
+    pub fn is_empty(&self) -> bool {
+        self.words.iter().all(|&w| w == 0)
+    }
+}
+
 pub trait TempSensor<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
-    fn read_temperature(&mut self) -> Result<userlib::units::Celsius, T>;
+    fn read_temperature(&mut self) -> Result<Celsius, T>;
 }
 
 pub trait PowerSensor<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
-    fn read_power(&mut self) -> Result<userlib::units::Watts, T>;
+    fn read_power(&mut self) -> Result<Watts, T>;
 }
 
 pub trait CurrentSensor<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
-    fn read_iout(&mut self) -> Result<userlib::units::Amperes, T>;
+    fn read_iout(&mut self) -> Result<Amperes, T>;
 }
 
 pub trait VoltageSensor<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
-    fn read_vout(&mut self) -> Result<userlib::units::Volts, T>;
+    fn read_vout(&mut self) -> Result<Volts, T>;
+}
+
+pub trait InputVoltageSensor<T: core::convert::Into<drv_i2c_api::ResponseCode>>
+{
+    fn read_vin(&mut self) -> Result<Volts, T>;
+}
+
+pub trait InputCurrentSensor<T: core::convert::Into<drv_i2c_api::ResponseCode>>
+{
+    fn read_iin(&mut self) -> Result<Amperes, T>;
+}
+
+pub trait EnergySensor<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
+    /// Reads the input energy accumulator, `READ_EIN`.
+    fn read_ein(&mut self) -> Result<EnergyCount, T>;
+
+    /// Converts a mean raw power sample, as returned by
+    /// [`EnergyCount::average_since`], into watts.
+    fn accumulated_power(&mut self, raw: f32) -> Result<Watts, T>;
+}
+
+pub trait VoltageRegulator<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
+    /// Reads the configured low margin target, `VOUT_MARGIN_LOW`.
+    fn read_margin_low(&mut self) -> Result<Volts, T>;
+
+    /// Reads the configured high margin target, `VOUT_MARGIN_HIGH`.
+    fn read_margin_high(&mut self) -> Result<Volts, T>;
+}
+
+///
+/// Margining of a voltage regulator's output.  This is only for validation,
+/// and so is only present with the `margining` feature; it is implemented
+/// only for parts whose margining via `OPERATION` has been checked against
+/// their datasheets, and not by the generic [`pmbus_device::PmbusDevice`].
+///
+#[cfg(feature = "margining")]
+pub trait Margining<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
+    /// Margins the output voltage high or low, or returns it to nominal.
+    fn set_margin(&mut self, margin: Margin) -> Result<(), T>;
+}
+
+pub trait FaultRecorder<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
+    /// Reads the device's black box, returning `None` if it is not reported
+    /// via this driver instance (e.g., because it is one of several rails of
+    /// the same device, or because the black box's address isn't known).
+    fn read_blackbox(&mut self) -> Result<Option<Blackbox>, T>;
+}
+
+pub trait StatusSensor<T: core::convert::Into<drv_i2c_api::ResponseCode>> {
+    fn read_status(&mut self) -> Result<PmbusStatus, T>;
+    fn clear_faults(&mut self) -> Result<(), T>;
 }
 
 pub mod adm1272;
 pub mod adt7420;
+pub mod at24;
 pub mod bmr491;
 pub mod ds2482;
+pub mod emc2305;
+pub mod ina226;
 pub mod isl68224;
+pub mod lm75;
 pub mod max31790;
 pub mod max6634;
 pub mod mcp9808;
 pub mod pct2075;
+pub mod pmbus_device;
 pub mod raa229618;
 pub mod sbtsi;
 pub mod tmp116;
+pub mod tmp451;
 pub mod tps546b24a;
+pub mod tse2004av;
@@ -0,0 +1,65 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for LM75-compatible temperature sensors
+//!
+//! There are many parts that are compatible with the LM75, differing in the
+//! resolution of their temperature register:  nine bits for the LM75 itself,
+//! and as many as twelve for some of its successors.  The temperature is
+//! left-justified in each, so it may be read without knowing the resolution.
+
+use crate::TempSensor;
+use drv_i2c_api::*;
+use units::*;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Register {
+    Temp = 0x00,
+    Conf = 0x01,
+    Thyst = 0x02,
+    Tos = 0x03,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    BadTempRead { code: ResponseCode },
+}
+
+impl From<Error> for ResponseCode {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::BadTempRead { code } => code,
+        }
+    }
+}
+
+pub struct Lm75 {
+    device: I2cDevice,
+}
+
+fn convert(raw: [u8; 2]) -> Celsius {
+    Celsius(f32::from(i16::from_be_bytes(raw)) / 256.0)
+}
+
+impl core::fmt::Display for Lm75 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "lm75: {}", &self.device)
+    }
+}
+
+impl Lm75 {
+    pub fn new(device: &I2cDevice) -> Self {
+        Self { device: *device }
+    }
+}
+
+impl TempSensor<Error> for Lm75 {
+    fn read_temperature(&mut self) -> Result<Celsius, Error> {
+        match self.device.read_reg::<u8, [u8; 2]>(Register::Temp as u8) {
+            Ok(buf) => Ok(convert(buf)),
+            Err(code) => Err(Error::BadTempRead { code }),
+        }
+    }
+}
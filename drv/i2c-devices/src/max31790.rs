@@ -6,8 +6,9 @@
 
 use bitfield::bitfield;
 use drv_i2c_api::*;
-use userlib::units::*;
-use userlib::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
+use units::*;
 
 #[allow(dead_code)]
 enum I2cWatchdog {
@@ -179,6 +180,24 @@ pub struct Fan(u8);
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct PWMDuty(pub u8);
 
+/// The fan fault status bits, one for each of the twelve tach inputs.  A
+/// fault is indicated when a fan's tach count reaches its maximum (i.e., the
+/// fan has stalled) or, in RPM mode, when the fan fails to reach its target
+/// speed; the bits are latched until cleared.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct FanFaults(pub u16);
+
+impl FanFaults {
+    /// Returns true if the tach input of `fan` indicates a fault
+    pub fn is_faulted(&self, fan: Fan) -> bool {
+        self.0 & (1 << fan.0) != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+}
+
 impl From<u8> for Fan {
     /// Fans are based on a 0-based index. This should *not* be the number
     /// of the fan (the fan numbers have a 1-based index)
@@ -192,6 +211,11 @@ impl From<u8> for Fan {
 }
 
 impl Fan {
+    /// Returns the 0-based index of the fan
+    pub fn index(&self) -> u8 {
+        self.0
+    }
+
     fn register(&self, base: Register, shift: u8) -> Register {
         let addend = self.0 << shift;
         Register::from_u8((base as u8) + addend).unwrap()
@@ -307,6 +331,26 @@ impl Max31790 {
         }
     }
 
+    /// Returns the fan fault status bits
+    pub fn fan_faults(&self) -> Result<FanFaults, ResponseCode> {
+        //
+        // Status 1 contains the bits for tach inputs 1 through 6, and status
+        // 2 those for 7 through 12.
+        //
+        let lo = read_reg8(&self.device, Register::FanFaultStatus1)?;
+        let hi = read_reg8(&self.device, Register::FanFaultStatus2)?;
+
+        Ok(FanFaults(
+            (u16::from(hi & 0b11_1111) << 6) | u16::from(lo & 0b11_1111),
+        ))
+    }
+
+    /// Clears the latched fan fault status bits
+    pub fn clear_fan_faults(&self) -> Result<(), ResponseCode> {
+        write_reg8(&self.device, Register::FanFaultStatus1, 0)?;
+        write_reg8(&self.device, Register::FanFaultStatus2, 0)
+    }
+
     /// Set the PWM duty cycle for a fan
     pub fn set_pwm(&self, fan: Fan, pwm: PWMDuty) -> Result<(), ResponseCode> {
         let perc = core::cmp::min(pwm.0, 100) as f32;
@@ -0,0 +1,304 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for a generic PMBus device
+//!
+//! This driver works with any PMBus device whose telemetry is in the formats
+//! defined by the PMBus specification:  output voltages (and their margin
+//! targets) are in the format given by `VOUT_MODE`, and all other telemetry
+//! is in the linear format -- or, for devices that use the direct format, is
+//! converted with coefficients supplied via
+//! [`PmbusDevice::with_coefficients`].  Devices with more than one rail are
+//! paged; the `pmbus` module generated by `build/i2c` has the page of each
+//! rail.
+//!
+//! We issue only the commands that a PMBus device can be expected to
+//! implement.  In particular, `READ_POUT` is optional (the TPS546B24A, for
+//! one, doesn't implement it, and latches a communications fault if it is
+//! read), so output power is the product of output voltage and current.
+//!
+//! Drivers for parts with functionality beyond the specification (e.g., the
+//! Renesas controllers' black boxes) build on this driver for their standard
+//! telemetry.
+
+#[cfg(feature = "margining")]
+use crate::Margin;
+use crate::{
+    CurrentSensor, InputCurrentSensor, InputVoltageSensor, PmbusStatus,
+    PowerSensor, StatusSensor, TempSensor, VoltageRegulator, VoltageSensor,
+};
+use drv_i2c_api::*;
+use num_traits::float::FloatCore;
+use units::*;
+
+//
+// Command codes, as defined by the PMBus specification.
+//
+mod cmd {
+    pub const PAGE: u8 = 0x00;
+    pub const VOUT_MODE: u8 = 0x20;
+    pub const VOUT_MARGIN_HIGH: u8 = 0x25;
+    pub const VOUT_MARGIN_LOW: u8 = 0x26;
+    pub const READ_VIN: u8 = 0x88;
+    pub const READ_IIN: u8 = 0x89;
+    pub const READ_VOUT: u8 = 0x8b;
+    pub const READ_IOUT: u8 = 0x8c;
+    pub const READ_TEMPERATURE_1: u8 = 0x8d;
+}
+
+///
+/// Coefficients for a device whose telemetry is in the direct format, by
+/// class of telemetry.  Each converts a raw value Y to a value X as
+/// `X = (Y * 10^-R - b) / m`.
+///
+#[derive(Copy, Clone)]
+pub struct DirectCoefficients {
+    pub voltage: pmbus::Coefficients,
+    pub current: pmbus::Coefficients,
+    pub temperature: pmbus::Coefficients,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum VOutMode {
+    /// Linear, with the given exponent
+    Linear(i8),
+
+    /// Direct, with the voltage coefficients
+    Direct,
+}
+
+//
+// `UnsupportedMode` denotes a `VOUT_MODE` in a format that we don't support
+// (e.g., VID), or in the direct format when we have no coefficients.
+//
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    BadRead { cmd: u8, code: ResponseCode },
+    BadWrite { cmd: u8, code: ResponseCode },
+    UnsupportedMode { mode: u8 },
+}
+
+impl From<Error> for ResponseCode {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::BadRead { code, .. } => code,
+            Error::BadWrite { code, .. } => code,
+            Error::UnsupportedMode { .. } => ResponseCode::BadResponse,
+        }
+    }
+}
+
+pub struct PmbusDevice {
+    device: I2cDevice,
+
+    /// Page of our rail, if the device has more than one
+    page: Option<u8>,
+
+    /// Coefficients, if the device uses the direct format
+    coefficients: Option<DirectCoefficients>,
+
+    /// Our (cached) VOUT_MODE
+    mode: Option<VOutMode>,
+}
+
+impl core::fmt::Display for PmbusDevice {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "pmbus: {}", &self.device)
+    }
+}
+
+///
+/// Decodes a value in the linear format:  a 5-bit two's complement exponent
+/// above an 11-bit two's complement mantissa.
+///
+fn linear11(raw: u16) -> f32 {
+    let exponent = (raw as i16) >> 11;
+    let mantissa = ((raw << 5) as i16) >> 5;
+
+    f32::from(mantissa) * 2.0f32.powi(exponent.into())
+}
+
+/// Decodes a value in the direct format.
+fn direct(raw: u16, c: &pmbus::Coefficients) -> f32 {
+    crate::from_direct(f32::from(raw as i16), c)
+}
+
+impl PmbusDevice {
+    pub fn new(device: &I2cDevice, page: Option<u8>) -> Self {
+        Self {
+            device: *device,
+            page,
+            coefficients: None,
+            mode: None,
+        }
+    }
+
+    pub fn with_coefficients(
+        device: &I2cDevice,
+        page: Option<u8>,
+        coefficients: DirectCoefficients,
+    ) -> Self {
+        Self {
+            coefficients: Some(coefficients),
+            ..Self::new(device, page)
+        }
+    }
+
+    ///
+    /// Margins the output voltage.  This isn't exposed via [`Margining`], as
+    /// not every PMBus device margins as the specification describes; it is
+    /// for the drivers of parts that have been checked to do so.
+    ///
+    /// [`Margining`]: crate::Margining
+    ///
+    #[cfg(feature = "margining")]
+    pub(crate) fn set_margin(&mut self, margin: Margin) -> Result<(), Error> {
+        self.set_page()?;
+        pmbus_set_margin!(self.device, margin)
+    }
+
+    fn set_page(&self) -> Result<(), Error> {
+        match self.page {
+            Some(page) => match self.device.write(&[cmd::PAGE, page]) {
+                Err(code) => Err(Error::BadWrite {
+                    cmd: cmd::PAGE,
+                    code,
+                }),
+                Ok(_) => Ok(()),
+            },
+            None => Ok(()),
+        }
+    }
+
+    fn read_word(&self, cmd: u8) -> Result<u16, Error> {
+        match self.device.read_reg::<u8, u16>(cmd) {
+            Ok(rval) => Ok(rval),
+            Err(code) => Err(Error::BadRead { cmd, code }),
+        }
+    }
+
+    fn read_mode(&mut self) -> Result<VOutMode, Error> {
+        if let Some(mode) = self.mode {
+            return Ok(mode);
+        }
+
+        let cmd = cmd::VOUT_MODE;
+
+        let raw = match self.device.read_reg::<u8, u8>(cmd) {
+            Ok(rval) => Ok(rval),
+            Err(code) => Err(Error::BadRead { cmd, code }),
+        }?;
+
+        //
+        // The top three bits are the mode; the bottom five are its
+        // parameter, which for the linear mode is a two's complement
+        // exponent.
+        //
+        let mode = match (raw >> 5, self.coefficients) {
+            (0b000, _) => VOutMode::Linear(((raw << 3) as i8) >> 3),
+            (0b010, Some(_)) => VOutMode::Direct,
+            _ => return Err(Error::UnsupportedMode { mode: raw }),
+        };
+
+        self.mode = Some(mode);
+        Ok(mode)
+    }
+
+    /// Reads an output voltage (or a target thereof); the page must have
+    /// been selected.
+    fn read_vout_format(&mut self, cmd: u8) -> Result<Volts, Error> {
+        let raw = self.read_word(cmd)?;
+
+        Ok(Volts(match (self.read_mode()?, &self.coefficients) {
+            (VOutMode::Linear(exponent), _) => {
+                f32::from(raw) * 2.0f32.powi(exponent.into())
+            }
+            (VOutMode::Direct, Some(c)) => direct(raw, &c.voltage),
+            (VOutMode::Direct, None) => unreachable!(),
+        }))
+    }
+
+    /// Reads telemetry in the linear or direct format; the page must have
+    /// been selected.
+    fn read_telemetry(
+        &self,
+        cmd: u8,
+        class: fn(&DirectCoefficients) -> &pmbus::Coefficients,
+    ) -> Result<f32, Error> {
+        let raw = self.read_word(cmd)?;
+
+        Ok(match &self.coefficients {
+            None => linear11(raw),
+            Some(c) => direct(raw, class(c)),
+        })
+    }
+}
+
+impl VoltageSensor<Error> for PmbusDevice {
+    fn read_vout(&mut self) -> Result<Volts, Error> {
+        self.set_page()?;
+        self.read_vout_format(cmd::READ_VOUT)
+    }
+}
+
+impl CurrentSensor<Error> for PmbusDevice {
+    fn read_iout(&mut self) -> Result<Amperes, Error> {
+        self.set_page()?;
+        Ok(Amperes(
+            self.read_telemetry(cmd::READ_IOUT, |c| &c.current)?,
+        ))
+    }
+}
+
+impl InputVoltageSensor<Error> for PmbusDevice {
+    fn read_vin(&mut self) -> Result<Volts, Error> {
+        self.set_page()?;
+        Ok(Volts(self.read_telemetry(cmd::READ_VIN, |c| &c.voltage)?))
+    }
+}
+
+impl InputCurrentSensor<Error> for PmbusDevice {
+    fn read_iin(&mut self) -> Result<Amperes, Error> {
+        self.set_page()?;
+        Ok(Amperes(self.read_telemetry(cmd::READ_IIN, |c| &c.current)?))
+    }
+}
+
+impl PowerSensor<Error> for PmbusDevice {
+    fn read_power(&mut self) -> Result<Watts, Error> {
+        Ok(self.read_vout()? * self.read_iout()?)
+    }
+}
+
+impl TempSensor<Error> for PmbusDevice {
+    fn read_temperature(&mut self) -> Result<Celsius, Error> {
+        self.set_page()?;
+        let cmd = cmd::READ_TEMPERATURE_1;
+        Ok(Celsius(self.read_telemetry(cmd, |c| &c.temperature)?))
+    }
+}
+
+impl VoltageRegulator<Error> for PmbusDevice {
+    fn read_margin_low(&mut self) -> Result<Volts, Error> {
+        self.set_page()?;
+        self.read_vout_format(cmd::VOUT_MARGIN_LOW)
+    }
+
+    fn read_margin_high(&mut self) -> Result<Volts, Error> {
+        self.set_page()?;
+        self.read_vout_format(cmd::VOUT_MARGIN_HIGH)
+    }
+}
+
+impl StatusSensor<Error> for PmbusDevice {
+    fn read_status(&mut self) -> Result<PmbusStatus, Error> {
+        self.set_page()?;
+        pmbus_status!(self.device)
+    }
+
+    fn clear_faults(&mut self) -> Result<(), Error> {
+        self.set_page()?;
+        pmbus_clear_faults!(self.device)
+    }
+}
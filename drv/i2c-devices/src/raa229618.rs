@@ -2,16 +2,30 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::{CurrentSensor, TempSensor, VoltageSensor};
+use crate::pmbus_device::{self, PmbusDevice};
+use crate::{
+    Blackbox, CurrentSensor, EnergyCount, EnergySensor, FaultRecorder,
+    InputCurrentSensor, InputVoltageSensor, PmbusStatus, PowerSensor,
+    StatusSensor, TempSensor, VoltageRegulator, VoltageSensor,
+};
+#[cfg(feature = "margining")]
+use crate::{Margin, Margining};
 use drv_i2c_api::*;
 use pmbus::commands::raa229618::*;
-use pmbus::*;
-use userlib::units::*;
+use units::*;
 
 pub struct Raa229618 {
     device: I2cDevice,
     rail: u8,
-    mode: Option<pmbus::VOutModeCommandData>,
+
+    /// Driver for our rail's standard telemetry
+    pmbus: PmbusDevice,
+
+    /// Address of the black box in the part's memory, if known
+    blackbox: Option<u16>,
+
+    /// Our (cached) coefficients for `READ_EIN`
+    ein: Option<pmbus::Coefficients>,
 }
 
 impl core::fmt::Display for Raa229618 {
@@ -26,6 +40,7 @@ pub enum Error {
     BadWrite { cmd: u8, code: ResponseCode },
     BadData { cmd: u8 },
     InvalidData { err: pmbus::Error },
+    UnsupportedMode { mode: u8 },
 }
 
 impl From<Error> for ResponseCode {
@@ -33,6 +48,7 @@ impl From<Error> for ResponseCode {
         match err {
             Error::BadRead { code, .. } => code,
             Error::BadWrite { code, .. } => code,
+            Error::UnsupportedMode { .. } => ResponseCode::BadResponse,
             _ => panic!(),
         }
     }
@@ -44,24 +60,60 @@ impl From<pmbus::Error> for Error {
     }
 }
 
+impl From<pmbus_device::Error> for Error {
+    fn from(err: pmbus_device::Error) -> Self {
+        match err {
+            pmbus_device::Error::BadRead { cmd, code } => {
+                Error::BadRead { cmd, code }
+            }
+            pmbus_device::Error::BadWrite { cmd, code } => {
+                Error::BadWrite { cmd, code }
+            }
+            pmbus_device::Error::UnsupportedMode { mode } => {
+                Error::UnsupportedMode { mode }
+            }
+        }
+    }
+}
+
 impl Raa229618 {
     pub fn new(device: &I2cDevice, rail: u8) -> Self {
         Raa229618 {
             device: *device,
             rail: rail,
-            mode: None,
+            pmbus: PmbusDevice::new(device, Some(rail)),
+            blackbox: None,
+            ein: None,
         }
     }
 
-    fn read_mode(&mut self) -> Result<pmbus::VOutModeCommandData, Error> {
-        Ok(match self.mode {
-            None => {
-                let mode = pmbus_read!(self.device, commands::VOUT_MODE)?;
-                self.mode = Some(mode);
-                mode
-            }
-            Some(mode) => mode,
-        })
+    ///
+    /// Returns a driver that can also read the part's black box, at address
+    /// `blackbox` in its memory.  (The address depends on the part's
+    /// firmware; without it, the black box isn't read.)
+    ///
+    pub fn with_blackbox(device: &I2cDevice, rail: u8, blackbox: u16) -> Self {
+        Self {
+            blackbox: Some(blackbox),
+            ..Self::new(device, rail)
+        }
+    }
+
+    //
+    // The scale of READ_EIN isn't documented for this part, so rather than
+    // assume one, we ask the part for it (and cache the result).
+    //
+    fn read_ein_coefficients(&mut self) -> Result<pmbus::Coefficients, Error> {
+        if let Some(coefficients) = self.ein {
+            return Ok(coefficients);
+        }
+
+        self.set_rail()?;
+        let coefficients =
+            pmbus_coefficients!(self.device, crate::energy::READ_EIN)?;
+        self.ein = Some(coefficients);
+
+        Ok(coefficients)
     }
 
     fn set_rail(&self) -> Result<(), Error> {
@@ -86,24 +138,90 @@ impl Raa229618 {
 
 impl VoltageSensor<Error> for Raa229618 {
     fn read_vout(&mut self) -> Result<Volts, Error> {
-        self.set_rail()?;
-        let vout = pmbus_read!(self.device, READ_VOUT)?;
-        Ok(Volts(vout.get(self.read_mode()?)?.0))
+        Ok(self.pmbus.read_vout()?)
     }
 }
 
 impl TempSensor<Error> for Raa229618 {
     fn read_temperature(&mut self) -> Result<Celsius, Error> {
-        self.set_rail()?;
-        let temp = pmbus_read!(self.device, READ_TEMPERATURE_1)?;
-        Ok(Celsius(temp.get()?.0))
+        Ok(self.pmbus.read_temperature()?)
     }
 }
 
 impl CurrentSensor<Error> for Raa229618 {
     fn read_iout(&mut self) -> Result<Amperes, Error> {
+        Ok(self.pmbus.read_iout()?)
+    }
+}
+
+impl InputVoltageSensor<Error> for Raa229618 {
+    fn read_vin(&mut self) -> Result<Volts, Error> {
+        Ok(self.pmbus.read_vin()?)
+    }
+}
+
+impl InputCurrentSensor<Error> for Raa229618 {
+    fn read_iin(&mut self) -> Result<Amperes, Error> {
+        Ok(self.pmbus.read_iin()?)
+    }
+}
+
+impl PowerSensor<Error> for Raa229618 {
+    fn read_power(&mut self) -> Result<Watts, Error> {
+        Ok(self.pmbus.read_power()?)
+    }
+}
+
+impl EnergySensor<Error> for Raa229618 {
+    fn read_ein(&mut self) -> Result<EnergyCount, Error> {
         self.set_rail()?;
-        let iout = pmbus_read!(self.device, READ_IOUT)?;
-        Ok(Amperes(iout.get()?.0))
+        pmbus_energy!(self.device, crate::energy::READ_EIN)
+    }
+
+    fn accumulated_power(&mut self, raw: f32) -> Result<Watts, Error> {
+        let c = self.read_ein_coefficients()?;
+        Ok(Watts(crate::from_direct(raw, &c)))
+    }
+}
+
+#[cfg(feature = "margining")]
+impl Margining<Error> for Raa229618 {
+    fn set_margin(&mut self, margin: Margin) -> Result<(), Error> {
+        Ok(self.pmbus.set_margin(margin)?)
+    }
+}
+
+impl VoltageRegulator<Error> for Raa229618 {
+    fn read_margin_low(&mut self) -> Result<Volts, Error> {
+        Ok(self.pmbus.read_margin_low()?)
+    }
+
+    fn read_margin_high(&mut self) -> Result<Volts, Error> {
+        Ok(self.pmbus.read_margin_high()?)
+    }
+}
+
+impl FaultRecorder<Error> for Raa229618 {
+    fn read_blackbox(&mut self) -> Result<Option<Blackbox>, Error> {
+        //
+        // The black box belongs to the device rather than to any one rail;
+        // we report it only via the first, so that it's read once.
+        //
+        match self.blackbox {
+            Some(addr) if self.rail == 0 => {
+                renesas_blackbox!(self.device, addr).map(Some)
+            }
+            _ => Ok(None),
+        }
+    }
+}
+
+impl StatusSensor<Error> for Raa229618 {
+    fn read_status(&mut self) -> Result<PmbusStatus, Error> {
+        Ok(self.pmbus.read_status()?)
+    }
+
+    fn clear_faults(&mut self) -> Result<(), Error> {
+        Ok(self.pmbus.clear_faults()?)
     }
 }
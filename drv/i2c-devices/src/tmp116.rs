@@ -6,7 +6,7 @@
 
 use crate::TempSensor;
 use drv_i2c_api::*;
-use userlib::units::*;
+use units::*;
 
 #[allow(dead_code)]
 #[derive(Copy, Clone, Debug, PartialEq)]
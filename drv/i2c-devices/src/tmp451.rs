@@ -0,0 +1,143 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for the TMP451 remote and local temperature sensor
+
+use crate::TempSensor;
+use bitfield::bitfield;
+use drv_i2c_api::*;
+use units::*;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Register {
+    LocalTempHigh = 0x00,
+    RemoteTempHigh = 0x01,
+    Status = 0x02,
+    Configuration = 0x03,
+    ConversionRate = 0x04,
+    RemoteTempLow = 0x10,
+    RemoteOffsetHigh = 0x11,
+    RemoteOffsetLow = 0x12,
+    LocalTempLow = 0x15,
+    NFactor = 0x23,
+    DigitalFilter = 0x24,
+    ManufacturerId = 0xfe,
+}
+
+bitfield! {
+    pub struct Configuration(u8);
+    alert_mask, set_alert_mask: 7;
+    shutdown, set_shutdown: 6;
+    therm2_enable, set_therm2_enable: 5;
+    extended_range, set_extended_range: 2;
+}
+
+/// The sensor that a [`Tmp451`] reads:  the remote diode (e.g., that of an
+/// ASIC) or the TMP451's own die.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Target {
+    Local,
+    Remote,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Error {
+    BadConfigRead { code: ResponseCode },
+    BadTempRead { code: ResponseCode },
+}
+
+impl From<Error> for ResponseCode {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::BadConfigRead { code } => code,
+            Error::BadTempRead { code } => code,
+        }
+    }
+}
+
+pub struct Tmp451 {
+    device: I2cDevice,
+    target: Target,
+
+    /// Whether the part is in its extended range, in which temperatures are
+    /// offset by 64 degrees; read from the part on our first read
+    extended: Option<bool>,
+}
+
+impl core::fmt::Display for Tmp451 {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "tmp451: {}", &self.device)
+    }
+}
+
+fn convert(high: u8, low: u8, extended: bool) -> Celsius {
+    //
+    // The high byte is whole degrees; the high nibble of the low byte is
+    // sixteenths of a degree.
+    //
+    let temp = f32::from(high) + f32::from(low >> 4) / 16.0;
+
+    if extended {
+        Celsius(temp - 64.0)
+    } else {
+        Celsius(temp)
+    }
+}
+
+impl Tmp451 {
+    pub fn new(device: &I2cDevice, target: Target) -> Self {
+        Self {
+            device: *device,
+            target,
+            extended: None,
+        }
+    }
+
+    fn extended(&mut self) -> Result<bool, Error> {
+        if let Some(extended) = self.extended {
+            return Ok(extended);
+        }
+
+        match self
+            .device
+            .read_reg::<u8, u8>(Register::Configuration as u8)
+        {
+            Ok(config) => {
+                let extended = Configuration(config).extended_range();
+                self.extended = Some(extended);
+                Ok(extended)
+            }
+            Err(code) => Err(Error::BadConfigRead { code }),
+        }
+    }
+}
+
+impl TempSensor<Error> for Tmp451 {
+    fn read_temperature(&mut self) -> Result<Celsius, Error> {
+        let extended = self.extended()?;
+
+        let (high, low) = match self.target {
+            Target::Local => (Register::LocalTempHigh, Register::LocalTempLow),
+            Target::Remote => {
+                (Register::RemoteTempHigh, Register::RemoteTempLow)
+            }
+        };
+
+        //
+        // Reading the high byte latches the low byte, so the two must be
+        // read in this order.
+        //
+        let read = |reg: Register| {
+            self.device
+                .read_reg::<u8, u8>(reg as u8)
+                .map_err(|code| Error::BadTempRead { code })
+        };
+
+        let high = read(high)?;
+        let low = read(low)?;
+
+        Ok(convert(high, low, extended))
+    }
+}
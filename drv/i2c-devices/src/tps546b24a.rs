@@ -4,10 +4,15 @@
 
 //! Driver for the TPS546B24A buck converter
 
-use crate::{CurrentSensor, TempSensor, VoltageSensor};
+use crate::{
+    CurrentSensor, InputVoltageSensor, PmbusStatus, PowerSensor, StatusSensor,
+    TempSensor, VoltageRegulator, VoltageSensor,
+};
+#[cfg(feature = "margining")]
+use crate::{Margin, Margining};
 use drv_i2c_api::*;
 use pmbus::commands::*;
-use userlib::units::*;
+use units::*;
 
 pub struct Tps546b24a {
     device: I2cDevice,
@@ -78,3 +83,45 @@ impl VoltageSensor<Error> for Tps546b24a {
         Ok(Volts(vout.get(self.read_mode()?)?.0))
     }
 }
+
+impl InputVoltageSensor<Error> for Tps546b24a {
+    fn read_vin(&mut self) -> Result<Volts, Error> {
+        let vin = pmbus_read!(self.device, tps546b24a::READ_VIN)?;
+        Ok(Volts(vin.get()?.0))
+    }
+}
+
+impl PowerSensor<Error> for Tps546b24a {
+    fn read_power(&mut self) -> Result<Watts, Error> {
+        Ok(self.read_vout()? * self.read_iout()?)
+    }
+}
+
+#[cfg(feature = "margining")]
+impl Margining<Error> for Tps546b24a {
+    fn set_margin(&mut self, margin: Margin) -> Result<(), Error> {
+        pmbus_set_margin!(self.device, margin)
+    }
+}
+
+impl VoltageRegulator<Error> for Tps546b24a {
+    fn read_margin_low(&mut self) -> Result<Volts, Error> {
+        let vout = pmbus_read!(self.device, tps546b24a::VOUT_MARGIN_LOW)?;
+        Ok(Volts(vout.get(self.read_mode()?)?.0))
+    }
+
+    fn read_margin_high(&mut self) -> Result<Volts, Error> {
+        let vout = pmbus_read!(self.device, tps546b24a::VOUT_MARGIN_HIGH)?;
+        Ok(Volts(vout.get(self.read_mode()?)?.0))
+    }
+}
+
+impl StatusSensor<Error> for Tps546b24a {
+    fn read_status(&mut self) -> Result<PmbusStatus, Error> {
+        pmbus_status!(self.device)
+    }
+
+    fn clear_faults(&mut self) -> Result<(), Error> {
+        pmbus_clear_faults!(self.device)
+    }
+}
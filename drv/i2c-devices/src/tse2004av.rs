@@ -0,0 +1,79 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Driver for JEDEC TS3000-compatible DIMM temperature sensors
+//!
+//! DDR4 DIMMs may carry a temperature sensor on die (TSOD) alongside their
+//! SPD EEPROM (e.g., the TSE2004av, which integrates both).  The sensor sits
+//! at address `0b0011_xxx`, where `xxx` is the DIMM's select code -- the same
+//! select code that the SPD EEPROM uses at `0b1010_xxx`.  The temperature
+//! register holds a 13-bit two's complement value in units of 1/16th of a
+//! degree; its upper three bits are alarm flags.
+
+use crate::TempSensor;
+use drv_i2c_api::*;
+use units::*;
+
+#[allow(dead_code)]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Register {
+    Capabilities = 0x00,
+    Configuration = 0x01,
+    HighLimit = 0x02,
+    LowLimit = 0x03,
+    CriticalLimit = 0x04,
+    Temperature = 0x05,
+    ManufacturerId = 0x06,
+    DeviceRevision = 0x07,
+}
+
+#[derive(Debug)]
+pub enum Error {
+    BadTempRead { code: ResponseCode },
+}
+
+impl From<Error> for ResponseCode {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::BadTempRead { code } => code,
+        }
+    }
+}
+
+pub struct Tse2004Av {
+    device: I2cDevice,
+}
+
+fn convert(raw: [u8; 2]) -> Celsius {
+    //
+    // Discard the alarm flags in the upper three bits, sign-extending the
+    // remaining 13 bits.
+    //
+    let raw = ((u16::from_be_bytes(raw) << 3) as i16) >> 3;
+    Celsius(f32::from(raw) / 16.0)
+}
+
+impl core::fmt::Display for Tse2004Av {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        write!(f, "tse2004av: {}", &self.device)
+    }
+}
+
+impl Tse2004Av {
+    pub fn new(device: &I2cDevice) -> Self {
+        Self { device: *device }
+    }
+}
+
+impl TempSensor<Error> for Tse2004Av {
+    fn read_temperature(&mut self) -> Result<Celsius, Error> {
+        match self
+            .device
+            .read_reg::<u8, [u8; 2]>(Register::Temperature as u8)
+        {
+            Ok(buf) => Ok(convert(buf)),
+            Err(code) => Err(Error::BadTempRead { code }),
+        }
+    }
+}
@@ -12,7 +12,8 @@
 
 #![no_std]
 
-use userlib::*;
+use num_derive::FromPrimitive;
+use num_traits::FromPrimitive;
 
 /// 1-wire commands.  Most devices support more commands, but these commands
 /// are supported by all devices.
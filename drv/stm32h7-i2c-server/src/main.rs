@@ -160,7 +160,7 @@ fn main() -> ! {
     configure_controllers(&controllers);
 
     // Field messages.
-    let mut buffer = [0; 4];
+    let mut buffer = [0; 5];
 
     let ctrl = I2cControl {
         enable: |notification| {
@@ -177,10 +177,10 @@ fn main() -> ! {
         hl::recv_without_notification(&mut buffer, |op, msg| match op {
             Op::WriteRead | Op::WriteReadBlock => {
                 let (payload, caller) = msg
-                    .fixed_with_leases::<[u8; 4], usize>(2)
+                    .fixed_with_leases::<[u8; 5], usize>(2)
                     .ok_or(ResponseCode::BadArg)?;
 
-                let (addr, controller, port, mux) =
+                let (addr, controller, port, mux, pec) =
                     Marshal::unmarshal(payload)?;
 
                 if let Some(_) = ReservedAddress::from_u8(addr) {
@@ -225,9 +225,19 @@ fn main() -> ! {
                     return Err(ResponseCode::BadArg);
                 }
 
-                if winfo.len > 255 || rinfo.len > 255 {
+                //
+                // With packet error checking, the PEC byte follows the write
+                // if there is no read, and otherwise follows the read.
+                //
+                let read = op == Op::WriteReadBlock || rinfo.len > 0;
+                let wpec = pec && !read;
+                let rpec = pec && read;
+
+                if winfo.len + wpec as usize > 255
+                    || rinfo.len + rpec as usize > 255
+                {
                     // For now, we don't support writing or reading more than
-                    // 255 bytes.
+                    // 255 bytes -- including any PEC byte.
                     return Err(ResponseCode::BadArg);
                 }
 
@@ -235,6 +245,7 @@ fn main() -> ! {
 
                 match controller.write_read(
                     addr,
+                    pec,
                     winfo.len,
                     |pos| wbuf.read_at(pos),
                     if op == Op::WriteRead {
@@ -28,6 +28,7 @@ pub mod pca9548;
 use ringbuf::*;
 use userlib::*;
 
+use drv_i2c_api::pec_update;
 use drv_stm32xx_sys_api as sys_api;
 
 pub struct I2cPin {
@@ -136,6 +137,7 @@ enum Trace {
     WaitRx,
     WaitTx,
     BusySleep,
+    BadPec(u8, u8),
     None,
 }
 
@@ -404,9 +406,17 @@ impl<'a> I2cController<'a> {
     /// be non-zero.  Additionally, both lengths must be less than 256 bytes:
     /// the device can support longer buffers, and the implementation could
     /// be extended in the future to allow them.
+    ///
+    /// If `pec` is set, the transaction uses SMBus packet error checking:  a
+    /// write alone is followed by a PEC byte, and a read (with or without a
+    /// preceding write) is expected to be followed by a PEC byte from the
+    /// device.  Neither is seen by `getbyte` or `putbyte`, and a PEC byte
+    /// that doesn't match results in `ResponseCode::BadChecksum`.  The PEC
+    /// byte counts against the 255 byte limit on either length.
     pub fn write_read(
         &self,
         addr: u8,
+        pec: bool,
         wlen: usize,
         getbyte: impl Fn(usize) -> Option<u8>,
         mut rlen: ReadLength,
@@ -415,21 +425,37 @@ impl<'a> I2cController<'a> {
     ) -> Result<(), drv_i2c_api::ResponseCode> {
         // Assert our preconditions as described above
         assert!(wlen > 0 || rlen != ReadLength::Fixed(0));
-        assert!(wlen <= 255);
+
+        //
+        // A PEC byte is sent after the write only if there is no read to
+        // follow it; otherwise, it is sent by the device after the read.
+        //
+        let wpec = pec && rlen == ReadLength::Fixed(0);
+        let rpec = pec && rlen != ReadLength::Fixed(0);
+
+        assert!(wlen + wpec as usize <= 255);
 
         if let ReadLength::Fixed(rlen) = rlen {
-            assert!(rlen <= 255);
+            assert!(rlen + rpec as usize <= 255);
         }
 
         let i2c = self.registers;
         let notification = self.notification;
+        let mut crc = 0;
+        let mut received = 0;
 
         self.wait_until_notbusy()?;
 
         if wlen > 0 {
+            let nbytes = wlen + wpec as usize;
+
+            if pec {
+                crc = pec_update(crc, addr << 1);
+            }
+
             #[rustfmt::skip]
             i2c.cr2.modify(|_, w| { w
-                .nbytes().bits(wlen as u8)
+                .nbytes().bits(nbytes as u8)
                 .autoend().clear_bit()
                 .add10().clear_bit()
                 .sadd().bits((addr << 1).into())
@@ -439,7 +465,7 @@ impl<'a> I2cController<'a> {
 
             let mut pos = 0;
 
-            while pos < wlen {
+            while pos < nbytes {
                 loop {
                     let isr = i2c.isr.read();
                     ringbuf_entry!(Trace::WriteISR(isr.bits()));
@@ -467,9 +493,16 @@ impl<'a> I2cController<'a> {
                     (ctrl.enable)(notification);
                 }
 
-                // Get a single byte.
-                let byte =
-                    getbyte(pos).ok_or(drv_i2c_api::ResponseCode::BadArg)?;
+                // Get a single byte -- or, at the end, our PEC byte.
+                let byte = if pos < wlen {
+                    getbyte(pos).ok_or(drv_i2c_api::ResponseCode::BadArg)?
+                } else {
+                    crc
+                };
+
+                if pec {
+                    crc = pec_update(crc, byte);
+                }
 
                 // And send it!
                 i2c.txdr.write(|w| w.txdata().bits(byte));
@@ -513,10 +546,14 @@ impl<'a> I2cController<'a> {
             // permit a STOP between a register address write and a subsequent
             // read).
             //
+            if rpec {
+                crc = pec_update(crc, (addr << 1) | 1);
+            }
+
             if let ReadLength::Fixed(rlen) = rlen {
                 #[rustfmt::skip]
                 i2c.cr2.modify(|_, w| { w
-                    .nbytes().bits(rlen as u8)
+                    .nbytes().bits((rlen + rpec as usize) as u8)
                     .autoend().clear_bit()
                     .add10().clear_bit()
                     .sadd().bits((addr << 1).into())
@@ -540,7 +577,7 @@ impl<'a> I2cController<'a> {
 
             loop {
                 if let ReadLength::Fixed(rlen) = rlen {
-                    if pos >= rlen {
+                    if pos >= rlen + rpec as usize {
                         break;
                     }
                 }
@@ -576,9 +613,21 @@ impl<'a> I2cController<'a> {
                 let byte: u8 = i2c.rxdr.read().rxdata().bits();
 
                 if rlen == ReadLength::Variable {
+                    //
+                    // The count is covered by the PEC, but doesn't itself
+                    // count the PEC byte that follows the data.
+                    //
+                    let nbytes = if rpec {
+                        crc = pec_update(crc, byte);
+                        byte.checked_add(1)
+                            .ok_or(drv_i2c_api::ResponseCode::BadArg)?
+                    } else {
+                        byte
+                    };
+
                     #[rustfmt::skip]
                     i2c.cr2.modify(|_, w| { w
-                        .nbytes().bits(byte)
+                        .nbytes().bits(nbytes)
                         .reload().clear_bit()
                     });
 
@@ -586,7 +635,20 @@ impl<'a> I2cController<'a> {
                     continue;
                 }
 
-                putbyte(pos, byte).ok_or(drv_i2c_api::ResponseCode::BadArg)?;
+                match rlen {
+                    ReadLength::Fixed(rlen) if pos == rlen => {
+                        received = byte;
+                    }
+                    _ => {
+                        if rpec {
+                            crc = pec_update(crc, byte);
+                        }
+
+                        putbyte(pos, byte)
+                            .ok_or(drv_i2c_api::ResponseCode::BadArg)?;
+                    }
+                }
+
                 pos += 1;
             }
 
@@ -620,6 +682,11 @@ impl<'a> I2cController<'a> {
         //
         i2c.cr2.modify(|_, w| w.stop().set_bit());
 
+        if rpec && received != crc {
+            ringbuf_entry!(Trace::BadPec(crc, received));
+            return Err(drv_i2c_api::ResponseCode::BadChecksum);
+        }
+
         Ok(())
     }
 
@@ -94,6 +94,7 @@ fn read_reg_u8(
 
     match controller.write_read(
         mux.address,
+        false,
         wlen,
         |_| Some(reg),
         ReadLength::Fixed(1),
@@ -117,6 +118,7 @@ fn write_reg_u8(
 ) -> Result<(), ResponseCode> {
     match controller.write_read(
         mux.address,
+        false,
         2,
         |pos| Some(if pos == 0 { reg } else { val }),
         ReadLength::Fixed(0),
@@ -78,6 +78,7 @@ fn read_regs(
 ) -> Result<(), ResponseCode> {
     match controller.write_read(
         mux.address,
+        false,
         0,
         |_| Some(0),
         ReadLength::Fixed(rbuf.len()),
@@ -125,6 +126,7 @@ fn write_reg(
 
     match controller.write_read(
         mux.address,
+        false,
         index + 1,
         |pos| Some(wbuf[pos]),
         ReadLength::Fixed(0),
@@ -76,6 +76,7 @@ impl I2cMuxDriver for Pca9548 {
         //
         match controller.write_read(
             mux.address,
+            false,
             1,
             |_| Some(reg.0),
             ReadLength::Fixed(0),
@@ -0,0 +1,306 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! DDR4 SPD parsing
+//!
+//! This parses the Serial Presence Detect (SPD) contents of a DDR4 module,
+//! per JEDEC Standard No. 21-C, Annex L.  We only parse what is needed to
+//! summarize a module; the SoC itself consumes the rest.  Like `vpd`, this
+//! crate has no dependencies, so that it can be tested on the host.
+
+#![no_std]
+
+const DEVICE_TYPE: usize = 2;
+const DENSITY: usize = 4;
+const PACKAGE_TYPE: usize = 6;
+const ORGANIZATION: usize = 12;
+const BUS_WIDTH: usize = 13;
+const TCK_MIN: usize = 18;
+const TCK_MIN_FINE: usize = 125;
+const BASE_CRC: usize = 126;
+const MANUFACTURER: usize = 320;
+const SERIAL: usize = 325;
+const PART: usize = 329;
+const PART_LEN: usize = 20;
+
+const DEVICE_TYPE_DDR4: u8 = 0x0c;
+
+/// A summary of a module
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Summary {
+    /// Capacity of the module, in MiB
+    pub size: u32,
+
+    /// Maximum data rate, in MT/s
+    pub speed: u16,
+
+    /// JEP-106 module manufacturer:  the number of continuation codes in the
+    /// low byte, and the code itself in the high byte (each with parity)
+    pub manufacturer: u16,
+
+    /// Module serial number
+    pub serial: u32,
+
+    /// Module part number, in ASCII padded with spaces
+    pub part: [u8; PART_LEN],
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The SPD is not that of a DDR4 module
+    UnknownFormat,
+
+    /// The CRC of the base configuration is incorrect
+    BadChecksum,
+
+    /// The SPD is truncated, or has reserved values
+    Malformed,
+}
+
+///
+/// Computes the CRC over the base configuration (bytes 0-125), which is the
+/// CRC-16 with the polynomial 0x1021 and an initial value of 0 (and which
+/// is stored little-endian).
+///
+pub fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0u16;
+
+    for &byte in data {
+        crc ^= (byte as u16) << 8;
+
+        for _ in 0..8 {
+            crc = if crc & 0x8000 != 0 {
+                (crc << 1) ^ 0x1021
+            } else {
+                crc << 1
+            };
+        }
+    }
+
+    crc
+}
+
+/// Returns the capacity of an SDRAM die, in Mib.
+fn die_capacity(density: u8) -> Option<u32> {
+    match density & 0xf {
+        0 => Some(256),
+        1 => Some(512),
+        n @ 2..=7 => Some(1024 << (n - 2)),
+        8 => Some(12 * 1024),
+        9 => Some(24 * 1024),
+        _ => None,
+    }
+}
+
+/// Summarizes a module from its SPD contents.
+pub fn summarize(spd: &[u8]) -> Result<Summary, Error> {
+    if spd.len() < PART + PART_LEN {
+        return Err(Error::Malformed);
+    }
+
+    if spd[DEVICE_TYPE] != DEVICE_TYPE_DDR4 {
+        return Err(Error::UnknownFormat);
+    }
+
+    let crc = u16::from_le_bytes([spd[BASE_CRC], spd[BASE_CRC + 1]]);
+
+    if crc16(&spd[..BASE_CRC]) != crc {
+        return Err(Error::BadChecksum);
+    }
+
+    let die = die_capacity(spd[DENSITY]).ok_or(Error::Malformed)?;
+
+    //
+    // The SDRAM width is 4 << bits 2:0, and the primary bus width 8 << bits
+    // 2:0; anything beyond x32 in either case is reserved.
+    //
+    let width = spd[ORGANIZATION] & 0b111;
+    let bus = spd[BUS_WIDTH] & 0b111;
+
+    if width > 3 || bus > 3 {
+        return Err(Error::Malformed);
+    }
+
+    //
+    // Each package rank holds as many ranks as there are dies in a 3DS
+    // package (signal loading of 0b10); otherwise, it is a single rank.
+    //
+    let package = spd[PACKAGE_TYPE];
+    let prank = ((spd[ORGANIZATION] >> 3) & 0b111) as u32 + 1;
+
+    let ranks = if package & 0b11 == 0b10 {
+        prank * (((package >> 4) & 0b111) as u32 + 1)
+    } else {
+        prank
+    };
+
+    //
+    // Capacity in MiB is the die capacity in Mib over 8, times the number of
+    // dies needed to span the bus, times the number of ranks.
+    //
+    let size = (die / 8) * ((8 << bus) / (4 << width)) * ranks;
+
+    //
+    // The minimum cycle time is in units of the medium timebase (125 ps),
+    // corrected by a signed value in units of the fine timebase (1 ps).
+    //
+    let tck = spd[TCK_MIN] as i32 * 125 + spd[TCK_MIN_FINE] as i8 as i32;
+
+    if tck <= 0 {
+        return Err(Error::Malformed);
+    }
+
+    // Two transfers per cycle, rounded to the nearest MT/s
+    let speed = ((2_000_000 + tck / 2) / tck) as u16;
+
+    let mut part = [0u8; PART_LEN];
+    part.copy_from_slice(&spd[PART..PART + PART_LEN]);
+
+    Ok(Summary {
+        size,
+        speed,
+        manufacturer: u16::from_le_bytes([
+            spd[MANUFACTURER],
+            spd[MANUFACTURER + 1],
+        ]),
+        serial: u32::from_be_bytes([
+            spd[SERIAL],
+            spd[SERIAL + 1],
+            spd[SERIAL + 2],
+            spd[SERIAL + 3],
+        ]),
+        part,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PART_NUMBER: &[u8; PART_LEN] = b"36ASF4G72PZ-3G2E1   ";
+
+    /// Returns the SPD of a 32 GiB, dual-rank x4 DDR4-3200 RDIMM.
+    fn rdimm() -> [u8; 512] {
+        let mut spd = [0u8; 512];
+
+        spd[DEVICE_TYPE] = DEVICE_TYPE_DDR4;
+        spd[DENSITY] = 0x85; // 8 Gib
+        spd[ORGANIZATION] = 0x08; // two package ranks, x4
+        spd[BUS_WIDTH] = 0x0b; // 64 bits, with ECC
+        spd[TCK_MIN] = 0x05; // 625 ps
+        spd[MANUFACTURER..MANUFACTURER + 2].copy_from_slice(&[0x80, 0x2c]);
+        spd[SERIAL..SERIAL + 4].copy_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        spd[PART..PART + PART_LEN].copy_from_slice(PART_NUMBER);
+
+        checksum(&mut spd);
+        spd
+    }
+
+    fn checksum(spd: &mut [u8]) {
+        let crc = crc16(&spd[..BASE_CRC]).to_le_bytes();
+        spd[BASE_CRC..BASE_CRC + 2].copy_from_slice(&crc);
+    }
+
+    #[test]
+    fn crc() {
+        // The check value of CRC-16/XMODEM
+        assert_eq!(crc16(b"123456789"), 0x31c3);
+        assert_eq!(crc16(b""), 0);
+        assert_eq!(crc16(&[0; 126]), 0);
+        assert_eq!(crc16(&[0xff; 2]), 0x1d0f);
+    }
+
+    #[test]
+    fn summary() {
+        assert_eq!(
+            summarize(&rdimm()),
+            Ok(Summary {
+                size: 32768,
+                speed: 3200,
+                manufacturer: 0x2c80,
+                serial: 0x1234_5678,
+                part: *PART_NUMBER,
+            })
+        );
+    }
+
+    #[test]
+    fn organization() {
+        let size = |density, org, package| {
+            let mut spd = rdimm();
+            spd[DENSITY] = density;
+            spd[ORGANIZATION] = org;
+            spd[PACKAGE_TYPE] = package;
+            checksum(&mut spd);
+            summarize(&spd).unwrap().size
+        };
+
+        // Single rank of x8 dies, of 4 Gib and of 16 Gib
+        assert_eq!(size(0x84, 0x01, 0x00), 4096);
+        assert_eq!(size(0x86, 0x01, 0x00), 16384);
+
+        // Two ranks of x16 dies, of 8 Gib
+        assert_eq!(size(0x85, 0x0a, 0x00), 8192);
+
+        // Dies of 12 Gib and 24 Gib
+        assert_eq!(size(0x88, 0x01, 0x00), 12288);
+        assert_eq!(size(0x89, 0x01, 0x00), 24576);
+
+        //
+        // A 3DS package of four dies has four ranks per package rank; other
+        // non-monolithic packages (here, multi-load stacks) don't.
+        //
+        assert_eq!(size(0x86, 0x08, 0xb2), 262144);
+        assert_eq!(size(0x86, 0x08, 0xb1), 65536);
+    }
+
+    #[test]
+    fn speed() {
+        let speed = |mtb, ftb: i8| {
+            let mut spd = rdimm();
+            spd[TCK_MIN] = mtb;
+            spd[TCK_MIN_FINE] = ftb as u8;
+            checksum(&mut spd);
+            summarize(&spd).unwrap().speed
+        };
+
+        assert_eq!(speed(0x06, -0x44), 2933); // 682 ps
+        assert_eq!(speed(0x0a, 0x00), 1600); // 1250 ps
+    }
+
+    #[test]
+    fn errors() {
+        let mut spd = rdimm();
+        spd[DEVICE_TYPE] = 0x0b; // DDR3
+        assert_eq!(summarize(&spd), Err(Error::UnknownFormat));
+
+        // The CRC covers the base configuration, but no further.
+        let mut spd = rdimm();
+        spd[TCK_MIN] ^= 1;
+        assert_eq!(summarize(&spd), Err(Error::BadChecksum));
+
+        let mut spd = rdimm();
+        spd[BASE_CRC + 1] ^= 1;
+        assert_eq!(summarize(&spd), Err(Error::BadChecksum));
+
+        let mut spd = rdimm();
+        spd[SERIAL] ^= 1;
+        assert!(summarize(&spd).is_ok());
+
+        let malformed = |offset, value| {
+            let mut spd = rdimm();
+            spd[offset] = value;
+            checksum(&mut spd);
+            summarize(&spd)
+        };
+
+        assert_eq!(malformed(DENSITY, 0x8a), Err(Error::Malformed));
+        assert_eq!(malformed(ORGANIZATION, 0x04), Err(Error::Malformed));
+        assert_eq!(malformed(BUS_WIDTH, 0x04), Err(Error::Malformed));
+        assert_eq!(malformed(TCK_MIN, 0x00), Err(Error::Malformed));
+
+        assert_eq!(summarize(&rdimm()[..348]), Err(Error::Malformed));
+        assert_eq!(summarize(&[]), Err(Error::Malformed));
+    }
+}
@@ -0,0 +1,463 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//!
+//! A fixed-capacity table of requests awaiting a deferred reply
+//!
+//! Servers that front hardware often want to accept a request, hold on to
+//! the caller, and only reply once the hardware has finished (or failed).
+//! A [`PendingTable`] holds up to `N` such callers, each with an optional
+//! deadline (in kernel timer ticks) and a value of type `T` of the server's
+//! choosing.
+//!
+//! Entries are keyed by the caller's `TaskId`.  Because a task blocked in
+//! `sys_send` cannot send another message, a given task index can have at
+//! most one entry in the table; if a message arrives from a task whose index
+//! is already present, the existing entry must belong to a previous
+//! generation of that task (that is, the task has restarted) and is dropped
+//! without a reply.
+//!
+//! The table will never reply to anyone on its own; methods that remove
+//! entries either hand back the caller or reply with the code you provide.
+//!
+//! The table is generic over its callers (see [`Deferred`]), which
+//! `userlib::hl::Caller` implements with system calls; `userlib::hl`
+//! re-exports the table for those callers.  This crate doesn't itself depend
+//! on `userlib`, so that it can be built and tested on the host.
+//!
+
+#![no_std]
+
+use abi::TaskId;
+
+/// A caller whose reply has been deferred
+pub trait Deferred {
+    /// Returns the `TaskId` of the caller.
+    fn task_id(&self) -> TaskId;
+
+    /// Returns `true` if the caller has since died, i.e. its task has been
+    /// restarted.
+    fn is_dead(&self) -> bool;
+
+    /// Replies to the caller with response code `rc`, consuming it.
+    fn reply_fail(self, rc: u32);
+}
+
+/// An entry in a [`PendingTable`]: a caller that we have accepted a request
+/// from but have not yet replied to, along with some server-specific state.
+pub struct Pending<C, T> {
+    caller: C,
+    deadline: Option<u64>,
+    data: T,
+}
+
+impl<C: Deferred, T> Pending<C, T> {
+    /// Returns the `TaskId` of the waiting caller.
+    pub fn task_id(&self) -> TaskId {
+        self.caller.task_id()
+    }
+
+    /// Returns the deadline for this request, if one was specified.
+    pub fn deadline(&self) -> Option<u64> {
+        self.deadline
+    }
+
+    /// Returns a reference to the server-specific state for this request.
+    pub fn data(&self) -> &T {
+        &self.data
+    }
+
+    /// Returns a mutable reference to the server-specific state for this
+    /// request.
+    pub fn data_mut(&mut self) -> &mut T {
+        &mut self.data
+    }
+
+    /// Consumes the entry, returning the caller and its state.
+    pub fn into_parts(self) -> (C, T) {
+        (self.caller, self.data)
+    }
+}
+
+/// A fixed-capacity table of requests awaiting a deferred reply; see the
+/// crate documentation.
+pub struct PendingTable<C, T, const N: usize> {
+    entries: [Option<Pending<C, T>>; N],
+}
+
+impl<C: Deferred, T, const N: usize> Default for PendingTable<C, T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<C: Deferred, T, const N: usize> PendingTable<C, T, N> {
+    const EMPTY: Option<Pending<C, T>> = None;
+
+    /// Creates an empty table.
+    pub fn new() -> Self {
+        Self {
+            entries: [Self::EMPTY; N],
+        }
+    }
+
+    /// Returns the number of pending requests.
+    pub fn len(&self) -> usize {
+        self.entries.iter().filter(|e| e.is_some()).count()
+    }
+
+    /// Returns `true` if there are no pending requests.
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(|e| e.is_none())
+    }
+
+    /// Returns `true` if there is no room for another request.
+    pub fn is_full(&self) -> bool {
+        self.entries.iter().all(|e| e.is_some())
+    }
+
+    /// Adds a request from `caller` to the table, with server state `data`
+    /// and an optional `deadline` in kernel timer ticks.
+    ///
+    /// Any existing entry for an earlier generation of the same task is
+    /// dropped.  If the table is full, the caller and data are handed back in
+    /// `Err` so that the server can fail the request.
+    pub fn insert(
+        &mut self,
+        caller: C,
+        data: T,
+        deadline: Option<u64>,
+    ) -> Result<(), (C, T)> {
+        let index = caller.task_id().index();
+
+        for e in self.entries.iter_mut() {
+            if let Some(p) = e {
+                if p.caller.task_id().index() == index {
+                    *e = None;
+                }
+            }
+        }
+
+        match self.entries.iter_mut().find(|e| e.is_none()) {
+            Some(slot) => {
+                *slot = Some(Pending {
+                    caller,
+                    deadline,
+                    data,
+                });
+                Ok(())
+            }
+            None => Err((caller, data)),
+        }
+    }
+
+    /// Returns a reference to the pending request from `task`, if any.
+    pub fn get(&self, task: TaskId) -> Option<&Pending<C, T>> {
+        self.entries
+            .iter()
+            .flatten()
+            .find(|p| p.caller.task_id() == task)
+    }
+
+    /// Returns a mutable reference to the pending request from `task`, if
+    /// any.
+    pub fn get_mut(&mut self, task: TaskId) -> Option<&mut Pending<C, T>> {
+        self.entries
+            .iter_mut()
+            .flatten()
+            .find(|p| p.caller.task_id() == task)
+    }
+
+    /// Removes the pending request from `task`, returning it.
+    pub fn remove(&mut self, task: TaskId) -> Option<Pending<C, T>> {
+        self.entries
+            .iter_mut()
+            .find(|e| match e {
+                Some(p) => p.caller.task_id() == task,
+                None => false,
+            })
+            .and_then(|e| e.take())
+    }
+
+    /// Removes and returns the first pending request for which `pred`
+    /// returns `true`, in table order.  This is useful when completions are
+    /// identified by server state rather than by caller.
+    pub fn remove_first(
+        &mut self,
+        mut pred: impl FnMut(&T) -> bool,
+    ) -> Option<Pending<C, T>> {
+        self.entries
+            .iter_mut()
+            .find(|e| match e {
+                Some(p) => pred(&p.data),
+                None => false,
+            })
+            .and_then(|e| e.take())
+    }
+
+    /// Iterates over all pending requests.
+    pub fn iter(&self) -> impl Iterator<Item = &Pending<C, T>> {
+        self.entries.iter().flatten()
+    }
+
+    /// Iterates mutably over all pending requests.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut Pending<C, T>> {
+        self.entries.iter_mut().flatten()
+    }
+
+    /// Returns the earliest deadline of any pending request, suitable for
+    /// passing to `sys_set_timer`.
+    pub fn next_deadline(&self) -> Option<u64> {
+        self.entries
+            .iter()
+            .flatten()
+            .filter_map(|p| p.deadline)
+            .min()
+    }
+
+    /// Fails every pending request whose deadline is at or before `now` with
+    /// response code `rc`, returning the number of requests failed.
+    pub fn expire(&mut self, now: u64, rc: impl Into<u32> + Copy) -> usize {
+        let mut count = 0;
+
+        for e in self.entries.iter_mut() {
+            let expired = match e {
+                Some(Pending {
+                    deadline: Some(deadline),
+                    ..
+                }) => *deadline <= now,
+                _ => false,
+            };
+
+            if expired {
+                e.take().unwrap().caller.reply_fail(rc.into());
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Drops (without replying) every pending request whose caller has since
+    /// died.  Returns the number of requests dropped.
+    ///
+    /// Replying to a dead task is harmless, so calling this is not required
+    /// for correctness -- but it does free up the entry (and whatever
+    /// resources the server associated with it) rather than waiting for the
+    /// request to complete or time out.
+    pub fn reap_dead(&mut self) -> usize {
+        let mut count = 0;
+
+        for e in self.entries.iter_mut() {
+            if matches!(e, Some(p) if p.caller.is_dead()) {
+                *e = None;
+                count += 1;
+            }
+        }
+
+        count
+    }
+
+    /// Fails every pending request with response code `rc`, e.g. because
+    /// the server has had to reset its hardware.  Returns the number of
+    /// requests failed.
+    pub fn fail_all(&mut self, rc: impl Into<u32> + Copy) -> usize {
+        let mut count = 0;
+
+        for e in self.entries.iter_mut() {
+            if let Some(p) = e.take() {
+                p.caller.reply_fail(rc.into());
+                count += 1;
+            }
+        }
+
+        count
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    extern crate std;
+
+    use super::*;
+    use abi::Generation;
+    use core::cell::RefCell;
+    use std::vec::Vec;
+
+    std::thread_local! {
+        /// Replies sent, as (task, response code)
+        static REPLIES: RefCell<Vec<(TaskId, u32)>> = RefCell::default();
+    }
+
+    fn replies() -> Vec<(TaskId, u32)> {
+        REPLIES.with(|r| r.borrow_mut().split_off(0))
+    }
+
+    struct TestCaller {
+        task: TaskId,
+        dead: bool,
+    }
+
+    impl Deferred for TestCaller {
+        fn task_id(&self) -> TaskId {
+            self.task
+        }
+
+        fn is_dead(&self) -> bool {
+            self.dead
+        }
+
+        fn reply_fail(self, rc: u32) {
+            REPLIES.with(|r| r.borrow_mut().push((self.task, rc)));
+        }
+    }
+
+    fn task(index: usize, gen: u8) -> TaskId {
+        TaskId::for_index_and_gen(index, Generation::from(gen))
+    }
+
+    fn caller(index: usize) -> TestCaller {
+        TestCaller {
+            task: task(index, 0),
+            dead: false,
+        }
+    }
+
+    #[test]
+    fn insert_and_remove() {
+        let mut table = PendingTable::<TestCaller, u32, 2>::default();
+        assert!(table.is_empty());
+
+        assert!(table.insert(caller(1), 10, None).is_ok());
+        assert!(table.insert(caller(2), 20, Some(5)).is_ok());
+        assert!(table.is_full());
+        assert_eq!(table.len(), 2);
+
+        // A full table hands back the caller and its state.
+        match table.insert(caller(3), 30, None) {
+            Err((c, data)) => assert_eq!((c.task, data), (task(3, 0), 30)),
+            Ok(_) => panic!("insert into full table succeeded"),
+        }
+
+        assert_eq!(table.get(task(2, 0)).unwrap().data(), &20);
+        assert_eq!(table.get(task(2, 0)).unwrap().deadline(), Some(5));
+        assert!(table.get(task(3, 0)).is_none());
+
+        *table.get_mut(task(1, 0)).unwrap().data_mut() += 1;
+
+        let (c, data) = table.remove(task(1, 0)).unwrap().into_parts();
+        assert_eq!((c.task, data), (task(1, 0), 11));
+        assert!(table.remove(task(1, 0)).is_none());
+        assert_eq!(table.len(), 1);
+        assert!(replies().is_empty());
+    }
+
+    #[test]
+    fn restarted_task() {
+        let mut table = PendingTable::<TestCaller, u32, 2>::new();
+        table.insert(caller(1), 10, None).ok().unwrap();
+
+        //
+        // A request from a later generation of the same task replaces the
+        // earlier one, without a reply to the earlier generation -- even if
+        // the table is otherwise full.
+        //
+        table.insert(caller(2), 20, None).ok().unwrap();
+        let later = TestCaller {
+            task: task(1, 1),
+            dead: false,
+        };
+
+        assert!(table.insert(later, 11, None).is_ok());
+        assert_eq!(table.len(), 2);
+        assert!(table.get(task(1, 0)).is_none());
+        assert_eq!(table.get(task(1, 1)).unwrap().data(), &11);
+        assert!(replies().is_empty());
+    }
+
+    #[test]
+    fn remove_first_in_table_order() {
+        let mut table = PendingTable::<TestCaller, u32, 4>::new();
+
+        for (index, data) in [(1, 7), (2, 8), (3, 7)] {
+            table.insert(caller(index), data, None).ok().unwrap();
+        }
+
+        let first =
+            |p: Option<Pending<TestCaller, u32>>| p.map(|p| p.task_id());
+
+        assert_eq!(first(table.remove_first(|&d| d == 7)), Some(task(1, 0)));
+        assert_eq!(first(table.remove_first(|&d| d == 7)), Some(task(3, 0)));
+        assert_eq!(first(table.remove_first(|&d| d == 7)), None);
+
+        //
+        // A freed slot is reused, so table order is not arrival order.
+        //
+        table.insert(caller(4), 8, None).ok().unwrap();
+        assert_eq!(first(table.remove_first(|&d| d == 8)), Some(task(4, 0)));
+        assert_eq!(first(table.remove_first(|&d| d == 8)), Some(task(2, 0)));
+    }
+
+    #[test]
+    fn deadlines() {
+        let mut table = PendingTable::<TestCaller, u32, 4>::new();
+        assert_eq!(table.next_deadline(), None);
+
+        table.insert(caller(1), 0, Some(300)).ok().unwrap();
+        table.insert(caller(2), 0, None).ok().unwrap();
+        table.insert(caller(3), 0, Some(100)).ok().unwrap();
+        table.insert(caller(4), 0, Some(200)).ok().unwrap();
+        assert_eq!(table.next_deadline(), Some(100));
+
+        // Nothing has expired before the earliest deadline...
+        assert_eq!(table.expire(99, 9u32), 0);
+        assert!(replies().is_empty());
+
+        // ...and a deadline expires at its tick, not after it.
+        assert_eq!(table.expire(200, 9u32), 2);
+        assert_eq!(replies(), [(task(3, 0), 9), (task(4, 0), 9)]);
+        assert_eq!(table.next_deadline(), Some(300));
+
+        // A request without a deadline never expires.
+        assert_eq!(table.expire(u64::MAX, 9u32), 1);
+        assert_eq!(replies(), [(task(1, 0), 9)]);
+        assert_eq!(table.next_deadline(), None);
+        assert_eq!(table.len(), 1);
+    }
+
+    #[test]
+    fn reap_dead() {
+        let mut table = PendingTable::<TestCaller, u32, 4>::new();
+
+        for index in 1..4 {
+            let c = TestCaller {
+                task: task(index, 0),
+                dead: index != 2,
+            };
+
+            table.insert(c, 0, Some(100)).ok().unwrap();
+        }
+
+        // The dead are dropped, without a reply.
+        assert_eq!(table.reap_dead(), 2);
+        assert!(replies().is_empty());
+        assert_eq!(
+            table.iter().map(|p| p.task_id()).collect::<Vec<_>>(),
+            [task(2, 0)]
+        );
+        assert_eq!(table.reap_dead(), 0);
+    }
+
+    #[test]
+    fn fail_all() {
+        let mut table = PendingTable::<TestCaller, u32, 4>::new();
+        table.insert(caller(1), 0, None).ok().unwrap();
+        table.insert(caller(2), 0, Some(100)).ok().unwrap();
+
+        assert_eq!(table.fail_all(3u32), 2);
+        assert_eq!(replies(), [(task(1, 0), 3), (task(2, 0), 3)]);
+        assert!(table.is_empty());
+        assert_eq!(table.fail_all(3u32), 0);
+    }
+}
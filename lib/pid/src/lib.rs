@@ -0,0 +1,411 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Closed-loop control
+//!
+//! This contains a simple PID controller, [`Pid`], along with a [`Ramp`] to
+//! limit the rate at which an output may change, and a [`Controller`] that
+//! combines the two with a fallback output for when the measured input is
+//! unavailable.  These are intended for cooling loops, where the output
+//! (e.g., a fan duty cycle) must rise as the measurement (e.g., a
+//! temperature) exceeds its setpoint.
+//!
+//! This crate has no dependencies, and in particular doesn't depend on
+//! `userlib`, so that it can be built (and exercised) on the host.
+
+#![no_std]
+
+/// Gains and output limits for a [`Pid`].
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct PidConfig {
+    /// Proportional gain
+    pub kp: f32,
+
+    /// Integral gain, per second
+    pub ki: f32,
+
+    /// Derivative gain, in seconds
+    pub kd: f32,
+
+    /// Output limits
+    pub min: f32,
+    pub max: f32,
+}
+
+/// A PID controller.  The error is taken to be the measurement less the
+/// setpoint, so the output rises as the measurement exceeds the setpoint.
+#[derive(Copy, Clone, Debug)]
+pub struct Pid {
+    config: PidConfig,
+
+    /// Accumulated integral term (i.e., already scaled by `ki`)
+    integral: f32,
+
+    /// Previous measurement, if any
+    last: Option<f32>,
+}
+
+/// Clamps `value` to `min..=max`, which (unlike `f32::clamp`) does not panic
+/// if the limits are misordered or NaN.
+fn clamp(value: f32, min: f32, max: f32) -> f32 {
+    value.max(min).min(max)
+}
+
+impl Pid {
+    pub const fn new(config: PidConfig) -> Self {
+        Self {
+            config,
+            integral: 0.0,
+            last: None,
+        }
+    }
+
+    pub fn config(&self) -> &PidConfig {
+        &self.config
+    }
+
+    /// Changes the gains and limits.  The accumulated integral term is
+    /// retained, so this doesn't cause a step in the output.
+    pub fn set_config(&mut self, config: PidConfig) {
+        self.config = config;
+    }
+
+    /// Discards all accumulated state.
+    pub fn reset(&mut self) {
+        self.integral = 0.0;
+        self.last = None;
+    }
+
+    /// Returns the output for `measurement` against `setpoint`, `dt` seconds
+    /// after the previous step.
+    pub fn step(&mut self, setpoint: f32, measurement: f32, dt: f32) -> f32 {
+        let config = &self.config;
+        let error = measurement - setpoint;
+        let p = config.kp * error;
+
+        //
+        // The derivative is taken on the measurement rather than the error,
+        // so that a change in setpoint doesn't kick the output.
+        //
+        let d = match self.last {
+            Some(last) if dt > 0.0 => config.kd * (measurement - last) / dt,
+            _ => 0.0,
+        };
+
+        self.last = Some(measurement);
+
+        //
+        // To avoid winding up the integral term while the output is
+        // saturated, we integrate no further than would bring the output to
+        // its limit in the direction of the error (but don't unwind what has
+        // already been accumulated).
+        //
+        let integral = self.integral + config.ki * error * dt;
+
+        self.integral = if error > 0.0 {
+            integral.min((config.max - p - d).max(self.integral))
+        } else if error < 0.0 {
+            integral.max((config.min - p - d).min(self.integral))
+        } else {
+            integral
+        };
+
+        clamp(p + self.integral + d, config.min, config.max)
+    }
+}
+
+/// Limits on the rate of change of an output, in units per second.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Ramp {
+    pub up: f32,
+    pub down: f32,
+}
+
+impl Ramp {
+    /// Returns `to`, limited to the change permitted from `from` over `dt`
+    /// seconds.
+    pub fn limit(&self, from: f32, to: f32, dt: f32) -> f32 {
+        clamp(to, from - self.down * dt, from + self.up * dt)
+    }
+}
+
+/// A [`Pid`] driving an output toward a setpoint, subject to a [`Ramp`].
+/// When the measurement is unavailable, the output immediately assumes the
+/// fallback value.
+#[derive(Copy, Clone, Debug)]
+pub struct Controller {
+    pub setpoint: f32,
+    pid: Pid,
+    ramp: Ramp,
+    fallback: f32,
+
+    /// Previous output, if any
+    output: Option<f32>,
+}
+
+impl Controller {
+    pub const fn new(
+        setpoint: f32,
+        pid: PidConfig,
+        ramp: Ramp,
+        fallback: f32,
+    ) -> Self {
+        Self {
+            setpoint,
+            pid: Pid::new(pid),
+            ramp,
+            fallback,
+            output: None,
+        }
+    }
+
+    pub fn pid(&mut self) -> &mut Pid {
+        &mut self.pid
+    }
+
+    /// Returns the most recent output, if any.
+    pub fn output(&self) -> Option<f32> {
+        self.output
+    }
+
+    /// Discards all accumulated state, as when resuming control after the
+    /// output has been driven by some other means.
+    pub fn reset(&mut self) {
+        self.pid.reset();
+        self.output = None;
+    }
+
+    /// Returns the output for `measurement` (or for its absence), `dt`
+    /// seconds after the previous update.
+    pub fn update(&mut self, measurement: Option<f32>, dt: f32) -> f32 {
+        let output = match (measurement, self.output) {
+            (Some(m), Some(last)) => {
+                let target = self.pid.step(self.setpoint, m, dt);
+                self.ramp.limit(last, target, dt)
+            }
+            (Some(m), None) => self.pid.step(self.setpoint, m, dt),
+            (None, _) => {
+                //
+                // We don't ramp toward the fallback:  the ramp is there to
+                // keep the output from hunting, not to delay a response to
+                // a loss of input.
+                //
+                self.pid.reset();
+                self.fallback
+            }
+        };
+
+        self.output = Some(output);
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LIMITS: PidConfig = PidConfig {
+        kp: 0.0,
+        ki: 0.0,
+        kd: 0.0,
+        min: 0.0,
+        max: 100.0,
+    };
+
+    #[test]
+    fn proportional() {
+        let mut pid = Pid::new(PidConfig { kp: 2.0, ..LIMITS });
+
+        assert_eq!(pid.step(50.0, 60.0, 1.0), 20.0);
+        assert_eq!(pid.step(50.0, 55.0, 1.0), 10.0);
+
+        // The output is clamped to its limits in either direction.
+        assert_eq!(pid.step(50.0, 40.0, 1.0), 0.0);
+        assert_eq!(pid.step(50.0, 200.0, 1.0), 100.0);
+    }
+
+    #[test]
+    fn integral() {
+        let mut pid = Pid::new(PidConfig { ki: 0.5, ..LIMITS });
+
+        assert_eq!(pid.step(50.0, 60.0, 1.0), 5.0);
+        assert_eq!(pid.step(50.0, 60.0, 2.0), 15.0);
+        assert_eq!(pid.step(50.0, 50.0, 1.0), 15.0);
+        assert_eq!(pid.step(50.0, 46.0, 1.0), 13.0);
+
+        pid.reset();
+        assert_eq!(pid.step(50.0, 50.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn derivative() {
+        let mut pid = Pid::new(PidConfig {
+            kd: 2.0,
+            min: -100.0,
+            ..LIMITS
+        });
+
+        // There is no derivative term until there is a previous measurement.
+        assert_eq!(pid.step(50.0, 60.0, 1.0), 0.0);
+        assert_eq!(pid.step(50.0, 63.0, 1.0), 6.0);
+        assert_eq!(pid.step(50.0, 61.0, 0.5), -8.0);
+
+        // A change in setpoint doesn't kick the output...
+        assert_eq!(pid.step(30.0, 61.0, 1.0), 0.0);
+
+        // ...and a zero interval doesn't divide by zero.
+        assert_eq!(pid.step(30.0, 70.0, 0.0), 0.0);
+    }
+
+    #[test]
+    fn anti_windup_at_max() {
+        let mut pid = Pid::new(PidConfig { ki: 1.0, ..LIMITS });
+
+        for _ in 0..10 {
+            pid.step(50.0, 80.0, 1.0);
+        }
+
+        // The integral stopped accumulating at the limit...
+        assert_eq!(pid.step(50.0, 80.0, 1.0), 100.0);
+
+        // ...so the output comes off of the limit as soon as the error
+        // changes sign, rather than after unwinding 200 units.
+        assert_eq!(pid.step(50.0, 45.0, 1.0), 95.0);
+    }
+
+    #[test]
+    fn anti_windup_keeps_integral() {
+        let mut pid = Pid::new(PidConfig {
+            kp: 10.0,
+            ki: 1.0,
+            ..LIMITS
+        });
+
+        assert_eq!(pid.step(50.0, 55.0, 1.0), 55.0);
+
+        // With the proportional term alone saturating the output, the
+        // integral neither grows nor unwinds...
+        assert_eq!(pid.step(50.0, 70.0, 1.0), 100.0);
+        assert_eq!(pid.step(50.0, 70.0, 1.0), 100.0);
+
+        // ...so it is as it was once the error subsides.
+        assert_eq!(pid.step(50.0, 50.0, 1.0), 5.0);
+    }
+
+    #[test]
+    fn anti_windup_at_min() {
+        let mut pid = Pid::new(PidConfig {
+            kp: 1.0,
+            ki: 1.0,
+            min: 20.0,
+            ..LIMITS
+        });
+
+        for _ in 0..10 {
+            assert_eq!(pid.step(50.0, 40.0, 1.0), 20.0);
+        }
+
+        assert_eq!(pid.step(50.0, 55.0, 1.0), 20.0);
+        assert_eq!(pid.step(50.0, 70.0, 1.0), 45.0);
+    }
+
+    #[test]
+    fn set_config_keeps_integral() {
+        let mut pid = Pid::new(PidConfig { ki: 1.0, ..LIMITS });
+
+        assert_eq!(pid.step(50.0, 60.0, 1.0), 10.0);
+
+        pid.set_config(PidConfig { kp: 1.0, ..LIMITS });
+        assert_eq!(pid.config().kp, 1.0);
+        assert_eq!(pid.step(50.0, 50.0, 1.0), 10.0);
+    }
+
+    #[test]
+    fn misordered_limits() {
+        let mut pid = Pid::new(PidConfig {
+            kp: 1.0,
+            min: 100.0,
+            max: 0.0,
+            ..LIMITS
+        });
+
+        assert_eq!(pid.step(50.0, 60.0, 1.0), 0.0);
+    }
+
+    #[test]
+    fn ramp() {
+        let ramp = Ramp {
+            up: 10.0,
+            down: 2.0,
+        };
+
+        assert_eq!(ramp.limit(50.0, 100.0, 1.0), 60.0);
+        assert_eq!(ramp.limit(50.0, 100.0, 0.5), 55.0);
+        assert_eq!(ramp.limit(50.0, 53.0, 1.0), 53.0);
+        assert_eq!(ramp.limit(50.0, 0.0, 1.0), 48.0);
+        assert_eq!(ramp.limit(50.0, 0.0, 3.0), 44.0);
+        assert_eq!(ramp.limit(50.0, 49.0, 1.0), 49.0);
+        assert_eq!(ramp.limit(50.0, 50.0, 0.0), 50.0);
+    }
+
+    fn controller() -> Controller {
+        Controller::new(
+            50.0,
+            PidConfig {
+                kp: 5.0,
+                min: 20.0,
+                ..LIMITS
+            },
+            Ramp {
+                up: 10.0,
+                down: 2.0,
+            },
+            100.0,
+        )
+    }
+
+    #[test]
+    fn controller_ramps() {
+        let mut c = controller();
+        assert_eq!(c.output(), None);
+
+        // The first output isn't ramped...
+        assert_eq!(c.update(Some(60.0), 1.0), 50.0);
+
+        // ...but subsequent outputs are, in either direction.
+        assert_eq!(c.update(Some(70.0), 1.0), 60.0);
+        assert_eq!(c.update(Some(50.0), 1.0), 58.0);
+        assert_eq!(c.update(Some(50.0), 1.0), 56.0);
+        assert_eq!(c.output(), Some(56.0));
+    }
+
+    #[test]
+    fn controller_fallback() {
+        let mut c = controller();
+
+        assert_eq!(c.update(Some(52.0), 1.0), 20.0);
+
+        // The fallback is assumed immediately, without ramping...
+        assert_eq!(c.update(None, 1.0), 100.0);
+        assert_eq!(c.output(), Some(100.0));
+
+        // ...and the output ramps down from it once the input returns.
+        assert_eq!(c.update(Some(52.0), 1.0), 98.0);
+    }
+
+    #[test]
+    fn controller_reset() {
+        let mut c = controller();
+
+        assert_eq!(c.update(Some(60.0), 1.0), 50.0);
+        c.reset();
+        assert_eq!(c.output(), None);
+
+        // Following a reset, the output isn't ramped.
+        assert_eq!(c.update(Some(52.0), 1.0), 20.0);
+
+        c.setpoint = 40.0;
+        assert_eq!(c.update(Some(52.0), 1.0), 30.0);
+    }
+}
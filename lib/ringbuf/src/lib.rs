@@ -140,6 +140,7 @@
 
 /// Re-export the bits we use from `userlib` so that code generated by the
 /// macros is guaranteed to be able to find them.
+#[cfg(target_os = "none")]
 pub use userlib::util::StaticCell;
 
 /// Declares a ringbuffer in the current module or context.
@@ -156,7 +157,7 @@ pub use userlib::util::StaticCell;
 ///
 /// To support the common case of having one quickly-installed ringbuffer per
 /// module, if you omit the name, it will default to `__RINGBUF`.
-#[cfg(not(feature = "disabled"))]
+#[cfg(all(target_os = "none", not(feature = "disabled")))]
 #[macro_export]
 macro_rules! ringbuf {
     ($name:ident, $t:ty, $n:expr, $init:expr) => {
@@ -177,7 +178,7 @@ macro_rules! ringbuf {
     };
 }
 
-#[cfg(feature = "disabled")]
+#[cfg(any(not(target_os = "none"), feature = "disabled"))]
 #[macro_export]
 macro_rules! ringbuf {
     ($name:ident, $t:ty, $n:expr, $init:expr) => {
@@ -198,7 +199,7 @@ macro_rules! ringbuf {
 ///
 /// If you declared your ringbuffer without a name, you can also use this
 /// without a name, and it will default to `__RINGBUF`.
-#[cfg(not(feature = "disabled"))]
+#[cfg(all(target_os = "none", not(feature = "disabled")))]
 #[macro_export]
 macro_rules! ringbuf_entry {
     ($buf:expr, $payload:expr) => {{
@@ -220,7 +221,7 @@ macro_rules! ringbuf_entry {
     };
 }
 
-#[cfg(feature = "disabled")]
+#[cfg(any(not(target_os = "none"), feature = "disabled"))]
 #[macro_export]
 macro_rules! ringbuf_entry {
     ($buf:expr, $payload:expr) => {{
@@ -0,0 +1,518 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//!
+//! Tuple structs for units that are useful in the real world
+//!
+//! The floating point types support arithmetic with other values of the same
+//! unit and scaling by a bare `f32`; where multiplying or dividing two units
+//! yields a third (e.g., `Volts * Amperes = Watts`), that is implemented as
+//! well.
+//!
+//! Floating point is expensive on targets without an FPU (e.g., armv6m), and
+//! `f32` is a poor choice for a value that is going to be sent over IPC.  For
+//! these cases, there are fixed-point integer variants in thousandths of each
+//! unit (e.g., [`MilliVolts`]) that convert to and from their floating point
+//! counterparts, and which can be multiplied without resorting to floating
+//! point.
+//!
+//! This crate doesn't depend on `userlib` (which re-exports it as
+//! `userlib::units`), so that it can be built and tested on the host.
+//!
+
+#![no_std]
+
+use core::convert::TryFrom;
+use core::ops::{Add, Div, Mul, Neg, Sub};
+use zerocopy::{AsBytes, FromBytes};
+
+/// Degrees Celsius
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Celsius(pub f32);
+
+/// Degrees Fahrenheit
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Fahrenheit(pub f32);
+
+/// Kelvin
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Kelvin(pub f32);
+
+/// Rotations per minute
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct Rpm(pub u16);
+
+/// Volts of potential
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Volts(pub f32);
+
+/// Amperes of current
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Amperes(pub f32);
+
+/// Ohms of resistence
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Ohms(pub f32);
+
+/// Watts of power
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Watts(pub f32);
+
+/// Joules of energy
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Joules(pub f32);
+
+/// Seconds of time
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Seconds(pub f32);
+
+/// A percentage, where 100.0 is the whole
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct Percent(pub f32);
+
+/// Duty cycle of a PWM signal, as a percentage in the range 0..=100
+#[derive(Copy, Clone, PartialEq, PartialOrd, Debug)]
+pub struct DutyCycle(u8);
+
+//
+// Arithmetic between values of the same floating point unit, and scaling
+// by a bare f32.
+//
+macro_rules! float_unit_arithmetic {
+    ($($unit:ident),*) => {
+        $(
+            impl Add for $unit {
+                type Output = Self;
+                fn add(self, rhs: Self) -> Self {
+                    Self(self.0 + rhs.0)
+                }
+            }
+
+            impl Sub for $unit {
+                type Output = Self;
+                fn sub(self, rhs: Self) -> Self {
+                    Self(self.0 - rhs.0)
+                }
+            }
+
+            impl Neg for $unit {
+                type Output = Self;
+                fn neg(self) -> Self {
+                    Self(-self.0)
+                }
+            }
+
+            impl Mul<f32> for $unit {
+                type Output = Self;
+                fn mul(self, rhs: f32) -> Self {
+                    Self(self.0 * rhs)
+                }
+            }
+
+            impl Div<f32> for $unit {
+                type Output = Self;
+                fn div(self, rhs: f32) -> Self {
+                    Self(self.0 / rhs)
+                }
+            }
+        )*
+    };
+}
+
+float_unit_arithmetic!(
+    Celsius, Fahrenheit, Kelvin, Volts, Amperes, Ohms, Watts, Joules, Seconds,
+    Percent
+);
+
+//
+// Products and quotients of different units:  for each `a * b = c`, this
+// implements `a * b`, `b * a`, `c / a` and `c / b`.
+//
+macro_rules! unit_product {
+    ($a:ident * $b:ident = $c:ident) => {
+        impl Mul<$b> for $a {
+            type Output = $c;
+            fn mul(self, rhs: $b) -> $c {
+                $c(self.0 * rhs.0)
+            }
+        }
+
+        impl Mul<$a> for $b {
+            type Output = $c;
+            fn mul(self, rhs: $a) -> $c {
+                $c(self.0 * rhs.0)
+            }
+        }
+
+        impl Div<$a> for $c {
+            type Output = $b;
+            fn div(self, rhs: $a) -> $b {
+                $b(self.0 / rhs.0)
+            }
+        }
+
+        impl Div<$b> for $c {
+            type Output = $a;
+            fn div(self, rhs: $b) -> $a {
+                $a(self.0 / rhs.0)
+            }
+        }
+    };
+}
+
+unit_product!(Volts * Amperes = Watts);
+unit_product!(Amperes * Ohms = Volts);
+unit_product!(Watts * Seconds = Joules);
+
+impl From<Celsius> for Fahrenheit {
+    fn from(c: Celsius) -> Self {
+        Fahrenheit(c.0 * 9.0 / 5.0 + 32.0)
+    }
+}
+
+impl From<Fahrenheit> for Celsius {
+    fn from(f: Fahrenheit) -> Self {
+        Celsius((f.0 - 32.0) * 5.0 / 9.0)
+    }
+}
+
+impl From<Celsius> for Kelvin {
+    fn from(c: Celsius) -> Self {
+        Kelvin(c.0 + 273.15)
+    }
+}
+
+impl From<Kelvin> for Celsius {
+    fn from(k: Kelvin) -> Self {
+        Celsius(k.0 - 273.15)
+    }
+}
+
+impl Percent {
+    /// Returns this percentage as a fraction of the whole (e.g., 50% is 0.5)
+    pub fn fraction(self) -> f32 {
+        self.0 / 100.0
+    }
+
+    /// Returns the percentage that `part` is of `whole`
+    pub fn of(part: f32, whole: f32) -> Self {
+        Percent(part / whole * 100.0)
+    }
+}
+
+impl DutyCycle {
+    pub const MAX: Self = DutyCycle(100);
+
+    /// Creates a duty cycle from a percentage, returning `None` if it is
+    /// greater than 100%.
+    pub fn new(percent: u8) -> Option<Self> {
+        if percent <= Self::MAX.0 {
+            Some(DutyCycle(percent))
+        } else {
+            None
+        }
+    }
+
+    /// Creates a duty cycle from a percentage, clamping it at 100%.
+    pub fn saturating(percent: u8) -> Self {
+        DutyCycle(percent.min(Self::MAX.0))
+    }
+
+    pub fn percent(self) -> u8 {
+        self.0
+    }
+
+    /// Scales this duty cycle into the range `0..=max`, as is often required
+    /// when programming a PWM controller.
+    pub fn scale(self, max: u16) -> u16 {
+        ((u32::from(self.0) * u32::from(max) + 50) / 100) as u16
+    }
+}
+
+impl From<Percent> for DutyCycle {
+    /// Converts a percentage to a duty cycle, rounding to the nearest whole
+    /// percent and clamping to the range 0..=100.
+    fn from(p: Percent) -> Self {
+        if p.0 > 0.0 {
+            DutyCycle::saturating((p.0 + 0.5) as u8)
+        } else {
+            DutyCycle(0)
+        }
+    }
+}
+
+impl From<DutyCycle> for Percent {
+    fn from(d: DutyCycle) -> Self {
+        Percent(f32::from(d.0))
+    }
+}
+
+/// Rounds `value * 1000` to the nearest integer.  (The conversion with `as`
+/// saturates at the bounds of the integer type.)
+fn to_milli(value: f32) -> i64 {
+    let scaled = value * 1000.0;
+
+    if scaled < 0.0 {
+        (scaled - 0.5) as i64
+    } else {
+        (scaled + 0.5) as i64
+    }
+}
+
+//
+// Fixed-point variants, in thousandths of the corresponding floating point
+// unit.  These are laid out for IPC, and convert to and from their floating
+// point counterparts.
+//
+macro_rules! fixed_unit {
+    ($(#[$attr:meta])* $fixed:ident($repr:ty) <=> $float:ident) => {
+        $(#[$attr])*
+        #[derive(
+            Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Debug, AsBytes,
+            FromBytes,
+        )]
+        #[repr(transparent)]
+        pub struct $fixed(pub $repr);
+
+        impl From<$float> for $fixed {
+            fn from(value: $float) -> Self {
+                let milli = to_milli(value.0);
+
+                if milli > <$repr>::MAX as i64 {
+                    $fixed(<$repr>::MAX)
+                } else if milli < <$repr>::MIN as i64 {
+                    $fixed(<$repr>::MIN)
+                } else {
+                    $fixed(milli as $repr)
+                }
+            }
+        }
+
+        impl From<$fixed> for $float {
+            fn from(value: $fixed) -> Self {
+                $float(value.0 as f32 / 1000.0)
+            }
+        }
+
+        impl Add for $fixed {
+            type Output = Self;
+            fn add(self, rhs: Self) -> Self {
+                Self(self.0.saturating_add(rhs.0))
+            }
+        }
+
+        impl Sub for $fixed {
+            type Output = Self;
+            fn sub(self, rhs: Self) -> Self {
+                Self(self.0.saturating_sub(rhs.0))
+            }
+        }
+    };
+}
+
+fixed_unit!(
+    /// Thousandths of a degree Celsius
+    MilliCelsius(i32) <=> Celsius
+);
+
+fixed_unit!(
+    /// Millivolts of potential
+    MilliVolts(i32) <=> Volts
+);
+
+fixed_unit!(
+    /// Milliamperes of current
+    MilliAmperes(i32) <=> Amperes
+);
+
+fixed_unit!(
+    /// Milliwatts of power
+    MilliWatts(i32) <=> Watts
+);
+
+fixed_unit!(
+    /// Millijoules of energy.  This is 64 bits wide, as energy is generally
+    /// accumulated:  a 32-bit value would overflow after less than an hour
+    /// at 1 kW.
+    MilliJoules(i64) <=> Joules
+);
+
+impl Mul<MilliAmperes> for MilliVolts {
+    type Output = MilliWatts;
+    fn mul(self, rhs: MilliAmperes) -> MilliWatts {
+        let uw = i64::from(self.0) * i64::from(rhs.0);
+        MilliWatts((uw / 1000).clamp(i32::MIN.into(), i32::MAX.into()) as i32)
+    }
+}
+
+impl Mul<MilliVolts> for MilliAmperes {
+    type Output = MilliWatts;
+    fn mul(self, rhs: MilliVolts) -> MilliWatts {
+        rhs * self
+    }
+}
+
+impl MilliWatts {
+    /// Returns the energy consumed at this power over `ms` milliseconds --
+    /// e.g., the number of timer ticks between two power readings.
+    pub fn over_millis(self, ms: u64) -> MilliJoules {
+        let ms = i64::try_from(ms).unwrap_or(i64::MAX);
+        MilliJoules(i64::from(self.0).saturating_mul(ms) / 1000)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn close(a: f32, b: f32) -> bool {
+        (a - b).abs() < 1e-3
+    }
+
+    #[test]
+    fn temperature_conversions() {
+        assert!(close(Fahrenheit::from(Celsius(100.0)).0, 212.0));
+        assert!(close(Fahrenheit::from(Celsius(-40.0)).0, -40.0));
+        assert!(close(Celsius::from(Fahrenheit(32.0)).0, 0.0));
+        assert!(close(Kelvin::from(Celsius(0.0)).0, 273.15));
+        assert!(close(Celsius::from(Kelvin(0.0)).0, -273.15));
+
+        let c = Celsius(37.5);
+        assert!(close(Celsius::from(Fahrenheit::from(c)).0, c.0));
+        assert!(close(Celsius::from(Kelvin::from(c)).0, c.0));
+    }
+
+    #[test]
+    fn same_unit_arithmetic() {
+        assert_eq!(Volts(1.5) + Volts(2.0), Volts(3.5));
+        assert_eq!(Volts(1.5) - Volts(2.0), Volts(-0.5));
+        assert_eq!(-Amperes(3.0), Amperes(-3.0));
+        assert_eq!(Watts(10.0) * 0.5, Watts(5.0));
+        assert_eq!(Joules(10.0) / 4.0, Joules(2.5));
+    }
+
+    #[test]
+    fn unit_products() {
+        assert_eq!(Volts(12.0) * Amperes(2.5), Watts(30.0));
+        assert_eq!(Amperes(2.5) * Volts(12.0), Watts(30.0));
+        assert_eq!(Watts(30.0) / Volts(12.0), Amperes(2.5));
+        assert_eq!(Watts(30.0) / Amperes(2.5), Volts(12.0));
+
+        assert_eq!(Amperes(2.0) * Ohms(6.0), Volts(12.0));
+        assert_eq!(Volts(12.0) / Ohms(6.0), Amperes(2.0));
+
+        assert_eq!(Watts(30.0) * Seconds(2.0), Joules(60.0));
+        assert_eq!(Seconds(2.0) * Watts(30.0), Joules(60.0));
+        assert_eq!(Joules(60.0) / Seconds(2.0), Watts(30.0));
+        assert_eq!(Joules(60.0) / Watts(30.0), Seconds(2.0));
+    }
+
+    #[test]
+    fn percent_and_duty_cycle() {
+        assert_eq!(Percent(50.0).fraction(), 0.5);
+        assert_eq!(Percent::of(1.0, 4.0), Percent(25.0));
+
+        assert_eq!(DutyCycle::new(100), Some(DutyCycle::MAX));
+        assert_eq!(DutyCycle::new(101), None);
+        assert_eq!(DutyCycle::saturating(255), DutyCycle::MAX);
+
+        assert_eq!(DutyCycle::from(Percent(49.4)).percent(), 49);
+        assert_eq!(DutyCycle::from(Percent(49.5)).percent(), 50);
+        assert_eq!(DutyCycle::from(Percent(250.0)), DutyCycle::MAX);
+        assert_eq!(DutyCycle::from(Percent(-10.0)).percent(), 0);
+        assert_eq!(Percent::from(DutyCycle::MAX), Percent(100.0));
+
+        assert_eq!(DutyCycle::MAX.scale(255), 255);
+        assert_eq!(DutyCycle::saturating(50).scale(255), 128);
+        assert_eq!(DutyCycle::saturating(0).scale(255), 0);
+    }
+
+    #[test]
+    fn fixed_point_conversions() {
+        assert_eq!(MilliVolts::from(Volts(1.2)), MilliVolts(1200));
+        assert_eq!(MilliVolts::from(Volts(0.0004)), MilliVolts(0));
+        assert_eq!(MilliVolts::from(Volts(0.0005)), MilliVolts(1));
+        assert_eq!(MilliAmperes::from(Amperes(-0.0015)), MilliAmperes(-2));
+        assert_eq!(MilliCelsius::from(Celsius(-40.25)), MilliCelsius(-40250));
+        assert_eq!(Volts::from(MilliVolts(1800)), Volts(1.8));
+        assert_eq!(Joules::from(MilliJoules(-500)), Joules(-0.5));
+    }
+
+    #[test]
+    fn to_milli_saturates() {
+        assert_eq!(MilliVolts::from(Volts(3e6)), MilliVolts(i32::MAX));
+        assert_eq!(MilliVolts::from(Volts(-3e6)), MilliVolts(i32::MIN));
+        assert_eq!(MilliWatts::from(Watts(f32::INFINITY)).0, i32::MAX);
+        assert_eq!(MilliWatts::from(Watts(f32::NEG_INFINITY)).0, i32::MIN);
+        assert_eq!(MilliWatts::from(Watts(f32::NAN)), MilliWatts(0));
+
+        assert_eq!(MilliJoules::from(Joules(1e30)), MilliJoules(i64::MAX));
+        assert_eq!(MilliJoules::from(Joules(-1e30)), MilliJoules(i64::MIN));
+    }
+
+    #[test]
+    fn fixed_point_arithmetic() {
+        assert_eq!(MilliVolts(1200) + MilliVolts(600), MilliVolts(1800));
+        assert_eq!(MilliVolts(1200) - MilliVolts(1800), MilliVolts(-600));
+        assert_eq!(MilliVolts(i32::MAX) + MilliVolts(1), MilliVolts(i32::MAX));
+        assert_eq!(MilliVolts(i32::MIN) - MilliVolts(1), MilliVolts(i32::MIN));
+        assert_eq!(
+            MilliJoules(i64::MAX) + MilliJoules(1),
+            MilliJoules(i64::MAX)
+        );
+    }
+
+    #[test]
+    fn fixed_point_products() {
+        assert_eq!(
+            MilliVolts(12_000) * MilliAmperes(2_500),
+            MilliWatts(30_000)
+        );
+        assert_eq!(
+            MilliAmperes(2_500) * MilliVolts(12_000),
+            MilliWatts(30_000)
+        );
+        assert_eq!(
+            MilliVolts(-1_000) * MilliAmperes(1_500),
+            MilliWatts(-1_500)
+        );
+
+        // Truncates toward zero below a milliwatt...
+        assert_eq!(MilliVolts(1) * MilliAmperes(999), MilliWatts(0));
+
+        // ...and saturates at the bounds of a MilliWatts.
+        assert_eq!(
+            MilliVolts(i32::MAX) * MilliAmperes(i32::MAX),
+            MilliWatts(i32::MAX)
+        );
+        assert_eq!(
+            MilliVolts(i32::MAX) * MilliAmperes(i32::MIN),
+            MilliWatts(i32::MIN)
+        );
+    }
+
+    #[test]
+    fn over_millis() {
+        assert_eq!(MilliWatts(30_000).over_millis(1000), MilliJoules(30_000));
+        assert_eq!(MilliWatts(1_500).over_millis(500), MilliJoules(750));
+        assert_eq!(MilliWatts(-2_000).over_millis(250), MilliJoules(-500));
+        assert_eq!(MilliWatts(0).over_millis(u64::MAX), MilliJoules(0));
+
+        // A tick count beyond i64 saturates rather than wrapping negative...
+        assert_eq!(
+            MilliWatts(1).over_millis(u64::MAX),
+            MilliJoules(i64::MAX / 1000)
+        );
+
+        // ...as does the product itself.
+        assert_eq!(
+            MilliWatts(i32::MAX).over_millis(u64::MAX),
+            MilliJoules(i64::MAX / 1000)
+        );
+        assert_eq!(
+            MilliWatts(i32::MIN).over_millis(u64::MAX),
+            MilliJoules(i64::MIN / 1000)
+        );
+    }
+}
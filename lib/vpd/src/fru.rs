@@ -0,0 +1,461 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! IPMI FRU information
+//!
+//! FRU information begins with an eight-byte common header, which contains
+//! the offsets (in multiples of eight bytes) of each of its areas.  We read
+//! the board info area and the product info area, each of which is a
+//! sequence of fields, each prefixed by a type/length byte; the sequence is
+//! terminated by a type/length byte of `0xc1`.  The header and each area are
+//! checksummed such that their bytes sum to zero.
+
+use crate::{copy_out, Error, Field};
+
+const HEADER_LEN: usize = 8;
+const FORMAT_VERSION: u8 = 0x01;
+
+/// Offsets within the common header of the offsets of each area
+const BOARD_AREA: usize = 3;
+const PRODUCT_AREA: usize = 4;
+
+/// Type/length byte that terminates the fields of an area
+const END_OF_FIELDS: u8 = 0xc1;
+
+/// Offset of the first field of the board info area:  its version, length,
+/// language code and (three bytes of) manufacturing date precede it.
+const BOARD_FIELDS: usize = 6;
+
+/// Offset of the first field of the product info area:  its version, length
+/// and language code precede it.
+const PRODUCT_FIELDS: usize = 3;
+
+/// Indices of the fields of the board info area
+mod board {
+    pub const MANUFACTURER: usize = 0;
+    pub const PRODUCT: usize = 1;
+    pub const SERIAL_NUMBER: usize = 2;
+    pub const PART_NUMBER: usize = 3;
+}
+
+/// Indices of the fields of the product info area
+mod product {
+    pub const MANUFACTURER: usize = 0;
+    pub const PRODUCT: usize = 1;
+    pub const PART_NUMBER: usize = 2;
+    pub const VERSION: usize = 3;
+    pub const SERIAL_NUMBER: usize = 4;
+}
+
+fn checksum_ok(data: &[u8]) -> bool {
+    data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b)) == 0
+}
+
+/// Returns true if `data` begins with a valid common header.
+pub fn is_fru(data: &[u8]) -> bool {
+    data.len() >= HEADER_LEN
+        && data[0] & 0x0f == FORMAT_VERSION
+        && checksum_ok(&data[..HEADER_LEN])
+}
+
+/// The encodings of a field, as given by the top two bits of its type/length
+/// byte
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Encoding {
+    Binary,
+    BcdPlus,
+    SixBitAscii,
+    Text,
+}
+
+/// A field, as encoded
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct EncodedField<'a> {
+    pub encoding: Encoding,
+    pub bytes: &'a [u8],
+}
+
+impl EncodedField<'_> {
+    ///
+    /// Decodes the field, writing as much of it as fits into `out`, and
+    /// returns its decoded length.
+    ///
+    pub fn decode(&self, out: &mut [u8]) -> usize {
+        match self.encoding {
+            Encoding::Binary | Encoding::Text => copy_out(self.bytes, out),
+            Encoding::BcdPlus => {
+                //
+                // Two characters per byte, most significant nibble first.
+                //
+                const BCD_PLUS: &[u8; 16] = b"0123456789 -.???";
+                let len = self.bytes.len() * 2;
+
+                for (i, o) in out.iter_mut().take(len).enumerate() {
+                    let byte = self.bytes[i / 2];
+                    let nibble = if i % 2 == 0 { byte >> 4 } else { byte };
+                    *o = BCD_PLUS[(nibble & 0xf) as usize];
+                }
+
+                len
+            }
+            Encoding::SixBitAscii => {
+                //
+                // Four characters in every three bytes, least significant
+                // bits first; each character is offset from 0x20.
+                //
+                let len = self.bytes.len() * 8 / 6;
+
+                for (i, o) in out.iter_mut().take(len).enumerate() {
+                    let bit = i * 6;
+                    let lo = u16::from(self.bytes[bit / 8]);
+                    let hi = self
+                        .bytes
+                        .get(bit / 8 + 1)
+                        .map_or(0, |&b| u16::from(b));
+                    let word = (hi << 8 | lo) >> (bit % 8);
+                    *o = 0x20 + (word & 0x3f) as u8;
+                }
+
+                len
+            }
+        }
+    }
+}
+
+///
+/// Returns the area whose offset is at `which` in the common header, or
+/// `None` if it is absent.
+///
+fn area(data: &[u8], which: usize) -> Result<Option<&[u8]>, Error> {
+    let offset = data[which] as usize * 8;
+
+    if offset == 0 {
+        return Ok(None);
+    }
+
+    let len = data.get(offset + 1).ok_or(Error::Malformed)?;
+    let area = data
+        .get(offset..offset + *len as usize * 8)
+        .ok_or(Error::Malformed)?;
+
+    match area.first() {
+        Some(version) if version & 0x0f == FORMAT_VERSION => {}
+        _ => return Err(Error::Malformed),
+    }
+
+    if !checksum_ok(area) {
+        return Err(Error::BadChecksum);
+    }
+
+    Ok(Some(area))
+}
+
+///
+/// Returns the `index`th field of `area`, whose fields begin at `start`, or
+/// `None` if there are fewer fields (or the field is empty).
+///
+fn field(
+    area: &[u8],
+    start: usize,
+    index: usize,
+) -> Result<Option<EncodedField<'_>>, Error> {
+    let mut pos = start;
+
+    for i in 0.. {
+        let tl = *area.get(pos).ok_or(Error::Malformed)?;
+
+        if tl == END_OF_FIELDS {
+            break;
+        }
+
+        let len = (tl & 0x3f) as usize;
+        let bytes = area.get(pos + 1..pos + 1 + len).ok_or(Error::Malformed)?;
+
+        if i == index {
+            if len == 0 {
+                break;
+            }
+
+            let encoding = match tl >> 6 {
+                0b00 => Encoding::Binary,
+                0b01 => Encoding::BcdPlus,
+                0b10 => Encoding::SixBitAscii,
+                _ => Encoding::Text,
+            };
+
+            return Ok(Some(EncodedField { encoding, bytes }));
+        }
+
+        pos += 1 + len;
+    }
+
+    Ok(None)
+}
+
+///
+/// Finds `field` in FRU information `data`, writing as much of it as fits
+/// into `out`, and returns its length.  We look first in the board info area
+/// and then, for fields that it lacks, in the product info area.
+///
+pub fn read_field(
+    data: &[u8],
+    field: Field,
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    if !is_fru(data) {
+        return Err(Error::UnknownFormat);
+    }
+
+    let board = match field {
+        Field::Manufacturer => Some(board::MANUFACTURER),
+        Field::Product => Some(board::PRODUCT),
+        Field::PartNumber => Some(board::PART_NUMBER),
+        Field::SerialNumber => Some(board::SERIAL_NUMBER),
+        Field::Revision => None,
+    };
+
+    if let (Some(index), Some(area)) = (board, area(data, BOARD_AREA)?) {
+        if let Some(f) = self::field(area, BOARD_FIELDS, index)? {
+            return Ok(f.decode(out));
+        }
+    }
+
+    let index = match field {
+        Field::Manufacturer => product::MANUFACTURER,
+        Field::Product => product::PRODUCT,
+        Field::PartNumber => product::PART_NUMBER,
+        Field::Revision => product::VERSION,
+        Field::SerialNumber => product::SERIAL_NUMBER,
+    };
+
+    if let Some(area) = area(data, PRODUCT_AREA)? {
+        if let Some(f) = self::field(area, PRODUCT_FIELDS, index)? {
+            return Ok(f.decode(out));
+        }
+    }
+
+    Err(Error::NoField)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    fn text(s: &[u8]) -> (u8, &[u8]) {
+        (0xc0 | s.len() as u8, s)
+    }
+
+    /// Builds an area from its preamble and fields, padded and checksummed.
+    fn area(preamble: &[u8], fields: &[(u8, &[u8])]) -> Vec<u8> {
+        let mut a = vec![FORMAT_VERSION, 0];
+        a.extend_from_slice(preamble);
+
+        for (tl, bytes) in fields {
+            a.push(*tl);
+            a.extend_from_slice(bytes);
+        }
+
+        a.push(END_OF_FIELDS);
+
+        while (a.len() + 1) % 8 != 0 {
+            a.push(0);
+        }
+
+        a[1] = ((a.len() + 1) / 8) as u8;
+        let sum = a.iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        a.push(0u8.wrapping_sub(sum));
+        a
+    }
+
+    fn board(fields: &[(u8, &[u8])]) -> Vec<u8> {
+        area(&[0, 0x10, 0x20, 0x30], fields)
+    }
+
+    fn product(fields: &[(u8, &[u8])]) -> Vec<u8> {
+        area(&[0], fields)
+    }
+
+    /// Builds FRU information from its (optional) board and product areas.
+    fn fru(board: Option<&[u8]>, product: Option<&[u8]>) -> Vec<u8> {
+        let mut data = vec![FORMAT_VERSION, 0, 0, 0, 0, 0, 0, 0];
+        let mut offset = 1;
+
+        for (which, area) in [(BOARD_AREA, board), (PRODUCT_AREA, product)] {
+            if let Some(area) = area {
+                data[which] = offset as u8;
+                data.extend_from_slice(area);
+                offset += area.len() / 8;
+            }
+        }
+
+        let sum = data[..7].iter().fold(0u8, |sum, &b| sum.wrapping_add(b));
+        data[7] = 0u8.wrapping_sub(sum);
+        data
+    }
+
+    fn read(data: &[u8], field: Field) -> Result<Vec<u8>, Error> {
+        let mut out = [0u8; 64];
+        let len = read_field(data, field, &mut out)?;
+        Ok(out[..len].to_vec())
+    }
+
+    #[test]
+    fn header() {
+        let data = fru(None, None);
+        assert!(is_fru(&data));
+        assert!(!is_fru(&data[..7]));
+        assert!(!is_fru(&[0xff; 8]));
+        assert!(!is_fru(&[0; 8]));
+
+        let mut bad = data.clone();
+        bad[7] ^= 1;
+        assert!(!is_fru(&bad));
+        assert_eq!(read(&bad, Field::Product), Err(Error::UnknownFormat));
+        assert_eq!(read(&data, Field::Product), Err(Error::NoField));
+    }
+
+    #[test]
+    fn board_and_product() {
+        let b = board(&[
+            text(b"Oxide"),
+            text(b"Gimlet"),
+            text(b"BRM42220036"),
+            text(b"913-0000019"),
+        ]);
+        let p = product(&[
+            text(b"Oxide Computer"),
+            text(b"Gimlet SP"),
+            text(b"999-0000001"),
+            text(b"B.0"),
+            text(b"SN1"),
+        ]);
+
+        let data = fru(Some(&b), Some(&p));
+
+        //
+        // The board info area is preferred; the revision is found only in
+        // the product info area.
+        //
+        assert_eq!(read(&data, Field::Manufacturer).unwrap(), b"Oxide");
+        assert_eq!(read(&data, Field::Product).unwrap(), b"Gimlet");
+        assert_eq!(read(&data, Field::SerialNumber).unwrap(), b"BRM42220036");
+        assert_eq!(read(&data, Field::PartNumber).unwrap(), b"913-0000019");
+        assert_eq!(read(&data, Field::Revision).unwrap(), b"B.0");
+
+        let data = fru(None, Some(&p));
+        assert_eq!(
+            read(&data, Field::Manufacturer).unwrap(),
+            b"Oxide Computer"
+        );
+        assert_eq!(read(&data, Field::PartNumber).unwrap(), b"999-0000001");
+        assert_eq!(read(&data, Field::SerialNumber).unwrap(), b"SN1");
+
+        let data = fru(Some(&b), None);
+        assert_eq!(read(&data, Field::Revision), Err(Error::NoField));
+    }
+
+    #[test]
+    fn missing_fields() {
+        //
+        // An empty field in the board info area, or one beyond its last,
+        // is looked for in the product info area.
+        //
+        let b = board(&[text(b"Oxide"), (0xc0, &[])]);
+        let p = product(&[text(b""), text(b"Gimlet SP"), text(b"999-0000001")]);
+
+        let data = fru(Some(&b), Some(&p));
+        assert_eq!(read(&data, Field::Product).unwrap(), b"Gimlet SP");
+        assert_eq!(read(&data, Field::PartNumber).unwrap(), b"999-0000001");
+        assert_eq!(read(&data, Field::SerialNumber), Err(Error::NoField));
+        assert_eq!(read(&data, Field::Revision), Err(Error::NoField));
+    }
+
+    #[test]
+    fn encodings() {
+        let b = board(&[
+            (0x03, &[0xde, 0xad, 0xbe]),
+            (0x43, &[0x12, 0xb3, 0xac]),
+            (0x83, &[0x29, 0xdc, 0xa6]),
+        ]);
+        let data = fru(Some(&b), None);
+
+        assert_eq!(
+            read(&data, Field::Manufacturer).unwrap(),
+            [0xde, 0xad, 0xbe]
+        );
+        assert_eq!(read(&data, Field::Product).unwrap(), b"12-3 .");
+
+        // This is the example of 6-bit ASCII in the specification.
+        assert_eq!(read(&data, Field::SerialNumber).unwrap(), b"IPMI");
+    }
+
+    #[test]
+    fn decode_short() {
+        let mut out = [0u8; 3];
+
+        let f = EncodedField {
+            encoding: Encoding::BcdPlus,
+            bytes: &[0x12, 0x34],
+        };
+        assert_eq!(f.decode(&mut out), 4);
+        assert_eq!(&out, b"123");
+
+        let f = EncodedField {
+            encoding: Encoding::SixBitAscii,
+            bytes: &[0x29, 0xdc, 0xa6],
+        };
+        assert_eq!(f.decode(&mut out), 4);
+        assert_eq!(&out, b"IPM");
+
+        let f = EncodedField {
+            encoding: Encoding::Text,
+            bytes: b"Oxide",
+        };
+        assert_eq!(f.decode(&mut out), 5);
+        assert_eq!(&out, b"Oxi");
+    }
+
+    #[test]
+    fn bad_area_checksum() {
+        let mut b = board(&[text(b"Oxide")]);
+        b[6] ^= 1;
+
+        let data = fru(Some(&b), None);
+        assert_eq!(read(&data, Field::Manufacturer), Err(Error::BadChecksum));
+    }
+
+    #[test]
+    fn malformed() {
+        let b = board(&[text(b"Oxide")]);
+        let data = fru(Some(&b), None);
+
+        // The area runs past the end of the data.
+        let truncated = &data[..data.len() - 1];
+        assert_eq!(read(truncated, Field::Manufacturer), Err(Error::Malformed));
+
+        // The area's offset is past the end of the data.
+        let data = fru(None, None);
+        let mut header = data.clone();
+        header[BOARD_AREA] = 4;
+        header[7] = header[7].wrapping_sub(4);
+        assert_eq!(read(&header, Field::Manufacturer), Err(Error::Malformed));
+
+        // The area has the wrong version.
+        let mut b = board(&[text(b"Oxide")]);
+        b[0] = 0x02;
+        let last = b.len() - 1;
+        b[last] = b[last].wrapping_sub(1);
+        let data = fru(Some(&b), None);
+        assert_eq!(read(&data, Field::Manufacturer), Err(Error::Malformed));
+
+        // A field runs past the end of the area, which has no terminator.
+        let mut b = board(&[(0xc2, b"Ox")]);
+        let n = b.len();
+        b[BOARD_FIELDS] = 0xc0 | 0x3f;
+        b[n - 1] = b[n - 1].wrapping_sub(0x3d);
+        let data = fru(Some(&b), None);
+        assert_eq!(read(&data, Field::Product), Err(Error::Malformed));
+    }
+}
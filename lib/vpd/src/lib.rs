@@ -0,0 +1,110 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Vital product data
+//!
+//! This contains parsers for the two formats in which we expect to find a
+//! board's vital product data (VPD) -- its identity, and the MAC addresses
+//! allocated to it -- in an EEPROM:
+//!
+//! - [`fru`]: the FRU information format of the IPMI Platform Management
+//!   FRU Information Storage Definition
+//! - [`tlv`]: a tag-length-value format, in which a `FRU0` chunk contains
+//!   a barcode and a MAC address block
+//!
+//! [`read_field`] and [`read_mac_block`] determine the format of the data,
+//! and extract from it.  Like `pid`, this crate has no dependencies and does
+//! not allocate, so that it can be used both on the SP and on the host.
+
+#![no_std]
+
+#[cfg(test)]
+#[macro_use]
+extern crate std;
+
+pub mod fru;
+pub mod tlv;
+
+/// A field of a board's identity
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Field {
+    Manufacturer,
+    Product,
+    PartNumber,
+    Revision,
+    SerialNumber,
+}
+
+/// A block of MAC addresses:  `count` addresses from `base`, each `stride`
+/// from the last.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct MacBlock {
+    pub base: [u8; 6],
+    pub count: u16,
+    pub stride: u8,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Error {
+    /// The data is in neither format (e.g., the EEPROM is blank)
+    UnknownFormat,
+
+    /// A checksum is incorrect
+    BadChecksum,
+
+    /// The data is truncated, or otherwise malformed
+    Malformed,
+
+    /// The data does not contain the requested field
+    NoField,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum Format {
+    Fru,
+    Tlv,
+}
+
+fn format(data: &[u8]) -> Result<Format, Error> {
+    if tlv::is_tlv(data) {
+        Ok(Format::Tlv)
+    } else if fru::is_fru(data) {
+        Ok(Format::Fru)
+    } else {
+        Err(Error::UnknownFormat)
+    }
+}
+
+///
+/// Finds `field` in `data`, writing as much of it as fits into `out`, and
+/// returns its length.
+///
+pub fn read_field(
+    data: &[u8],
+    field: Field,
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    match format(data)? {
+        Format::Fru => fru::read_field(data, field, out),
+        Format::Tlv => tlv::read_field(data, field, out),
+    }
+}
+
+/// Finds the MAC address block in `data`.
+pub fn read_mac_block(data: &[u8]) -> Result<MacBlock, Error> {
+    match format(data)? {
+        //
+        // The FRU format has no standard record for MAC addresses.
+        //
+        Format::Fru => Err(Error::NoField),
+        Format::Tlv => tlv::read_mac_block(data),
+    }
+}
+
+/// Copies as much of `src` as fits into `out`, returning the length of `src`.
+fn copy_out(src: &[u8], out: &mut [u8]) -> usize {
+    let n = src.len().min(out.len());
+    out[..n].copy_from_slice(&src[..n]);
+    src.len()
+}
@@ -0,0 +1,367 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Tag-length-value VPD
+//!
+//! In this format, data is a sequence of chunks.  Each chunk has a
+//! twelve-byte header -- a four-byte tag, the length of its body as a
+//! little-endian `u32`, and a checksum of the tag and length -- followed by
+//! its body, padded with zeroes to a multiple of four bytes, and a checksum
+//! of the (unpadded) body.  Checksums are little-endian CRC-32Cs.
+//!
+//! The VPD of a board is a `FRU0` chunk, whose body is a sequence of chunks
+//! including:
+//!
+//! - `BARC`:  the board's barcode, of the form
+//!   `0XV1:<part number>:<revision>:<serial number>`
+//! - `MAC0`:  its block of MAC addresses, as the base address, the number of
+//!   addresses (as a little-endian `u16`) and the stride between them
+
+use crate::{copy_out, Error, Field, MacBlock};
+
+const HEADER_LEN: usize = 12;
+
+pub const VPD_TAG: [u8; 4] = *b"FRU0";
+pub const BARCODE_TAG: [u8; 4] = *b"BARC";
+pub const MAC_TAG: [u8; 4] = *b"MAC0";
+
+/// Prefix of the version of barcode that we understand
+const BARCODE_PREFIX: &[u8] = b"0XV1";
+
+/// Computes the CRC-32C (Castagnoli) of `data`.
+pub fn crc32c(data: &[u8]) -> u32 {
+    let mut crc = !0u32;
+
+    for &byte in data {
+        crc ^= u32::from(byte);
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0x82f6_3b78
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
+
+fn read_u32(data: &[u8]) -> u32 {
+    u32::from_le_bytes([data[0], data[1], data[2], data[3]])
+}
+
+/// A chunk, with its checksums verified
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Chunk<'a> {
+    pub tag: [u8; 4],
+    pub body: &'a [u8],
+}
+
+/// An iterator over the chunks in a sequence
+pub struct Chunks<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Chunks<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data }
+    }
+
+    fn chunk(&mut self) -> Result<Chunk<'a>, Error> {
+        let header = self.data.get(..HEADER_LEN).ok_or(Error::Malformed)?;
+
+        if crc32c(&header[..8]) != read_u32(&header[8..]) {
+            return Err(Error::BadChecksum);
+        }
+
+        let mut tag = [0u8; 4];
+        tag.copy_from_slice(&header[..4]);
+
+        let len = read_u32(&header[4..]) as usize;
+
+        if len > self.data.len() {
+            return Err(Error::Malformed);
+        }
+
+        let padded = (len + 3) & !3;
+
+        let body = self
+            .data
+            .get(HEADER_LEN..HEADER_LEN + len)
+            .ok_or(Error::Malformed)?;
+        let checksum = self
+            .data
+            .get(HEADER_LEN + padded..HEADER_LEN + padded + 4)
+            .ok_or(Error::Malformed)?;
+
+        if crc32c(body) != read_u32(checksum) {
+            return Err(Error::BadChecksum);
+        }
+
+        self.data = &self.data[HEADER_LEN + padded + 4..];
+        Ok(Chunk { tag, body })
+    }
+}
+
+impl<'a> Iterator for Chunks<'a> {
+    type Item = Result<Chunk<'a>, Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let chunk = self.chunk();
+
+        //
+        // If a chunk is bad, we can't find the next one; stop here.
+        //
+        if chunk.is_err() {
+            self.data = &[];
+        }
+
+        Some(chunk)
+    }
+}
+
+/// Returns the body of the first chunk in `data` tagged `tag`, if any.
+pub fn find<'a>(
+    data: &'a [u8],
+    tag: &[u8; 4],
+) -> Result<Option<&'a [u8]>, Error> {
+    for chunk in Chunks::new(data) {
+        let chunk = chunk?;
+
+        if chunk.tag == *tag {
+            return Ok(Some(chunk.body));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Returns true if `data` begins with a VPD chunk.
+pub fn is_tlv(data: &[u8]) -> bool {
+    data.get(..4) == Some(&VPD_TAG[..])
+}
+
+/// Returns the body of the VPD chunk at the start of `data`.
+fn vpd(data: &[u8]) -> Result<&[u8], Error> {
+    match Chunks::new(data).next() {
+        Some(Ok(chunk)) if chunk.tag == VPD_TAG => Ok(chunk.body),
+        Some(Err(err)) => Err(err),
+        _ => Err(Error::UnknownFormat),
+    }
+}
+
+///
+/// Finds `field` in TLV `data`, writing as much of it as fits into `out`, and
+/// returns its length.  The part number, revision and serial number are
+/// found in the barcode; there is no manufacturer or product name.
+///
+pub fn read_field(
+    data: &[u8],
+    field: Field,
+    out: &mut [u8],
+) -> Result<usize, Error> {
+    let index = match field {
+        Field::PartNumber => 1,
+        Field::Revision => 2,
+        Field::SerialNumber => 3,
+        Field::Manufacturer | Field::Product => return Err(Error::NoField),
+    };
+
+    let barcode = find(vpd(data)?, &BARCODE_TAG)?.ok_or(Error::NoField)?;
+    let mut parts = barcode.split(|&b| b == b':');
+
+    if parts.next() != Some(BARCODE_PREFIX) {
+        return Err(Error::Malformed);
+    }
+
+    match parts.nth(index - 1) {
+        Some(part) => Ok(copy_out(part, out)),
+        None => Err(Error::Malformed),
+    }
+}
+
+/// Finds the MAC address block in TLV `data`.
+pub fn read_mac_block(data: &[u8]) -> Result<MacBlock, Error> {
+    let body = find(vpd(data)?, &MAC_TAG)?.ok_or(Error::NoField)?;
+
+    if body.len() < 9 {
+        return Err(Error::Malformed);
+    }
+
+    let mut base = [0u8; 6];
+    base.copy_from_slice(&body[..6]);
+
+    Ok(MacBlock {
+        base,
+        count: u16::from_le_bytes([body[6], body[7]]),
+        stride: body[8],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::vec::Vec;
+
+    /// Builds a chunk, with its padding and checksums.
+    fn chunk(tag: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut c = tag.to_vec();
+        c.extend_from_slice(&(body.len() as u32).to_le_bytes());
+        c.extend_from_slice(&crc32c(&c).to_le_bytes());
+        c.extend_from_slice(body);
+
+        c.resize((c.len() + 3) & !3, 0);
+
+        c.extend_from_slice(&crc32c(body).to_le_bytes());
+        c
+    }
+
+    fn vpd(chunks: &[Vec<u8>]) -> Vec<u8> {
+        chunk(&VPD_TAG, &chunks.concat())
+    }
+
+    const BARCODE: &[u8] = b"0XV1:913-0000019:006:BRM42220036";
+    const MAC: [u8; 9] = [0xa8, 0x40, 0x25, 0x00, 0x00, 0x01, 0x08, 0x00, 0x01];
+
+    fn read(data: &[u8], field: Field) -> Result<Vec<u8>, Error> {
+        let mut out = [0u8; 64];
+        let len = read_field(data, field, &mut out)?;
+        Ok(out[..len].to_vec())
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32c(b""), 0);
+        assert_eq!(crc32c(b"123456789"), 0xe306_9283);
+        assert_eq!(crc32c(&[0; 32]), 0x8a91_36aa);
+        assert_eq!(crc32c(&[0xff; 32]), 0x62a8_ab43);
+    }
+
+    #[test]
+    fn chunks() {
+        let data = [chunk(b"ABCD", b"x"), chunk(b"EFGH", b"12345678")].concat();
+        let mut chunks = Chunks::new(&data);
+
+        assert_eq!(
+            chunks.next(),
+            Some(Ok(Chunk {
+                tag: *b"ABCD",
+                body: b"x"
+            }))
+        );
+        assert_eq!(
+            chunks.next(),
+            Some(Ok(Chunk {
+                tag: *b"EFGH",
+                body: b"12345678"
+            }))
+        );
+        assert_eq!(chunks.next(), None);
+
+        assert_eq!(find(&data, b"EFGH"), Ok(Some(&b"12345678"[..])));
+        assert_eq!(find(&data, b"IJKL"), Ok(None));
+    }
+
+    #[test]
+    fn fields() {
+        let data = vpd(&[chunk(&BARCODE_TAG, BARCODE), chunk(&MAC_TAG, &MAC)]);
+
+        assert!(is_tlv(&data));
+        assert_eq!(read(&data, Field::PartNumber).unwrap(), b"913-0000019");
+        assert_eq!(read(&data, Field::Revision).unwrap(), b"006");
+        assert_eq!(read(&data, Field::SerialNumber).unwrap(), b"BRM42220036");
+        assert_eq!(read(&data, Field::Manufacturer), Err(Error::NoField));
+        assert_eq!(read(&data, Field::Product), Err(Error::NoField));
+
+        assert_eq!(
+            read_mac_block(&data),
+            Ok(MacBlock {
+                base: [0xa8, 0x40, 0x25, 0x00, 0x00, 0x01],
+                count: 8,
+                stride: 1,
+            })
+        );
+
+        // The format is detected by the crate's entry points.
+        let mut out = [0u8; 3];
+        assert_eq!(crate::read_field(&data, Field::Revision, &mut out), Ok(3));
+        assert_eq!(&out, b"006");
+        assert!(crate::read_mac_block(&data).is_ok());
+        assert_eq!(
+            crate::read_field(&[0xff; 64], Field::Revision, &mut out),
+            Err(Error::UnknownFormat)
+        );
+    }
+
+    #[test]
+    fn missing() {
+        let data = vpd(&[]);
+        assert_eq!(read(&data, Field::SerialNumber), Err(Error::NoField));
+        assert_eq!(read_mac_block(&data), Err(Error::NoField));
+    }
+
+    #[test]
+    fn bad_checksums() {
+        let data = vpd(&[chunk(&BARCODE_TAG, BARCODE)]);
+
+        // The header's checksum covers the tag and length...
+        let mut bad = data.clone();
+        bad[4] ^= 1;
+        assert_eq!(read(&bad, Field::Revision), Err(Error::BadChecksum));
+
+        let mut bad = data.clone();
+        bad[8] ^= 1;
+        assert_eq!(read(&bad, Field::Revision), Err(Error::BadChecksum));
+
+        // ...and the body's covers the body.
+        let mut bad = data.clone();
+        bad[HEADER_LEN + HEADER_LEN + 6] ^= 1;
+        assert_eq!(read(&bad, Field::Revision), Err(Error::BadChecksum));
+
+        // A bad chunk ends iteration.
+        let data = [chunk(b"ABCD", b"x"), chunk(b"EFGH", b"y")].concat();
+        let mut bad = data.clone();
+        bad[HEADER_LEN] ^= 1;
+        let mut chunks = Chunks::new(&bad);
+        assert_eq!(chunks.next(), Some(Err(Error::BadChecksum)));
+        assert_eq!(chunks.next(), None);
+    }
+
+    #[test]
+    fn malformed() {
+        let data = vpd(&[chunk(&BARCODE_TAG, BARCODE), chunk(&MAC_TAG, &MAC)]);
+
+        // Truncated anywhere, the data is malformed.
+        for len in 4..data.len() {
+            assert_eq!(
+                read(&data[..len], Field::Revision),
+                Err(Error::Malformed),
+                "truncated to {}",
+                len
+            );
+        }
+
+        // A length that exceeds the data is malformed.
+        let mut c = chunk(b"ABCD", b"");
+        c[4..8].copy_from_slice(&0xffff_fff0u32.to_le_bytes());
+        let crc = crc32c(&c[..8]);
+        c[8..12].copy_from_slice(&crc.to_le_bytes());
+        assert_eq!(Chunks::new(&c).next(), Some(Err(Error::Malformed)));
+
+        // So is a barcode of another version, or one with too few parts.
+        for barcode in [&b"0XV2:913-0000019:006:BRM42220036"[..], b"0XV1:913"] {
+            let data = vpd(&[chunk(&BARCODE_TAG, barcode)]);
+            assert_eq!(read(&data, Field::Revision), Err(Error::Malformed));
+        }
+
+        // So is a short MAC address block.
+        let data = vpd(&[chunk(&MAC_TAG, &MAC[..8])]);
+        assert_eq!(read_mac_block(&data), Err(Error::Malformed));
+    }
+}
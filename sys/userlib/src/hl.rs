@@ -14,8 +14,8 @@ use zerocopy::{AsBytes, FromBytes, LayoutVerified};
 
 use crate::{
     sys_borrow_info, sys_borrow_read, sys_borrow_write, sys_get_timer,
-    sys_recv, sys_recv_closed, sys_recv_open, sys_reply, sys_send,
-    sys_set_timer, BorrowInfo, ClosedRecvError, FromPrimitive,
+    sys_recv, sys_recv_closed, sys_recv_open, sys_refresh_task_id, sys_reply,
+    sys_send, sys_set_timer, BorrowInfo, ClosedRecvError, FromPrimitive,
 };
 
 const INTERNAL_TIMER_NOTIFICATION: u32 = 1 << 31;
@@ -635,3 +635,26 @@ pub fn sleep_until(time: u64) {
 pub fn sleep_for(ticks: u64) {
     sleep_until(sys_get_timer().now + ticks)
 }
+
+/// `Caller`s can be held in a [`PendingTable`] for a deferred reply.
+impl<R> pending::Deferred for Caller<R> {
+    fn task_id(&self) -> TaskId {
+        self.id
+    }
+
+    fn is_dead(&self) -> bool {
+        sys_refresh_task_id(self.id).generation() != self.id.generation()
+    }
+
+    fn reply_fail(self, rc: u32) {
+        sys_reply(self.id, rc, &[]);
+    }
+}
+
+/// An entry in a [`PendingTable`].
+pub type Pending<R, T> = pending::Pending<Caller<R>, T>;
+
+/// A fixed-capacity table of requests awaiting a deferred reply; see the
+/// `pending` crate.
+pub type PendingTable<R, T, const N: usize> =
+    pending::PendingTable<Caller<R>, T, N>;
@@ -40,9 +40,10 @@ use core::marker::PhantomData;
 pub mod hl;
 pub mod kipc;
 pub mod task_slot;
-pub mod units;
 pub mod util;
 
+pub use units;
+
 #[derive(Debug)]
 #[repr(transparent)]
 pub struct Lease<'a> {
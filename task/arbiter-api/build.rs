@@ -0,0 +1,58 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct Config {
+    arbiter: Option<ArbiterConfig>,
+}
+
+#[derive(Deserialize)]
+struct ArbiterConfig {
+    resources: Vec<String>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    idol::client::build_client_stub(
+        "../../idl/arbiter.idol",
+        "client_stub.rs",
+    )?;
+
+    //
+    // Resources are named in the app-wide configuration; clients refer to
+    // them via the constants that we generate here.  An application that
+    // doesn't configure an arbiter simply gets no constants.
+    //
+    let resources = match build_util::config::<Config>() {
+        Ok(Config {
+            arbiter: Some(arbiter),
+        }) => arbiter.resources,
+        _ => vec![],
+    };
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("resources.rs");
+    let mut file = File::create(&dest_path)?;
+
+    writeln!(file, "pub mod resources {{")?;
+    writeln!(file, "    use super::Resource;")?;
+
+    for (index, name) in resources.iter().enumerate() {
+        writeln!(
+            file,
+            "    pub const {}: Resource = Resource({});",
+            name.to_uppercase(),
+            index
+        )?;
+    }
+
+    writeln!(file, "}}")?;
+
+    Ok(())
+}
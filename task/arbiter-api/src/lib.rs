@@ -0,0 +1,117 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the Arbiter task.
+//!
+//! The arbiter hands out exclusive ownership of named resources (configured
+//! as `resources` in the `[config.arbiter]` section of the application
+//! TOML).  Constants for each configured resource are found in
+//! [`resources`].
+
+#![no_std]
+
+use userlib::*;
+
+#[derive(zerocopy::AsBytes, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Resource(pub u8);
+
+impl From<u8> for Resource {
+    fn from(index: u8) -> Self {
+        Resource(index)
+    }
+}
+
+impl From<Resource> for u8 {
+    fn from(resource: Resource) -> Self {
+        resource.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum ArbiterError {
+    /// Resource index is out of range
+    BadResource = 1,
+
+    /// Resource is held by another task
+    Held = 2,
+
+    /// Timed out waiting for the resource
+    Timeout = 3,
+
+    /// Release of a resource that the caller doesn't hold
+    NotHolder = 4,
+
+    /// Resource is not held by anyone
+    NotHeld = 5,
+
+    /// Too many tasks are already waiting on resources
+    TooManyWaiters = 6,
+
+    /// Malformed request
+    BadArg = 7,
+}
+
+impl From<ArbiterError> for u16 {
+    fn from(rc: ArbiterError) -> Self {
+        rc as u16
+    }
+}
+
+impl From<ArbiterError> for u32 {
+    fn from(rc: ArbiterError) -> Self {
+        rc as u32
+    }
+}
+
+impl core::convert::TryFrom<u32> for ArbiterError {
+    type Error = ();
+    fn try_from(rc: u32) -> Result<Self, Self::Error> {
+        Self::from_u32(rc).ok_or(())
+    }
+}
+
+impl Arbiter {
+    /// Returns the current holder of `resource`, or `None` if it is free.
+    pub fn current_holder(
+        &self,
+        resource: Resource,
+    ) -> Result<Option<TaskId>, ArbiterError> {
+        match self.holder(resource) {
+            Ok(id) => Ok(Some(TaskId(id))),
+            Err(ArbiterError::NotHeld) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Variant of `acquire` that returns a guard object that, when dropped,
+    /// will issue `release`.
+    pub fn acquire_auto(
+        &self,
+        resource: Resource,
+    ) -> Result<ResourceLock, ArbiterError> {
+        self.acquire(resource)?;
+        Ok(ResourceLock {
+            arbiter: self,
+            resource,
+        })
+    }
+}
+
+/// A held resource that is released on drop; see [`Arbiter::acquire_auto`].
+pub struct ResourceLock<'a> {
+    arbiter: &'a Arbiter,
+    resource: Resource,
+}
+
+impl Drop for ResourceLock<'_> {
+    fn drop(&mut self) {
+        // We ignore the result of release because, if the server has
+        // restarted, we don't need to do anything.
+        let _ = self.arbiter.release(self.resource);
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/resources.rs"));
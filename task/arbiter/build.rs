@@ -0,0 +1,58 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct Config {
+    arbiter: ArbiterConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ArbiterConfig {
+    /// names of the resources that we arbitrate
+    resources: Vec<String>,
+
+    /// maximum number of tasks that can be blocked waiting for a resource
+    #[serde(default = "default_waiters")]
+    waiters: usize,
+}
+
+fn default_waiters() -> usize {
+    4
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let config = build_util::config::<Config>()?.arbiter;
+
+    if config.resources.is_empty() {
+        return Err("arbiter configured without any resources".into());
+    }
+
+    if config.resources.len() > usize::from(u8::MAX) {
+        return Err("too many arbiter resources".into());
+    }
+
+    if config.waiters == 0 {
+        return Err("arbiter must allow at least one waiter".into());
+    }
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("arbiter_config.rs");
+    let mut file = File::create(&dest_path)?;
+
+    writeln!(
+        file,
+        "pub const NUM_RESOURCES: usize = {};",
+        config.resources.len()
+    )?;
+    writeln!(file, "pub const MAX_WAITERS: usize = {};", config.waiters)?;
+
+    Ok(())
+}
@@ -0,0 +1,331 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Resource arbiter
+//!
+//! This task hands out exclusive ownership of shared resources (a mux, a
+//! bus, a flash part) to tasks that would otherwise step on one another.
+//! The resources themselves are named in `[config.arbiter]` in the
+//! application TOML; this task knows them only by index.
+//!
+//! Unlike most of our servers, `acquire` and `try_acquire` can block: a
+//! caller that wants a held resource is not replied to until the resource is
+//! released (or, for `try_acquire`, the timeout expires).  Because this
+//! requires a deferred reply, we don't use the Idol-generated server support,
+//! and instead speak the Idol wire protocol by hand via `hl::recv`.  Clients
+//! use the generated client stubs in `task-arbiter-api`.
+//!
+//! If a task dies while holding a resource, the resource is released.  We
+//! notice this either when the task's successor sends us a message or when
+//! we periodically check the generation numbers of the holders.
+
+#![no_std]
+#![no_main]
+
+use ringbuf::*;
+use task_arbiter_api::ArbiterError;
+use userlib::*;
+use zerocopy::FromBytes;
+
+include!(concat!(env!("OUT_DIR"), "/arbiter_config.rs"));
+
+const TIMER_MASK: u32 = 1 << 0;
+
+/// How often we check on the health of holders, in ticks.
+const TIMER_INTERVAL: u64 = 100;
+
+//
+// These must match the order of operations in `idl/arbiter.idol`.
+//
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+enum Operation {
+    Acquire = 1,
+    TryAcquire = 2,
+    Release = 3,
+    Holder = 4,
+}
+
+#[derive(FromBytes)]
+#[repr(C, packed)]
+struct ResourceArgs {
+    resource: u8,
+}
+
+#[derive(FromBytes)]
+#[repr(C, packed)]
+struct TryAcquireArgs {
+    resource: u8,
+    timeout: u32,
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    Acquired(usize, TaskId),
+    Waiting(usize, TaskId),
+    Released(usize, TaskId),
+    HolderDied(usize, TaskId),
+    Timeout(usize),
+    None,
+}
+
+ringbuf!(Trace, 16, Trace::None);
+
+/// What a blocked caller is waiting for.
+struct Waiter {
+    resource: usize,
+
+    /// Monotonically increasing sequence number, used to grant a resource to
+    /// its waiters in the order that they arrived.
+    seq: u32,
+}
+
+struct ServerImpl {
+    holders: [Option<TaskId>; NUM_RESOURCES],
+    waiters: hl::PendingTable<(), Waiter, MAX_WAITERS>,
+    seq: u32,
+    deadline: u64,
+}
+
+fn is_dead(task: TaskId) -> bool {
+    sys_refresh_task_id(task).generation() != task.generation()
+}
+
+impl ServerImpl {
+    fn resource(&self, resource: u8) -> Result<usize, ArbiterError> {
+        let index = usize::from(resource);
+
+        if index < NUM_RESOURCES {
+            Ok(index)
+        } else {
+            Err(ArbiterError::BadResource)
+        }
+    }
+
+    /// Marks `resource` as free, and hands it to the longest-waiting caller
+    /// (if any).
+    fn release(&mut self, resource: usize) {
+        self.holders[resource] = None;
+
+        //
+        // A waiter that has since died would never release the resource, so
+        // we drop any dead waiters before choosing one.
+        //
+        self.waiters.reap_dead();
+
+        let next = self
+            .waiters
+            .iter()
+            .filter(|p| p.data().resource == resource)
+            .min_by_key(|p| p.data().seq)
+            .map(|p| p.task_id());
+
+        if let Some(task) = next {
+            let (caller, _) = self.waiters.remove(task).unwrap().into_parts();
+            ringbuf_entry!(Trace::Acquired(resource, task));
+            self.holders[resource] = Some(task);
+            caller.reply(());
+        }
+    }
+
+    /// Releases any resource held by a task that has since died.  If
+    /// `sender` is specified, it is a live task that has just sent us a
+    /// message: any resource held by a different generation of the same task
+    /// is released without the need to ask the kernel.
+    fn reap(&mut self, sender: Option<TaskId>) {
+        self.waiters.reap_dead();
+
+        for resource in 0..NUM_RESOURCES {
+            let dead = match (self.holders[resource], sender) {
+                (None, _) => false,
+                (Some(holder), Some(sender))
+                    if holder.index() == sender.index() =>
+                {
+                    holder != sender
+                }
+                (Some(holder), _) => is_dead(holder),
+            };
+
+            if dead {
+                let holder = self.holders[resource].unwrap();
+                ringbuf_entry!(Trace::HolderDied(resource, holder));
+                self.release(resource);
+            }
+        }
+    }
+
+    /// Attempts to take `resource` on behalf of `caller`.  If the resource is
+    /// held, the caller is added to our waiters with the specified deadline
+    /// (if any).
+    fn acquire(
+        &mut self,
+        caller: hl::Caller<()>,
+        resource: usize,
+        deadline: Option<u64>,
+    ) -> Result<(), ArbiterError> {
+        let task = caller.task_id();
+
+        match self.holders[resource] {
+            None => {
+                ringbuf_entry!(Trace::Acquired(resource, task));
+                self.holders[resource] = Some(task);
+                caller.reply(());
+                Ok(())
+            }
+            Some(holder) if holder == task => {
+                caller.reply(());
+                Ok(())
+            }
+            Some(_) => {
+                let waiter = Waiter {
+                    resource,
+                    seq: self.seq,
+                };
+
+                self.seq = self.seq.wrapping_add(1);
+
+                match self.waiters.insert(caller, waiter, deadline) {
+                    Ok(_) => {
+                        ringbuf_entry!(Trace::Waiting(resource, task));
+                        Ok(())
+                    }
+                    Err(_) => Err(ArbiterError::TooManyWaiters),
+                }
+            }
+        }
+    }
+
+    fn handle_timer(&mut self) {
+        let now = sys_get_timer().now;
+
+        if now >= self.deadline {
+            self.reap(None);
+            self.deadline = now + TIMER_INTERVAL;
+        }
+
+        for p in self.waiters.iter() {
+            if matches!(p.deadline(), Some(deadline) if deadline <= now) {
+                ringbuf_entry!(Trace::Timeout(p.data().resource));
+            }
+        }
+
+        self.waiters.expire(now, ArbiterError::Timeout);
+        self.set_timer();
+    }
+
+    fn set_timer(&self) {
+        let deadline = match self.waiters.next_deadline() {
+            Some(deadline) if deadline < self.deadline => deadline,
+            _ => self.deadline,
+        };
+
+        sys_set_timer(Some(deadline), TIMER_MASK);
+    }
+
+    fn handle_message(
+        &mut self,
+        op: Operation,
+        msg: hl::Message,
+    ) -> Result<(), ArbiterError> {
+        match op {
+            Operation::Acquire => {
+                let (args, caller) = msg
+                    .fixed::<ResourceArgs, ()>()
+                    .ok_or(ArbiterError::BadArg)?;
+                let resource = self.resource(args.resource)?;
+
+                self.reap(Some(caller.task_id()));
+                self.acquire(caller, resource, None)
+            }
+
+            Operation::TryAcquire => {
+                let (args, caller) = msg
+                    .fixed::<TryAcquireArgs, ()>()
+                    .ok_or(ArbiterError::BadArg)?;
+                let resource = self.resource(args.resource)?;
+                let timeout = args.timeout;
+
+                self.reap(Some(caller.task_id()));
+
+                if timeout == 0 {
+                    match self.holders[resource] {
+                        Some(holder) if holder != caller.task_id() => {
+                            return Err(ArbiterError::Held);
+                        }
+                        _ => {}
+                    }
+                }
+
+                let deadline = sys_get_timer().now + u64::from(timeout);
+                self.acquire(caller, resource, Some(deadline))?;
+                self.set_timer();
+                Ok(())
+            }
+
+            Operation::Release => {
+                let (args, caller) = msg
+                    .fixed::<ResourceArgs, ()>()
+                    .ok_or(ArbiterError::BadArg)?;
+                let resource = self.resource(args.resource)?;
+                let task = caller.task_id();
+
+                if self.holders[resource] != Some(task) {
+                    return Err(ArbiterError::NotHolder);
+                }
+
+                ringbuf_entry!(Trace::Released(resource, task));
+                self.release(resource);
+                caller.reply(());
+                Ok(())
+            }
+
+            Operation::Holder => {
+                let (args, caller) = msg
+                    .fixed::<ResourceArgs, u16>()
+                    .ok_or(ArbiterError::BadArg)?;
+                let resource = self.resource(args.resource)?;
+
+                self.reap(None);
+
+                match self.holders[resource] {
+                    Some(holder) => {
+                        caller.reply(holder.0);
+                        Ok(())
+                    }
+                    None => Err(ArbiterError::NotHeld),
+                }
+            }
+        }
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut server = ServerImpl {
+        holders: [None; NUM_RESOURCES],
+        waiters: hl::PendingTable::new(),
+        seq: 0,
+        deadline: sys_get_timer().now + TIMER_INTERVAL,
+    };
+
+    server.set_timer();
+
+    //
+    // Our largest message is the arguments to `try_acquire`.
+    //
+    let mut buffer = [0; core::mem::size_of::<TryAcquireArgs>()];
+
+    loop {
+        hl::recv(
+            &mut buffer,
+            TIMER_MASK,
+            &mut server,
+            |server, bits| {
+                if bits & TIMER_MASK != 0 {
+                    server.handle_timer();
+                }
+            },
+            |server, op, msg| server.handle_message(op, msg),
+        );
+    }
+}
@@ -0,0 +1,54 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct Config {
+    events: Option<EventsConfig>,
+}
+
+#[derive(Deserialize)]
+struct EventsConfig {
+    topics: Vec<String>,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    idol::client::build_client_stub("../../idl/events.idol", "client_stub.rs")?;
+
+    //
+    // Topics are named in the app-wide configuration; publishers and
+    // subscribers refer to them via the constants that we generate here.
+    //
+    let topics = match build_util::config::<Config>() {
+        Ok(Config {
+            events: Some(events),
+        }) => events.topics,
+        _ => vec![],
+    };
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("topics.rs");
+    let mut file = File::create(&dest_path)?;
+
+    writeln!(file, "pub mod topics {{")?;
+    writeln!(file, "    use super::Topic;")?;
+
+    for (index, name) in topics.iter().enumerate() {
+        writeln!(
+            file,
+            "    pub const {}: Topic = Topic({});",
+            name.to_uppercase(),
+            index
+        )?;
+    }
+
+    writeln!(file, "}}")?;
+
+    Ok(())
+}
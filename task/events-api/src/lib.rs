@@ -0,0 +1,116 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the event bus.
+//!
+//! Topics are named as `topics` in the `[config.events]` section of the
+//! application TOML; constants for each are found in [`topics`].  An event
+//! consists of a publisher-defined `kind` and up to [`EVENT_DATA_MAX`] bytes
+//! of payload.
+//!
+//! A subscriber names a notification bit when subscribing; when that bit is
+//! posted, it should call [`Events::receive`] until it returns
+//! [`EventError::QueueEmpty`].
+
+#![no_std]
+
+use serde::{Deserialize, Serialize};
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
+
+/// Maximum size of an event payload, in bytes.  This must match the lease
+/// limits in `idl/events.idol`.
+pub const EVENT_DATA_MAX: usize = 16;
+
+#[derive(zerocopy::AsBytes, Copy, Clone, Debug, PartialEq)]
+#[repr(C)]
+pub struct Topic(pub u8);
+
+impl From<u8> for Topic {
+    fn from(index: u8) -> Self {
+        Topic(index)
+    }
+}
+
+impl From<Topic> for u8 {
+    fn from(topic: Topic) -> Self {
+        topic.0
+    }
+}
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum EventError {
+    /// Topic index is out of range
+    BadTopic = 1,
+
+    /// Topic already has its maximum number of subscribers
+    TooManySubscribers = 2,
+
+    /// Caller is not subscribed to the topic
+    NotSubscribed = 3,
+
+    /// Notification mask is empty
+    BadNotification = 4,
+
+    /// No events are pending for the caller
+    QueueEmpty = 5,
+}
+
+impl From<EventError> for u16 {
+    fn from(rc: EventError) -> Self {
+        rc as u16
+    }
+}
+
+impl From<EventError> for u32 {
+    fn from(rc: EventError) -> Self {
+        rc as u32
+    }
+}
+
+impl core::convert::TryFrom<u32> for EventError {
+    type Error = ();
+    fn try_from(rc: u32) -> Result<Self, Self::Error> {
+        Self::from_u32(rc).ok_or(())
+    }
+}
+
+/// Describes an event returned by [`Events::receive`]; the payload itself is
+/// written into the caller's buffer.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct EventHeader {
+    /// Topic on which the event was published
+    pub topic: u8,
+    /// Publisher-defined event type
+    pub kind: u16,
+    /// Number of payload bytes written
+    pub len: u8,
+    /// Number of events on this topic discarded for this subscriber, because
+    /// its queue was full, since its last successful `receive`
+    pub dropped: u16,
+}
+
+impl EventHeader {
+    /// Interprets the payload in `data` as a `T`, returning `None` if the
+    /// payload is not exactly the size of a `T`.
+    pub fn decode<T: FromBytes>(&self, data: &[u8]) -> Option<T> {
+        let data = data.get(..usize::from(self.len))?;
+        T::read_from(data)
+    }
+}
+
+impl Events {
+    /// Publishes `value` as the payload of an event of type `kind`.
+    pub fn publish_value<T: AsBytes>(
+        &self,
+        topic: Topic,
+        kind: u16,
+        value: &T,
+    ) -> Result<(), EventError> {
+        self.publish(topic, kind, value.as_bytes())
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/topics.rs"));
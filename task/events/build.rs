@@ -0,0 +1,77 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize)]
+struct Config {
+    events: EventsConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct EventsConfig {
+    /// names of the topics on the bus
+    topics: Vec<String>,
+
+    /// maximum number of subscribers to any one topic
+    #[serde(default = "default_subscribers")]
+    subscribers: usize,
+
+    /// number of events that can be queued for each subscriber to a topic
+    #[serde(default = "default_depth")]
+    depth: usize,
+}
+
+fn default_subscribers() -> usize {
+    4
+}
+
+fn default_depth() -> usize {
+    4
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    idol::server::build_server_support(
+        "../../idl/events.idol",
+        "server_stub.rs",
+        idol::server::ServerStyle::InOrder,
+    )?;
+
+    let config = build_util::config::<Config>()?.events;
+
+    if config.topics.is_empty() {
+        return Err("event bus configured without any topics".into());
+    }
+
+    if config.topics.len() > usize::from(u8::MAX) {
+        return Err("too many event bus topics".into());
+    }
+
+    if config.subscribers == 0 || config.depth == 0 {
+        return Err("event bus subscribers and depth must be non-zero".into());
+    }
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("events_config.rs");
+    let mut file = File::create(&dest_path)?;
+
+    writeln!(
+        file,
+        "pub const NUM_TOPICS: usize = {};",
+        config.topics.len()
+    )?;
+    writeln!(
+        file,
+        "pub const MAX_SUBSCRIBERS: usize = {};",
+        config.subscribers
+    )?;
+    writeln!(file, "pub const QUEUE_DEPTH: usize = {};", config.depth)?;
+
+    Ok(())
+}
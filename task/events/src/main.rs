@@ -0,0 +1,330 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Event bus
+//!
+//! This task allows tasks to publish small events on named topics without
+//! knowing who (if anyone) is interested in them.  Topics are configured as
+//! `topics` in `[config.events]` in the application TOML, along with the
+//! maximum number of `subscribers` to any one topic and the `depth` of each
+//! subscriber's queue; all storage is allocated statically from these.
+//!
+//! A subscriber names a notification mask when it subscribes.  When an event
+//! is published to a topic, it is queued for each subscriber and the
+//! subscriber's notification is posted; the subscriber then drains its
+//! queues via `receive`, which returns events oldest first across all topics.
+//! If a subscriber falls behind, its oldest events are discarded, and the
+//! number discarded is reported with the next event it receives.
+//!
+//! Subscribers that die are forgotten, either when posting to them fails or
+//! when their successor subscribes.
+
+#![no_std]
+#![no_main]
+
+use idol_runtime::{ClientError, Leased, LenLimit, RequestError, R, W};
+use ringbuf::*;
+use task_events_api::{EventError, EventHeader, Topic, EVENT_DATA_MAX};
+use userlib::*;
+
+include!(concat!(env!("OUT_DIR"), "/events_config.rs"));
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    Subscribed(usize, TaskId),
+    Unsubscribed(usize, TaskId),
+    Published(usize, u16),
+    Dropped(usize, TaskId),
+    SubscriberDied(usize, TaskId),
+    None,
+}
+
+ringbuf!(Trace, 16, Trace::None);
+
+#[derive(Copy, Clone)]
+struct Event {
+    kind: u16,
+    len: u8,
+    data: [u8; EVENT_DATA_MAX],
+
+    /// Sequence number assigned at publication, used to deliver events in
+    /// order across topics.
+    seq: u32,
+}
+
+impl Event {
+    const EMPTY: Self = Self {
+        kind: 0,
+        len: 0,
+        data: [0; EVENT_DATA_MAX],
+        seq: 0,
+    };
+}
+
+#[derive(Copy, Clone)]
+struct Subscriber {
+    task: TaskId,
+    notification: u32,
+
+    /// Circular queue of events: `len` events starting at `head`.
+    queue: [Event; QUEUE_DEPTH],
+    head: usize,
+    len: usize,
+
+    /// Events discarded since the last successful `receive`.
+    dropped: u16,
+}
+
+impl Subscriber {
+    fn new(task: TaskId, notification: u32) -> Self {
+        Self {
+            task,
+            notification,
+            queue: [Event::EMPTY; QUEUE_DEPTH],
+            head: 0,
+            len: 0,
+            dropped: 0,
+        }
+    }
+
+    /// Queues `event`, discarding the oldest queued event if full.  Returns
+    /// true if an event was discarded.
+    fn push(&mut self, event: Event) -> bool {
+        let full = self.len == QUEUE_DEPTH;
+
+        if full {
+            self.head = (self.head + 1) % QUEUE_DEPTH;
+            self.len -= 1;
+            self.dropped = self.dropped.saturating_add(1);
+        }
+
+        self.queue[(self.head + self.len) % QUEUE_DEPTH] = event;
+        self.len += 1;
+        full
+    }
+
+    fn peek(&self) -> Option<&Event> {
+        if self.len == 0 {
+            None
+        } else {
+            Some(&self.queue[self.head])
+        }
+    }
+
+    fn pop(&mut self) -> Option<Event> {
+        let event = *self.peek()?;
+        self.head = (self.head + 1) % QUEUE_DEPTH;
+        self.len -= 1;
+        Some(event)
+    }
+}
+
+struct ServerImpl {
+    topics: [[Option<Subscriber>; MAX_SUBSCRIBERS]; NUM_TOPICS],
+    seq: u32,
+}
+
+impl ServerImpl {
+    fn topic(&self, topic: Topic) -> Result<usize, EventError> {
+        let index = usize::from(topic.0);
+
+        if index < NUM_TOPICS {
+            Ok(index)
+        } else {
+            Err(EventError::BadTopic)
+        }
+    }
+
+    /// Forgets any subscription held by a different generation of `sender`,
+    /// which must therefore have died.
+    fn reap(&mut self, sender: TaskId) {
+        for (topic, subscribers) in self.topics.iter_mut().enumerate() {
+            for slot in subscribers.iter_mut() {
+                match slot {
+                    Some(s)
+                        if s.task.index() == sender.index()
+                            && s.task != sender =>
+                    {
+                        ringbuf_entry!(Trace::SubscriberDied(topic, s.task));
+                        *slot = None;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+impl idl::InOrderEventsImpl for ServerImpl {
+    fn subscribe(
+        &mut self,
+        msg: &RecvMessage,
+        topic: Topic,
+        notification: u32,
+    ) -> Result<(), RequestError<EventError>> {
+        let topic = self.topic(topic)?;
+
+        if notification == 0 {
+            return Err(EventError::BadNotification.into());
+        }
+
+        self.reap(msg.sender);
+
+        let subscribers = &mut self.topics[topic];
+
+        //
+        // Subscribing again just updates the notification.
+        //
+        if let Some(s) = subscribers
+            .iter_mut()
+            .flatten()
+            .find(|s| s.task == msg.sender)
+        {
+            s.notification = notification;
+            return Ok(());
+        }
+
+        let slot = subscribers
+            .iter_mut()
+            .find(|s| s.is_none())
+            .ok_or(EventError::TooManySubscribers)?;
+
+        *slot = Some(Subscriber::new(msg.sender, notification));
+        ringbuf_entry!(Trace::Subscribed(topic, msg.sender));
+        Ok(())
+    }
+
+    fn unsubscribe(
+        &mut self,
+        msg: &RecvMessage,
+        topic: Topic,
+    ) -> Result<(), RequestError<EventError>> {
+        let topic = self.topic(topic)?;
+
+        let slot = self.topics[topic]
+            .iter_mut()
+            .find(|s| matches!(s, Some(s) if s.task == msg.sender))
+            .ok_or(EventError::NotSubscribed)?;
+
+        *slot = None;
+        ringbuf_entry!(Trace::Unsubscribed(topic, msg.sender));
+        Ok(())
+    }
+
+    fn publish(
+        &mut self,
+        _: &RecvMessage,
+        topic: Topic,
+        kind: u16,
+        data: LenLimit<Leased<R, [u8]>, EVENT_DATA_MAX>,
+    ) -> Result<(), RequestError<EventError>> {
+        let topic = self.topic(topic)?;
+
+        let mut event = Event {
+            kind,
+            len: data.len() as u8,
+            data: [0; EVENT_DATA_MAX],
+            seq: self.seq,
+        };
+
+        data.read_range(0..data.len(), &mut event.data[..data.len()])
+            .map_err(|_| RequestError::went_away())?;
+
+        self.seq = self.seq.wrapping_add(1);
+        ringbuf_entry!(Trace::Published(topic, kind));
+
+        for slot in self.topics[topic].iter_mut() {
+            if let Some(s) = slot {
+                if s.push(event) {
+                    ringbuf_entry!(Trace::Dropped(topic, s.task));
+                }
+
+                //
+                // If the post fails, the subscriber has died; there's no
+                // point in holding its events for a successor that may not
+                // want them.
+                //
+                if sys_post(s.task, s.notification) != 0 {
+                    ringbuf_entry!(Trace::SubscriberDied(topic, s.task));
+                    *slot = None;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    fn receive(
+        &mut self,
+        msg: &RecvMessage,
+        data: LenLimit<Leased<W, [u8]>, EVENT_DATA_MAX>,
+    ) -> Result<EventHeader, RequestError<EventError>> {
+        //
+        // Find the oldest event pending for the caller on any topic.  We
+        // compare sequence numbers relative to the next one to be assigned
+        // to cope with wrapping.
+        //
+        let seq = self.seq;
+        let mut oldest: Option<(usize, usize, u32)> = None;
+
+        for (topic, subscribers) in self.topics.iter().enumerate() {
+            for (i, slot) in subscribers.iter().enumerate() {
+                let event = match slot {
+                    Some(s) if s.task == msg.sender => s.peek(),
+                    _ => None,
+                };
+
+                if let Some(event) = event {
+                    let age = seq.wrapping_sub(event.seq);
+
+                    if oldest.map_or(true, |(_, _, oldest)| age > oldest) {
+                        oldest = Some((topic, i, age));
+                    }
+                }
+            }
+        }
+
+        let (topic, i, _) = oldest.ok_or(EventError::QueueEmpty)?;
+        let s = self.topics[topic][i].as_mut().unwrap();
+        let event = s.peek().unwrap();
+        let len = usize::from(event.len);
+
+        if data.len() < len {
+            return Err(RequestError::Fail(ClientError::BadLease));
+        }
+
+        data.write_range(0..len, &event.data[..len])
+            .map_err(|_| RequestError::went_away())?;
+
+        let event = s.pop().unwrap();
+        let dropped = core::mem::replace(&mut s.dropped, 0);
+
+        Ok(EventHeader {
+            topic: topic as u8,
+            kind: event.kind,
+            len: event.len,
+            dropped,
+        })
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut server = ServerImpl {
+        topics: [[None; MAX_SUBSCRIBERS]; NUM_TOPICS],
+        seq: 0,
+    };
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+
+    loop {
+        idol_runtime::dispatch(&mut buffer, &mut server);
+    }
+}
+
+mod idl {
+    use task_events_api::{EventError, EventHeader, Topic};
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
@@ -0,0 +1,163 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the Power task.
+
+#![no_std]
+
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum PowerError {
+    InvalidRail = 1,
+    DeviceError = 2,
+    NoRecord = 3,
+    RailOff = 4,
+    Unsupported = 5,
+    MarginingDisabled = 6,
+}
+
+impl From<PowerError> for u16 {
+    fn from(rc: PowerError) -> Self {
+        rc as u16
+    }
+}
+
+impl From<PowerError> for u32 {
+    fn from(rc: PowerError) -> Self {
+        rc as u32
+    }
+}
+
+impl core::convert::TryFrom<u32> for PowerError {
+    type Error = ();
+    fn try_from(rc: u32) -> Result<Self, Self::Error> {
+        Self::from_u32(rc).ok_or(())
+    }
+}
+
+/// The PMBus status of a rail:  `STATUS_WORD`, and the per-class status
+/// registers that it summarizes.  Each field is the raw register, as defined
+/// by the PMBus specification.
+#[derive(Copy, Clone, Debug, Default, PartialEq, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct FaultStatus {
+    pub word: u16,
+    pub vout: u8,
+    pub iout: u8,
+    pub input: u8,
+    pub temperature: u8,
+}
+
+impl FaultStatus {
+    pub const EMPTY: Self = Self {
+        word: 0,
+        vout: 0,
+        iout: 0,
+        input: 0,
+        temperature: 0,
+    };
+
+    //
+    // Bits of STATUS_WORD.  The low byte is STATUS_BYTE; the bits for
+    // VOUT, IOUT, INPUT and TEMPERATURE indicate that the corresponding
+    // per-class register has a bit set.
+    //
+    pub const NONE_OF_THE_ABOVE: u16 = 1 << 0;
+    pub const CML: u16 = 1 << 1;
+    pub const TEMPERATURE: u16 = 1 << 2;
+    pub const VIN_UV_FAULT: u16 = 1 << 3;
+    pub const IOUT_OC_FAULT: u16 = 1 << 4;
+    pub const VOUT_OV_FAULT: u16 = 1 << 5;
+    pub const OFF: u16 = 1 << 6;
+    pub const BUSY: u16 = 1 << 7;
+    pub const UNKNOWN: u16 = 1 << 8;
+    pub const OTHER: u16 = 1 << 9;
+    pub const FANS: u16 = 1 << 10;
+    pub const POWER_GOOD_N: u16 = 1 << 11;
+    pub const MFR: u16 = 1 << 12;
+    pub const INPUT: u16 = 1 << 13;
+    pub const IOUT: u16 = 1 << 14;
+    pub const VOUT: u16 = 1 << 15;
+
+    /// Bits of STATUS_WORD that merely reflect a rail being off (as it is
+    /// in lower power states), rather than a fault or warning.
+    pub const STATE_MASK: u16 = Self::OFF | Self::POWER_GOOD_N;
+
+    /// Returns true if any fault or warning is indicated.
+    pub fn is_fault(&self) -> bool {
+        self.word & !Self::STATE_MASK != 0
+    }
+}
+
+/// Live readings of a rail.  A reading that the rail's controller does not
+/// support, or that could not be made, is NaN.
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct RailReadings {
+    /// Input voltage, in volts
+    pub vin: f32,
+
+    /// Output voltage, in volts
+    pub vout: f32,
+
+    /// Input current, in amperes
+    pub iin: f32,
+
+    /// Output current, in amperes
+    pub iout: f32,
+
+    /// Output power, in watts
+    pub pout: f32,
+
+    /// Controller temperature, in degrees Celsius
+    pub temperature: f32,
+}
+
+/// The margin state of a rail's output voltage.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum Margin {
+    /// The rail regulates to its nominal voltage.
+    Nominal = 0,
+
+    /// The rail regulates to its `VOUT_MARGIN_LOW` target.
+    Low = 1,
+
+    /// The rail regulates to its `VOUT_MARGIN_HIGH` target.
+    High = 2,
+}
+
+/// The margin targets configured for a rail, in volts.
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct MarginTargets {
+    pub low: f32,
+    pub high: f32,
+}
+
+/// A change in the fault status of a rail.
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct FaultRecord {
+    /// Time of the change, in kernel ticks since boot
+    pub timestamp: u64,
+
+    /// Index of the rail, as understood by `rail_name`
+    pub rail: u16,
+
+    /// The new status
+    pub status: FaultStatus,
+}
+
+impl FaultRecord {
+    pub const EMPTY: Self = Self {
+        timestamp: 0,
+        rail: 0,
+        status: FaultStatus::EMPTY,
+    };
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
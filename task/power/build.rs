@@ -2,7 +2,219 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() {
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskConfig {
+    /// rails that are powered only in A0; all others are powered in A2
+    #[serde(default)]
+    a0_rails: BTreeSet<String>,
+
+    /// sense resistors of hot swap controllers, in ohms, by rail
+    #[serde(default)]
+    rsense: BTreeMap<String, f32>,
+
+    /// coefficients of rails driven as generic PMBus devices whose telemetry
+    /// is in the direct format, by rail
+    #[serde(default)]
+    coefficients: BTreeMap<String, DirectCoefficients>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DirectCoefficients {
+    voltage: Coefficients,
+    current: Coefficients,
+    temperature: Coefficients,
+}
+
+///
+/// Direct-format coefficients, named as in the PMBus specification:  a raw
+/// value Y is converted to a value X as `X = (Y * 10^-R - b) / m`.
+///
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Coefficients {
+    m: i32,
+    b: i32,
+    #[serde(rename = "R")]
+    r: i8,
+}
+
+impl Coefficients {
+    fn emit(&self) -> String {
+        format!(
+            "pmbus::Coefficients {{ m: {}, b: {}, R: {} }}",
+            self.m, self.b, self.r
+        )
+    }
+}
+
+//
+// The subset of the app-wide I2C configuration that we need to find PMBus
+// rails.  This is shared with other build-specific types, so we must not set
+// `deny_unknown_fields` here.
+//
+#[derive(Deserialize)]
+struct GlobalConfig {
+    i2c: I2cConfig,
+}
+
+#[derive(Deserialize)]
+struct I2cConfig {
+    devices: Option<Vec<I2cDevice>>,
+}
+
+#[derive(Deserialize)]
+struct I2cDevice {
+    device: String,
+    pmbus: Option<I2cPmbus>,
+    sensors: Option<I2cSensors>,
+}
+
+#[derive(Deserialize)]
+struct I2cPmbus {
+    rails: Option<Vec<String>>,
+}
+
+#[derive(Default, Deserialize)]
+struct I2cSensors {
+    #[serde(default)]
+    temperature: usize,
+    #[serde(default)]
+    power: usize,
+    #[serde(default)]
+    current: usize,
+    #[serde(default)]
+    voltage: usize,
+    #[serde(default)]
+    energy: usize,
+}
+
+/// The driver for a PMBus part, and what it can measure beyond voltage and
+/// current.
+struct Driver {
+    /// name of the driver, which is also that of its `Device` variant
+    name: &'static str,
+
+    /// the driver can read the rail's temperature
+    temperature: bool,
+
+    /// the driver can read an input energy accumulator
+    energy: bool,
+
+    /// the driver is constructed with a sense resistor
+    rsense: bool,
+}
+
+///
+/// Returns the driver for a PMBus part.  Parts without a driver of their own
+/// are driven by `PmbusDevice`, which needs only what the PMBus specification
+/// requires of them.
+///
+fn rail_driver(device: &str) -> Driver {
+    let driver = |name, temperature, energy, rsense| Driver {
+        name,
+        temperature,
+        energy,
+        rsense,
+    };
+
+    match device {
+        "adm1272" => driver("Adm1272", true, true, true),
+        "bmr491" => driver("Bmr491", true, false, false),
+        "isl68224" => driver("Isl68224", false, false, false),
+        "raa229618" => driver("Raa229618", true, true, false),
+        _ => driver("PmbusDevice", true, false, false),
+    }
+}
+
+/// A rail that we monitor, and the sensors that it has
+struct Rail<'a> {
+    device: &'a str,
+    name: &'a str,
+    voltage: bool,
+    current: bool,
+    temperature: bool,
+    power: bool,
+    energy: bool,
+}
+
+impl Rail<'_> {
+    /// Checks that the rail's driver can read its sensors, and that it has
+    /// what its driver needs.
+    fn validate(
+        &self,
+        config: &TaskConfig,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        let driver = rail_driver(self.device);
+        let name = self.name;
+
+        if !(self.voltage && self.current) {
+            return Err(format!(
+                "rail {} must have voltage and current sensors",
+                name
+            )
+            .into());
+        }
+
+        if self.temperature && !driver.temperature {
+            return Err(
+                format!("rail {} can't measure temperature", name).into()
+            );
+        }
+
+        if self.energy && !(driver.energy && self.power) {
+            return Err(format!(
+                "rail {} can't measure energy (or has no power sensor)",
+                name
+            )
+            .into());
+        }
+
+        if let Some(c) = config.coefficients.get(name) {
+            if driver.name != "PmbusDevice" {
+                return Err(format!(
+                    "rail {} has a driver of its own, which knows its \
+                    coefficients",
+                    name
+                )
+                .into());
+            }
+
+            if [&c.voltage, &c.current, &c.temperature]
+                .iter()
+                .any(|c| c.m == 0)
+            {
+                return Err(
+                    format!("rail {} has a coefficient m of 0", name).into()
+                );
+            }
+        }
+
+        match (driver.rsense, config.rsense.get(name)) {
+            (true, Some(&rsense)) if rsense > 0.0 => Ok(()),
+            (true, Some(_)) => {
+                Err(format!("rail {} has a bad rsense", name).into())
+            }
+            (true, None) => {
+                Err(format!("rail {} must have an rsense", name).into())
+            }
+            (false, Some(_)) => {
+                Err(format!("rail {} has no sense resistor", name).into())
+            }
+            (false, None) => Ok(()),
+        }
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
 
     let disposition = build_i2c::Disposition::Sensors;
@@ -11,4 +223,157 @@ fn main() {
         println!("code generation failed: {}", e);
         std::process::exit(1);
     }
+
+    idol::server::build_server_support(
+        "../../idl/power.idol",
+        "server_stub.rs",
+        idol::server::ServerStyle::InOrder,
+    )?;
+
+    let config = build_util::task_config::<TaskConfig>()?;
+    let global = build_util::config::<GlobalConfig>()?;
+    let devices = global.i2c.devices.unwrap_or_default();
+
+    //
+    // Each rail that has sensors is a rail that we monitor, in the order in
+    // which the rails appear in the configuration.
+    //
+    let none = I2cSensors::default();
+    let mut rails = vec![];
+
+    for d in &devices {
+        let names = match d.pmbus.as_ref().and_then(|p| p.rails.as_ref()) {
+            Some(names) => names,
+            None => continue,
+        };
+
+        let sensors = d.sensors.as_ref().unwrap_or(&none);
+
+        for (index, name) in names.iter().enumerate() {
+            let has = |count: usize| index < count;
+
+            let rail = Rail {
+                device: &d.device,
+                name,
+                voltage: has(sensors.voltage),
+                current: has(sensors.current),
+                temperature: has(sensors.temperature),
+                power: has(sensors.power),
+                energy: has(sensors.energy),
+            };
+
+            if name.is_empty() || !(rail.voltage || rail.current) {
+                continue;
+            }
+
+            rail.validate(&config)?;
+            rails.push(rail);
+        }
+    }
+
+    let names = config
+        .a0_rails
+        .iter()
+        .chain(config.rsense.keys())
+        .chain(config.coefficients.keys());
+
+    for name in names {
+        if !rails.iter().any(|r| r.name == name) {
+            return Err(format!("{} is not a monitored rail", name).into());
+        }
+    }
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("power_config.rs");
+    let mut file = File::create(&dest_path)?;
+
+    writeln!(file, "pub const NUM_RAILS: usize = {};", rails.len())?;
+
+    writeln!(
+        file,
+        "\nfn controllers(task: TaskId) -> \
+        [PowerController; NUM_RAILS] {{\n    ["
+    )?;
+
+    for rail in &rails {
+        let driver = rail_driver(rail.device);
+        let lower = rail.name.to_lowercase();
+        let prefix = format!(
+            "sensors::{}_{}",
+            rail.device.to_uppercase(),
+            rail.name.to_uppercase()
+        );
+
+        let page =
+            format!("i2c_config::pmbus::{}_PAGE", rail.name.to_uppercase());
+
+        let (device, ctor, args) = match config.rsense.get(rail.name) {
+            Some(rsense) => ("device, _", "new", format!("Ohms({:?})", rsense)),
+            None if driver.name == "PmbusDevice" => {
+                match config.coefficients.get(rail.name) {
+                    Some(c) => (
+                        "device, _",
+                        "with_coefficients",
+                        format!(
+                            "{}, DirectCoefficients {{ voltage: {}, \
+                            current: {}, temperature: {} }}",
+                            page,
+                            c.voltage.emit(),
+                            c.current.emit(),
+                            c.temperature.emit()
+                        ),
+                    ),
+                    None => ("device, _", "new", page),
+                }
+            }
+            None => ("device, rail", "new", "rail".to_string()),
+        };
+
+        let state = if config.a0_rails.contains(rail.name) {
+            "A0"
+        } else {
+            "A2"
+        };
+
+        let temperature = if rail.temperature {
+            format!("Some({}_TEMPERATURE_SENSOR)", prefix)
+        } else {
+            "None".to_string()
+        };
+
+        let energy = if rail.energy {
+            format!("Some(({p}_POWER_SENSOR, {p}_ENERGY_SENSOR))", p = prefix)
+        } else {
+            "None".to_string()
+        };
+
+        writeln!(
+            file,
+            r##"        PowerController::new(
+            "{lower}",
+            PowerState::{state},
+            {{
+                let ({device}) = i2c_config::pmbus::{lower}(task);
+                Device::{driver}({driver}::{ctor}(&device, {args}))
+            }},
+            {prefix}_VOLTAGE_SENSOR,
+            {prefix}_CURRENT_SENSOR,
+            {temperature},
+            {energy},
+        ),"##,
+            lower = lower,
+            state = state,
+            device = device,
+            driver = driver.name,
+            ctor = ctor,
+            args = args,
+            prefix = prefix,
+            temperature = temperature,
+            energy = energy,
+        )?;
+    }
+
+    writeln!(file, "    ]\n}}")?;
+
+    Ok(())
 }
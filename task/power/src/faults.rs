@@ -0,0 +1,45 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Log of rail faults
+//!
+//! Each time that the fault status of a rail changes, the new status is
+//! recorded in a [`FaultLog`]; once the log is full, the oldest records are
+//! overwritten.
+
+use task_power_api::FaultRecord;
+
+/// Number of records retained
+pub const LOG_DEPTH: usize = 16;
+
+pub struct FaultLog {
+    /// Number of records ever made; the most recent is at `(count - 1) %
+    /// LOG_DEPTH`
+    count: u32,
+    records: [FaultRecord; LOG_DEPTH],
+}
+
+impl FaultLog {
+    pub const fn new() -> Self {
+        Self {
+            count: 0,
+            records: [FaultRecord::EMPTY; LOG_DEPTH],
+        }
+    }
+
+    pub fn record(&mut self, record: FaultRecord) {
+        self.records[self.count as usize % LOG_DEPTH] = record;
+        self.count = self.count.wrapping_add(1);
+    }
+
+    /// Returns the `index`th most recent record, if any.
+    pub fn get(&self, index: usize) -> Option<&FaultRecord> {
+        if index < LOG_DEPTH && index < self.count as usize {
+            let slot = (self.count as usize - 1 - index) % LOG_DEPTH;
+            Some(&self.records[slot])
+        } else {
+            None
+        }
+    }
+}
@@ -4,25 +4,65 @@
 
 //! Power monitoring
 //!
-//! This is a primordial power monitoring task.
+//! This is a primordial power monitoring task.  Along with the voltage,
+//! current and temperature of each rail, it reads the rail's PMBus status
+//! (`STATUS_WORD` and the per-class status registers) on each poll; changes
+//! in status are recorded in a bounded log of fault records, which can be
+//! retrieved via `get_fault_record`.  Faults latched by a rail's controller
+//! can be cleared with `clear_faults`.
+//!
+//! Rails are named as they are in the `pmbus` configuration of each I2C
+//! device in the application's TOML; `num_rails` and `rail_name` enumerate
+//! them, and `read_rail` makes a fresh set of readings of a single rail.
+//! Every such rail with voltage and current sensors is monitored, in the
+//! order in which it appears; the task's configuration names the rails that
+//! are powered only in A0 (all others being powered in A2), and the sense
+//! resistor of each hot swap controller.  A rail whose part has no driver of
+//! its own is driven as a generic PMBus device; if its telemetry is in the
+//! direct format, the configuration must also give its coefficients.
+//!
+//! Rails whose controllers are voltage regulators can be margined high or
+//! low via `set_margin`; as this is only for validation, it is available only
+//! in builds with the `margining` feature.  Only the parts whose margining
+//! has been checked can be margined:  a rail driven as a generic PMBus
+//! device can't be.
+//!
+//! For rails whose controllers have an input energy accumulator
+//! (`READ_EIN`), we also account for energy:  on each poll, the average
+//! input power since the previous poll is published as the rail's power
+//! sensor, and the energy consumed since boot as its energy sensor.
 //!
 
 #![no_std]
 #![no_main]
 
+mod faults;
+
 use drv_gimlet_seq_api as seq_api;
 use drv_i2c_devices::adm1272::*;
 use drv_i2c_devices::bmr491::*;
 use drv_i2c_devices::isl68224::*;
+use drv_i2c_devices::pmbus_device::*;
 use drv_i2c_devices::raa229618::*;
-use drv_i2c_devices::tps546b24a::*;
+use idol_runtime::{
+    ClientError, Leased, LenLimit, NotificationHandler, RequestError, R, W,
+};
+use ringbuf::*;
+use task_power_api::{
+    FaultRecord, FaultStatus, Margin, MarginTargets, PowerError, RailReadings,
+};
 use task_sensor_api as sensor_api;
 use userlib::units::*;
 use userlib::*;
 
 use drv_i2c_api::ResponseCode;
-use drv_i2c_devices::{CurrentSensor, TempSensor, VoltageSensor};
+use drv_i2c_devices::{
+    CurrentSensor, EnergyCount, EnergySensor, InputCurrentSensor,
+    InputVoltageSensor, PmbusStatus, PowerSensor, StatusSensor, TempSensor,
+    VoltageRegulator, VoltageSensor,
+};
 
+use faults::FaultLog;
 use sensor_api::{NoData, SensorId};
 use seq_api::PowerState;
 
@@ -35,21 +75,40 @@ include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 use i2c_config::sensors;
 
 enum Device {
-    IBC(Bmr491),
-    Core(Raa229618),
-    Mem(Raa229618),
-    MemVpp(Isl68224),
-    Sys(Tps546b24a),
-    HotSwap(Adm1272),
-    Fan(Adm1272),
+    Bmr491(Bmr491),
+    Raa229618(Raa229618),
+    Isl68224(Isl68224),
+    PmbusDevice(PmbusDevice),
+    Adm1272(Adm1272),
 }
 
 struct PowerController {
+    name: &'static str,
     state: seq_api::PowerState,
     device: Device,
     voltage: SensorId,
     current: SensorId,
     temperature: Option<SensorId>,
+
+    /// Most recently read fault status
+    status: FaultStatus,
+
+    /// Energy accounting, if the rail's controller supports it
+    energy: Option<Energy>,
+}
+
+struct Energy {
+    /// Sensor for the average power over the last poll interval
+    power: SensorId,
+
+    /// Sensor for the total energy consumed
+    energy: SensorId,
+
+    /// The previous accumulator reading, and the time it was made
+    last: Option<(EnergyCount, u64)>,
+
+    /// Energy consumed since accounting began
+    total: MilliJoules,
 }
 
 fn read_temperature<E, T: TempSensor<E>>(
@@ -82,6 +141,137 @@ where
     }
 }
 
+fn read_input_voltage<E, T: InputVoltageSensor<E>>(
+    device: &mut T,
+) -> Result<Volts, ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    match device.read_vin() {
+        Ok(reading) => Ok(reading),
+        Err(err) => {
+            let err: ResponseCode = err.into();
+            Err(err)
+        }
+    }
+}
+
+fn read_input_current<E, T: InputCurrentSensor<E>>(
+    device: &mut T,
+) -> Result<Amperes, ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    match device.read_iin() {
+        Ok(reading) => Ok(reading),
+        Err(err) => {
+            let err: ResponseCode = err.into();
+            Err(err)
+        }
+    }
+}
+
+fn read_power<E, T: PowerSensor<E>>(
+    device: &mut T,
+) -> Result<Watts, ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    match device.read_power() {
+        Ok(reading) => Ok(reading),
+        Err(err) => {
+            let err: ResponseCode = err.into();
+            Err(err)
+        }
+    }
+}
+
+///
+/// Reads the input energy accumulator at time `now`, and accounts for the
+/// energy consumed since the previous reading, returning the average power
+/// over that interval.  There is no such average for the first reading, or
+/// if the device took no samples in the interval.
+///
+fn account_energy<E, T: EnergySensor<E>>(
+    device: &mut T,
+    energy: &mut Energy,
+    now: u64,
+) -> Result<Option<Watts>, ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    let count = device.read_ein()?;
+
+    let (prev, then) = match energy.last.replace((count, now)) {
+        Some(last) => last,
+        None => return Ok(None),
+    };
+
+    let raw = match count.average_since(&prev) {
+        Some(raw) => raw,
+        None => return Ok(None),
+    };
+
+    let power = device.accumulated_power(raw)?;
+    let consumed = MilliWatts::from(power).over_millis(now - then);
+    energy.total = energy.total + consumed;
+
+    Ok(Some(power))
+}
+
+#[cfg(feature = "margining")]
+fn set_margin<E, T: drv_i2c_devices::Margining<E>>(
+    device: &mut T,
+    margin: drv_i2c_devices::Margin,
+) -> Result<(), ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    Ok(device.set_margin(margin)?)
+}
+
+fn read_margin_targets<E, T: VoltageRegulator<E>>(
+    device: &mut T,
+) -> Result<MarginTargets, ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    Ok(MarginTargets {
+        low: device.read_margin_low()?.0,
+        high: device.read_margin_high()?.0,
+    })
+}
+
+fn read_status<E, T: StatusSensor<E>>(
+    device: &mut T,
+) -> Result<PmbusStatus, ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    match device.read_status() {
+        Ok(status) => Ok(status),
+        Err(err) => {
+            let err: ResponseCode = err.into();
+            Err(err)
+        }
+    }
+}
+
+fn clear_faults<E, T: StatusSensor<E>>(
+    device: &mut T,
+) -> Result<(), ResponseCode>
+where
+    ResponseCode: From<E>,
+{
+    match device.clear_faults() {
+        Ok(()) => Ok(()),
+        Err(err) => {
+            let err: ResponseCode = err.into();
+            Err(err)
+        }
+    }
+}
+
 fn read_voltage<E, T: VoltageSensor<E>>(
     device: &mut T,
 ) -> Result<Volts, ResponseCode>
@@ -98,150 +288,248 @@ where
 }
 
 impl PowerController {
+    fn new(
+        name: &'static str,
+        state: PowerState,
+        device: Device,
+        voltage: SensorId,
+        current: SensorId,
+        temperature: Option<SensorId>,
+        energy: Option<(SensorId, SensorId)>,
+    ) -> Self {
+        Self {
+            name,
+            state,
+            device,
+            voltage,
+            current,
+            temperature,
+            status: FaultStatus::EMPTY,
+            energy: energy.map(|(power, energy)| Energy {
+                power,
+                energy,
+                last: None,
+                total: MilliJoules(0),
+            }),
+        }
+    }
+
     fn read_temperature(&mut self) -> Result<Celsius, ResponseCode> {
         match &mut self.device {
-            Device::IBC(dev) => read_temperature(dev),
-            Device::Core(dev) | Device::Mem(dev) => read_temperature(dev),
-            Device::MemVpp(_) => panic!(),
-            Device::Sys(dev) => read_temperature(dev),
-            Device::HotSwap(dev) | Device::Fan(dev) => read_temperature(dev),
+            Device::Bmr491(dev) => read_temperature(dev),
+            Device::Raa229618(dev) => read_temperature(dev),
+            Device::Isl68224(_) => panic!(),
+            Device::PmbusDevice(dev) => read_temperature(dev),
+            Device::Adm1272(dev) => read_temperature(dev),
         }
     }
 
     fn read_iout(&mut self) -> Result<Amperes, ResponseCode> {
         match &mut self.device {
-            Device::IBC(dev) => read_current(dev),
-            Device::Core(dev) | Device::Mem(dev) => read_current(dev),
-            Device::MemVpp(dev) => read_current(dev),
-            Device::Sys(dev) => read_current(dev),
-            Device::HotSwap(dev) | Device::Fan(dev) => read_current(dev),
+            Device::Bmr491(dev) => read_current(dev),
+            Device::Raa229618(dev) => read_current(dev),
+            Device::Isl68224(dev) => read_current(dev),
+            Device::PmbusDevice(dev) => read_current(dev),
+            Device::Adm1272(dev) => read_current(dev),
         }
     }
 
     fn read_vout(&mut self) -> Result<Volts, ResponseCode> {
         match &mut self.device {
-            Device::IBC(dev) => read_voltage(dev),
-            Device::Core(dev) | Device::Mem(dev) => read_voltage(dev),
-            Device::MemVpp(dev) => read_voltage(dev),
-            Device::Sys(dev) => read_voltage(dev),
-            Device::HotSwap(dev) | Device::Fan(dev) => read_voltage(dev),
+            Device::Bmr491(dev) => read_voltage(dev),
+            Device::Raa229618(dev) => read_voltage(dev),
+            Device::Isl68224(dev) => read_voltage(dev),
+            Device::PmbusDevice(dev) => read_voltage(dev),
+            Device::Adm1272(dev) => read_voltage(dev),
         }
     }
-}
 
-macro_rules! rail_controller {
-    ($task:expr, $which:ident, $dev:ident, $rail:ident, $state:ident) => {
-        paste::paste! {
-            PowerController {
-                state: seq_api::PowerState::$state,
-                device: Device::$which({
-                    let (device, rail) = i2c_config::pmbus::$rail($task);
-                    [<$dev:camel>]::new(&device, rail)
-                }),
-                voltage: sensors::[<$dev:upper _ $rail:upper _VOLTAGE_SENSOR>],
-                current: sensors::[<$dev:upper _ $rail:upper _CURRENT_SENSOR>],
-                temperature: Some(
-                    sensors::[<$dev:upper _ $rail:upper _TEMPERATURE_SENSOR>]
-                ),
-            }
+    fn read_vin(&mut self) -> Result<Volts, ResponseCode> {
+        match &mut self.device {
+            Device::Bmr491(dev) => read_input_voltage(dev),
+            Device::Raa229618(dev) => read_input_voltage(dev),
+            Device::Isl68224(dev) => read_input_voltage(dev),
+            Device::PmbusDevice(dev) => read_input_voltage(dev),
+            Device::Adm1272(dev) => read_input_voltage(dev),
         }
-    };
-}
+    }
 
-macro_rules! rail_controller_notemp {
-    ($task:expr, $which:ident, $dev:ident, $rail:ident, $state:ident) => {
-        paste::paste! {
-            PowerController {
-                state: seq_api::PowerState::$state,
-                device: Device::$which({
-                    let (device, rail) = i2c_config::pmbus::$rail($task);
-                    [<$dev:camel>]::new(&device, rail)
-                }),
-                voltage: sensors::[<$dev:upper _ $rail:upper _VOLTAGE_SENSOR>],
-                current: sensors::[<$dev:upper _ $rail:upper _CURRENT_SENSOR>],
-                temperature: None,
-            }
+    /// Reads the input current, returning `None` if the controller cannot
+    /// measure it.
+    fn read_iin(&mut self) -> Option<Result<Amperes, ResponseCode>> {
+        match &mut self.device {
+            Device::Bmr491(_) | Device::PmbusDevice(_) => None,
+            Device::Raa229618(dev) => Some(read_input_current(dev)),
+            Device::Isl68224(dev) => Some(read_input_current(dev)),
+            Device::Adm1272(dev) => Some(read_input_current(dev)),
         }
-    };
-}
+    }
 
-macro_rules! adm1272_controller {
-    ($task:expr, $which:ident, $rail:ident, $state:ident, $rsense:expr) => {
-        paste::paste! {
-            PowerController {
-                state: seq_api::PowerState::$state,
-                device: Device::$which({
-                    let (device, _) = i2c_config::pmbus::$rail($task);
-                    Adm1272::new(&device, $rsense)
-                }),
-                voltage: sensors::[<ADM1272_ $rail:upper _VOLTAGE_SENSOR>],
-                current: sensors::[<ADM1272_ $rail:upper _CURRENT_SENSOR>],
-                temperature: Some(
-                    sensors::[<ADM1272_ $rail:upper _TEMPERATURE_SENSOR>]
-                ),
-            }
+    fn read_power(&mut self) -> Result<Watts, ResponseCode> {
+        match &mut self.device {
+            Device::Bmr491(dev) => read_power(dev),
+            Device::Raa229618(dev) => read_power(dev),
+            Device::Isl68224(dev) => read_power(dev),
+            Device::PmbusDevice(dev) => read_power(dev),
+            Device::Adm1272(dev) => read_power(dev),
         }
-    };
+    }
+
+    fn account_energy(
+        &mut self,
+        now: u64,
+    ) -> Result<Option<Watts>, ResponseCode> {
+        let energy = match &mut self.energy {
+            Some(energy) => energy,
+            None => return Ok(None),
+        };
+
+        match &mut self.device {
+            Device::Raa229618(dev) => account_energy(dev, energy, now),
+            Device::Adm1272(dev) => account_energy(dev, energy, now),
+            _ => Ok(None),
+        }
+    }
+
+    /// Sets the margin state, returning `None` if the controller isn't a
+    /// voltage regulator whose margining has been checked.
+    #[cfg(feature = "margining")]
+    fn set_margin(
+        &mut self,
+        margin: Margin,
+    ) -> Option<Result<(), ResponseCode>> {
+        let margin = match margin {
+            Margin::Nominal => drv_i2c_devices::Margin::Nominal,
+            Margin::Low => drv_i2c_devices::Margin::Low,
+            Margin::High => drv_i2c_devices::Margin::High,
+        };
+
+        match &mut self.device {
+            Device::Bmr491(_) | Device::Adm1272(_) => None,
+            Device::PmbusDevice(_) => None,
+            Device::Raa229618(dev) => Some(set_margin(dev, margin)),
+            Device::Isl68224(dev) => Some(set_margin(dev, margin)),
+        }
+    }
+
+    /// Reads the margin targets, returning `None` if the controller isn't a
+    /// voltage regulator.
+    fn read_margin_targets(
+        &mut self,
+    ) -> Option<Result<MarginTargets, ResponseCode>> {
+        match &mut self.device {
+            Device::Bmr491(_) | Device::Adm1272(_) => None,
+            Device::Raa229618(dev) => Some(read_margin_targets(dev)),
+            Device::Isl68224(dev) => Some(read_margin_targets(dev)),
+            Device::PmbusDevice(dev) => Some(read_margin_targets(dev)),
+        }
+    }
+
+    /// Returns true if the rail is powered in power state `state`.
+    fn is_powered(&self, state: PowerState) -> bool {
+        !(self.state == PowerState::A0 && state != PowerState::A0)
+    }
+
+    fn readings(&mut self) -> RailReadings {
+        let temperature = match self.temperature {
+            Some(_) => self.read_temperature().map_or(f32::NAN, |t| t.0),
+            None => f32::NAN,
+        };
+
+        RailReadings {
+            vin: self.read_vin().map_or(f32::NAN, |v| v.0),
+            vout: self.read_vout().map_or(f32::NAN, |v| v.0),
+            iin: match self.read_iin() {
+                Some(Ok(reading)) => reading.0,
+                _ => f32::NAN,
+            },
+            iout: self.read_iout().map_or(f32::NAN, |i| i.0),
+            pout: self.read_power().map_or(f32::NAN, |p| p.0),
+            temperature,
+        }
+    }
+
+    fn read_status(&mut self) -> Result<FaultStatus, ResponseCode> {
+        let status = match &mut self.device {
+            Device::Bmr491(dev) => read_status(dev),
+            Device::Raa229618(dev) => read_status(dev),
+            Device::Isl68224(dev) => read_status(dev),
+            Device::PmbusDevice(dev) => read_status(dev),
+            Device::Adm1272(dev) => read_status(dev),
+        }?;
+
+        Ok(FaultStatus {
+            word: status.word,
+            vout: status.vout,
+            iout: status.iout,
+            input: status.input,
+            temperature: status.temperature,
+        })
+    }
+
+    fn clear_faults(&mut self) -> Result<(), ResponseCode> {
+        match &mut self.device {
+            Device::Bmr491(dev) => clear_faults(dev),
+            Device::Raa229618(dev) => clear_faults(dev),
+            Device::Isl68224(dev) => clear_faults(dev),
+            Device::PmbusDevice(dev) => clear_faults(dev),
+            Device::Adm1272(dev) => clear_faults(dev),
+        }
+    }
 }
 
-#[cfg(target_board = "gimlet-a")]
-fn controllers() -> [PowerController; 13] {
-    let task = I2C.get_task_id();
-
-    [
-        rail_controller!(task, IBC, bmr491, v12_sys_a2, A2),
-        rail_controller!(task, Core, raa229618, vdd_vcore, A0),
-        rail_controller!(task, Core, raa229618, vddcr_soc, A0),
-        rail_controller!(task, Mem, raa229618, vdd_mem_abcd, A0),
-        rail_controller!(task, Mem, raa229618, vdd_mem_efgh, A0),
-        rail_controller_notemp!(task, MemVpp, isl68224, vpp_abcd, A0),
-        rail_controller_notemp!(task, MemVpp, isl68224, vpp_efgh, A0),
-        rail_controller_notemp!(task, MemVpp, isl68224, v3p3_sys, A0),
-        rail_controller!(task, Sys, tps546b24a, v3p3_sp_a2, A2),
-        rail_controller!(task, Sys, tps546b24a, v1p8_sp3, A0),
-        rail_controller!(task, Sys, tps546b24a, v5_sys_a2, A2),
-        adm1272_controller!(task, HotSwap, v54_hs_output, A2, Ohms(0.001)),
-        adm1272_controller!(task, Fan, v54_fan, A2, Ohms(0.002)),
-    ]
+include!(concat!(env!("OUT_DIR"), "/power_config.rs"));
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    Fault(u16, FaultStatus),
+    StatusError(u16, ResponseCode),
+    ClearFaults(u16),
+    #[cfg(feature = "margining")]
+    Margin(u16, Margin),
+    None,
 }
 
-#[cfg(target_board = "gimlet-b")]
-fn controllers() -> [PowerController; 15] {
-    let task = I2C.get_task_id();
-
-    [
-        rail_controller!(task, IBC, bmr491, v12_sys_a2, A2),
-        rail_controller!(task, Core, raa229618, vdd_vcore, A0),
-        rail_controller!(task, Core, raa229618, vddcr_soc, A0),
-        rail_controller!(task, Mem, raa229618, vdd_mem_abcd, A0),
-        rail_controller!(task, Mem, raa229618, vdd_mem_efgh, A0),
-        rail_controller_notemp!(task, MemVpp, isl68224, vpp_abcd, A0),
-        rail_controller_notemp!(task, MemVpp, isl68224, vpp_efgh, A0),
-        rail_controller_notemp!(task, MemVpp, isl68224, v1p8_sp3, A0),
-        rail_controller!(task, Sys, tps546b24a, v3p3_sp_a2, A2),
-        rail_controller!(task, Sys, tps546b24a, v3p3_sys_a0, A0),
-        rail_controller!(task, Sys, tps546b24a, v5_sys_a2, A2),
-        rail_controller!(task, Sys, tps546b24a, v1p8_sys_a2, A2),
-        rail_controller!(task, Sys, tps546b24a, v0p96_nic_vdd_a0hp, A0),
-        adm1272_controller!(task, HotSwap, v54_hs_output, A2, Ohms(0.001)),
-        adm1272_controller!(task, Fan, v54_fan, A2, Ohms(0.002)),
-    ]
+ringbuf!(Trace, 16, Trace::None);
+
+struct ServerImpl {
+    sensor: sensor_api::Sensor,
+    sequencer: seq_api::Sequencer,
+    controllers: [PowerController; NUM_RAILS],
+    log: FaultLog,
+    deadline: u64,
 }
 
-#[export_name = "main"]
-fn main() -> ! {
-    let sensor = sensor_api::Sensor::from(SENSOR.get_task_id());
-    let sequencer = seq_api::Sequencer::from(SEQUENCER.get_task_id());
+const TIMER_MASK: u32 = 1 << 0;
+const TIMER_INTERVAL: u64 = 1000;
 
-    let mut controllers = controllers();
+/// Maximum length of a rail name
+const MAX_RAIL_NAME: usize = 32;
 
-    loop {
-        hl::sleep_for(1000);
+impl ServerImpl {
+    /// Returns the index of the rail named by `rail`.
+    fn rail(
+        &self,
+        rail: &LenLimit<Leased<R, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<usize, RequestError<PowerError>> {
+        let mut name = [0u8; MAX_RAIL_NAME];
+        let name = &mut name[..rail.len()];
+
+        rail.read_range(0..name.len(), name)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        self.controllers
+            .iter()
+            .position(|c| c.name.as_bytes() == name)
+            .ok_or_else(|| PowerError::InvalidRail.into())
+    }
 
-        let state = sequencer.get_state().unwrap();
+    fn poll(&mut self) {
+        let sensor = &self.sensor;
+        let state = self.sequencer.get_state().unwrap();
 
-        for c in &mut controllers {
-            if c.state == PowerState::A0 && state != PowerState::A0 {
+        for (ndx, c) in self.controllers.iter_mut().enumerate() {
+            if !c.is_powered(state) {
                 sensor.nodata(c.voltage, NoData::DeviceOff).unwrap();
                 sensor.nodata(c.current, NoData::DeviceOff).unwrap();
 
@@ -249,6 +537,15 @@ fn main() -> ! {
                     sensor.nodata(id, NoData::DeviceOff).unwrap();
                 }
 
+                //
+                // The controller's accumulator won't survive it being
+                // powered off, so we start afresh when it's next powered.
+                //
+                if let Some(energy) = &mut c.energy {
+                    energy.last = None;
+                    sensor.nodata(energy.power, NoData::DeviceOff).unwrap();
+                }
+
                 continue;
             }
 
@@ -280,6 +577,231 @@ fn main() -> ! {
                     sensor.nodata(c.voltage, NoData::DeviceError).unwrap();
                 }
             }
+
+            match c.account_energy(sys_get_timer().now) {
+                Ok(power) => {
+                    if let Some(energy) = &c.energy {
+                        if let Some(power) = power {
+                            sensor.post(energy.power, power.0).unwrap();
+                        }
+
+                        let total = Joules::from(energy.total);
+                        sensor.post(energy.energy, total.0).unwrap();
+                    }
+                }
+                Err(_) => {
+                    if let Some(energy) = &c.energy {
+                        sensor
+                            .nodata(energy.power, NoData::DeviceError)
+                            .unwrap();
+                        sensor
+                            .nodata(energy.energy, NoData::DeviceError)
+                            .unwrap();
+                    }
+                }
+            }
+
+            match c.read_status() {
+                Ok(status) if status != c.status => {
+                    ringbuf_entry!(Trace::Fault(ndx as u16, status));
+
+                    self.log.record(FaultRecord {
+                        timestamp: sys_get_timer().now,
+                        rail: ndx as u16,
+                        status,
+                    });
+
+                    c.status = status;
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    ringbuf_entry!(Trace::StatusError(ndx as u16, e));
+                }
+            }
+        }
+    }
+}
+
+impl idl::InOrderPowerImpl for ServerImpl {
+    fn num_rails(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<PowerError>> {
+        Ok(NUM_RAILS as u32)
+    }
+
+    fn rail_name(
+        &mut self,
+        _: &RecvMessage,
+        index: u32,
+        name: LenLimit<Leased<W, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<u32, RequestError<PowerError>> {
+        let rail = self
+            .controllers
+            .get(index as usize)
+            .ok_or(PowerError::InvalidRail)?
+            .name
+            .as_bytes();
+
+        let n = rail.len().min(name.len());
+
+        name.write_range(0..n, &rail[..n])
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        Ok(rail.len() as u32)
+    }
+
+    fn read_rail(
+        &mut self,
+        _: &RecvMessage,
+        rail: LenLimit<Leased<R, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<RailReadings, RequestError<PowerError>> {
+        let ndx = self.rail(&rail)?;
+        let state = self.sequencer.get_state().unwrap();
+        let c = &mut self.controllers[ndx];
+
+        if !c.is_powered(state) {
+            return Err(PowerError::RailOff.into());
+        }
+
+        Ok(c.readings())
+    }
+
+    fn get_fault_status(
+        &mut self,
+        _: &RecvMessage,
+        rail: LenLimit<Leased<R, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<FaultStatus, RequestError<PowerError>> {
+        let ndx = self.rail(&rail)?;
+        Ok(self.controllers[ndx].status)
+    }
+
+    #[cfg(not(feature = "margining"))]
+    fn set_margin(
+        &mut self,
+        _: &RecvMessage,
+        _: Margin,
+        _: LenLimit<Leased<R, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<(), RequestError<PowerError>> {
+        Err(PowerError::MarginingDisabled.into())
+    }
+
+    #[cfg(feature = "margining")]
+    fn set_margin(
+        &mut self,
+        _: &RecvMessage,
+        margin: Margin,
+        rail: LenLimit<Leased<R, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<(), RequestError<PowerError>> {
+        let ndx = self.rail(&rail)?;
+        let state = self.sequencer.get_state().unwrap();
+        let c = &mut self.controllers[ndx];
+
+        if !c.is_powered(state) {
+            return Err(PowerError::RailOff.into());
+        }
+
+        ringbuf_entry!(Trace::Margin(ndx as u16, margin));
+
+        match c.set_margin(margin) {
+            Some(Ok(())) => Ok(()),
+            Some(Err(_)) => Err(PowerError::DeviceError.into()),
+            None => Err(PowerError::Unsupported.into()),
+        }
+    }
+
+    fn get_margin_targets(
+        &mut self,
+        _: &RecvMessage,
+        rail: LenLimit<Leased<R, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<MarginTargets, RequestError<PowerError>> {
+        let ndx = self.rail(&rail)?;
+        let state = self.sequencer.get_state().unwrap();
+        let c = &mut self.controllers[ndx];
+
+        if !c.is_powered(state) {
+            return Err(PowerError::RailOff.into());
+        }
+
+        match c.read_margin_targets() {
+            Some(Ok(targets)) => Ok(targets),
+            Some(Err(_)) => Err(PowerError::DeviceError.into()),
+            None => Err(PowerError::Unsupported.into()),
+        }
+    }
+
+    fn get_fault_record(
+        &mut self,
+        _: &RecvMessage,
+        index: u32,
+    ) -> Result<FaultRecord, RequestError<PowerError>> {
+        match self.log.get(index as usize) {
+            Some(record) => Ok(*record),
+            None => Err(PowerError::NoRecord.into()),
+        }
+    }
+
+    fn clear_faults(
+        &mut self,
+        _: &RecvMessage,
+        rail: LenLimit<Leased<R, [u8]>, MAX_RAIL_NAME>,
+    ) -> Result<(), RequestError<PowerError>> {
+        let ndx = self.rail(&rail)?;
+
+        ringbuf_entry!(Trace::ClearFaults(ndx as u16));
+
+        //
+        // The cleared status will be recorded (and logged) on the next poll.
+        //
+        match self.controllers[ndx].clear_faults() {
+            Ok(()) => Ok(()),
+            Err(_) => Err(PowerError::DeviceError.into()),
         }
     }
 }
+
+impl NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        TIMER_MASK
+    }
+
+    fn handle_notification(&mut self, _bits: u32) {
+        self.deadline += TIMER_INTERVAL;
+        sys_set_timer(Some(self.deadline), TIMER_MASK);
+
+        self.poll();
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let deadline = sys_get_timer().now;
+
+    //
+    // This will put our timer in the past, and should immediately kick us.
+    //
+    sys_set_timer(Some(deadline), TIMER_MASK);
+
+    let mut server = ServerImpl {
+        sensor: sensor_api::Sensor::from(SENSOR.get_task_id()),
+        sequencer: seq_api::Sequencer::from(SEQUENCER.get_task_id()),
+        controllers: controllers(I2C.get_task_id()),
+        log: FaultLog::new(),
+        deadline,
+    };
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+
+    loop {
+        idol_runtime::dispatch_n(&mut buffer, &mut server);
+    }
+}
+
+mod idl {
+    use super::{
+        FaultRecord, FaultStatus, Margin, MarginTargets, PowerError,
+        RailReadings,
+    };
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
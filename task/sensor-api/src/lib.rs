@@ -8,6 +8,7 @@
 
 use drv_i2c_api::ResponseCode;
 use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
 
 #[derive(zerocopy::AsBytes, Copy, Clone, Debug, PartialEq)]
 #[repr(C)]
@@ -64,6 +65,8 @@ pub enum SensorError {
     DeviceUnavailable = 5,
     DeviceTimeout = 6,
     DeviceOff = 7,
+    BadThresholds = 8,
+    TooManySubscribers = 9,
 }
 
 impl From<NoData> for SensorError {
@@ -78,6 +81,470 @@ impl From<NoData> for SensorError {
     }
 }
 
+/// The most recent value of a sensor, along with the time (in kernel ticks)
+/// at which it was posted.
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct SensorReading {
+    pub value: f32,
+    pub timestamp: u64,
+}
+
+/// The full record kept for a sensor:  its most recent value and most recent
+/// error (each with the time at which it was posted), along with statistics
+/// accumulated since the record was last reset.
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct SensorRecord {
+    /// Most recent value, if `has_value` is set
+    value: f32,
+    timestamp: u64,
+
+    /// Minimum, maximum and mean of the `count` values posted since reset
+    pub min: f32,
+    pub max: f32,
+    pub average: f32,
+    pub count: u32,
+
+    /// Number of errors posted since reset
+    pub errors: u32,
+
+    /// Most recent error, if `last_error` is non-zero
+    error_timestamp: u64,
+
+    /// The `NoData` of the most recent error plus one, or 0 if none
+    last_error: u8,
+    has_value: u8,
+}
+
+impl SensorRecord {
+    pub const EMPTY: Self = Self {
+        value: 0.0,
+        timestamp: 0,
+        min: 0.0,
+        max: 0.0,
+        average: 0.0,
+        count: 0,
+        errors: 0,
+        error_timestamp: 0,
+        last_error: 0,
+        has_value: 0,
+    };
+
+    /// Returns the most recent value, or `None` if no value has been posted.
+    pub fn reading(&self) -> Option<SensorReading> {
+        if self.has_value != 0 {
+            Some(SensorReading {
+                value: self.value,
+                timestamp: self.timestamp,
+            })
+        } else {
+            None
+        }
+    }
+
+    /// Returns the most recent error and the time at which it was posted,
+    /// or `None` if no error has been posted.  (Unlike the statistics, this
+    /// is not cleared by a reset.)
+    pub fn last_error(&self) -> Option<(NoData, u64)> {
+        let nodata = NoData::from_u8(self.last_error.checked_sub(1)?)?;
+        Some((nodata, self.error_timestamp))
+    }
+
+    /// Records `value`, posted at `now`.
+    pub fn record_value(&mut self, value: f32, now: u64) {
+        self.value = value;
+        self.timestamp = now;
+        self.has_value = 1;
+
+        if self.count == 0 {
+            self.min = value;
+            self.max = value;
+            self.average = value;
+        } else {
+            self.min = self.min.min(value);
+            self.max = self.max.max(value);
+            self.average += (value - self.average) / (self.count + 1) as f32;
+        }
+
+        self.count = self.count.saturating_add(1);
+    }
+
+    /// Records the error `nodata`, posted at `now`.
+    pub fn record_error(&mut self, nodata: NoData, now: u64) {
+        self.last_error = nodata as u8 + 1;
+        self.error_timestamp = now;
+        self.errors = self.errors.saturating_add(1);
+    }
+
+    /// Resets the statistics, retaining the most recent value and error.
+    pub fn reset_stats(&mut self) {
+        self.min = 0.0;
+        self.max = 0.0;
+        self.average = 0.0;
+        self.count = 0;
+        self.errors = 0;
+    }
+}
+
+/// The kind of quantity that a sensor measures.
+#[derive(zerocopy::AsBytes, Copy, Clone, Debug, FromPrimitive, PartialEq)]
+#[repr(u8)]
+pub enum SensorKind {
+    Temperature = 0,
+    Power = 1,
+    Current = 2,
+    Voltage = 3,
+    Speed = 4,
+    Energy = 5,
+}
+
+impl SensorKind {
+    /// Returns the unit in which the sensor's readings are expressed, as
+    /// named in `userlib::units`.
+    pub fn unit(&self) -> &'static str {
+        match self {
+            SensorKind::Temperature => "Celsius",
+            SensorKind::Power => "Watts",
+            SensorKind::Current => "Amperes",
+            SensorKind::Voltage => "Volts",
+            SensorKind::Speed => "Rpm",
+            SensorKind::Energy => "Joules",
+        }
+    }
+}
+
+/// Static description of a sensor, as generated by `build/i2c` into
+/// `sensors::METADATA`.  Fields that are not configured are empty.
+#[derive(Copy, Clone, Debug)]
+pub struct SensorMetadata {
+    pub name: &'static str,
+    pub kind: SensorKind,
+    pub device: &'static str,
+    pub bus: &'static str,
+    pub refdes: &'static str,
+}
+
+/// A sensor's metadata as returned by [`Sensor::get_info`].  Strings are
+/// NUL-padded, and truncated if they don't fit.
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct SensorInfo {
+    /// The `SensorKind`, from which the unit follows
+    kind: u8,
+    name: [u8; 32],
+    device: [u8; 16],
+    bus: [u8; 16],
+    refdes: [u8; 16],
+}
+
+fn pad<const N: usize>(s: &str) -> [u8; N] {
+    let mut buf = [0; N];
+    let n = s.len().min(N);
+    buf[..n].copy_from_slice(&s.as_bytes()[..n]);
+    buf
+}
+
+fn unpad(buf: &[u8]) -> &str {
+    let n = buf.iter().position(|&c| c == 0).unwrap_or(buf.len());
+
+    match core::str::from_utf8(&buf[..n]) {
+        Ok(s) => s,
+        Err(e) => core::str::from_utf8(&buf[..e.valid_up_to()]).unwrap(),
+    }
+}
+
+impl From<&SensorMetadata> for SensorInfo {
+    fn from(m: &SensorMetadata) -> Self {
+        Self {
+            kind: m.kind as u8,
+            name: pad(m.name),
+            device: pad(m.device),
+            bus: pad(m.bus),
+            refdes: pad(m.refdes),
+        }
+    }
+}
+
+impl SensorInfo {
+    pub fn kind(&self) -> Option<SensorKind> {
+        SensorKind::from_u8(self.kind)
+    }
+
+    /// Returns the unit of the sensor's readings, or `None` if the kind is
+    /// unknown to this client.
+    pub fn unit(&self) -> Option<&'static str> {
+        self.kind().map(|kind| kind.unit())
+    }
+
+    pub fn name(&self) -> &str {
+        unpad(&self.name)
+    }
+
+    pub fn device(&self) -> &str {
+        unpad(&self.device)
+    }
+
+    pub fn bus(&self) -> &str {
+        unpad(&self.bus)
+    }
+
+    pub fn refdes(&self) -> &str {
+        unpad(&self.refdes)
+    }
+}
+
+/// A reading as returned by [`Sensor::bulk_get`]:  the state of a single
+/// sensor, and the time at which that state was posted (or 0 if the sensor
+/// has never been posted).
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct PackedReading {
+    value: f32,
+    timestamp: u64,
+    status: u8,
+}
+
+impl PackedReading {
+    const ABSENT: u8 = 0;
+    const VALUE: u8 = 1;
+
+    /// `NoData` values are encoded in the status as the value plus this
+    const NODATA: u8 = 2;
+
+    pub const EMPTY: Self = Self {
+        value: 0.0,
+        timestamp: 0,
+        status: Self::ABSENT,
+    };
+
+    pub fn new(reading: Reading, timestamp: u64) -> Self {
+        match reading {
+            Reading::Absent => Self::EMPTY,
+            Reading::Value(value) => Self {
+                value,
+                timestamp,
+                status: Self::VALUE,
+            },
+            Reading::NoData(nodata) => Self {
+                value: 0.0,
+                timestamp,
+                status: Self::NODATA + nodata as u8,
+            },
+        }
+    }
+
+    pub fn reading(&self) -> Reading {
+        match self.status {
+            Self::VALUE => Reading::Value(self.value),
+            status if status >= Self::NODATA => {
+                match NoData::from_u8(status - Self::NODATA) {
+                    Some(nodata) => Reading::NoData(nodata),
+                    None => Reading::Absent,
+                }
+            }
+            _ => Reading::Absent,
+        }
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+}
+
+/// Decodes the packed readings written by [`Sensor::bulk_get`] into `buf`.
+pub fn decode_readings(buf: &[u8]) -> impl Iterator<Item = PackedReading> + '_ {
+    buf.chunks_exact(core::mem::size_of::<PackedReading>())
+        .filter_map(PackedReading::read_from)
+}
+
+/// A downsampled summary of the values of a sensor over an interval, as
+/// returned by [`Sensor::get_history`].  `start` is the time (in kernel
+/// ticks) of the first value in the bucket.
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug)]
+#[repr(C, packed)]
+pub struct HistoryBucket {
+    pub start: u64,
+    pub min: f32,
+    pub max: f32,
+    pub average: f32,
+    pub count: u32,
+}
+
+impl HistoryBucket {
+    pub const EMPTY: Self = Self {
+        start: 0,
+        min: 0.0,
+        max: 0.0,
+        average: 0.0,
+        count: 0,
+    };
+
+    /// Returns a bucket starting at `now` that holds only `value`.
+    pub fn new(value: f32, now: u64) -> Self {
+        Self {
+            start: now,
+            min: value,
+            max: value,
+            average: value,
+            count: 1,
+        }
+    }
+
+    /// Adds `value` to the bucket.
+    pub fn add(&mut self, value: f32) {
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+        self.average += (value - self.average) / (self.count + 1) as f32;
+        self.count = self.count.saturating_add(1);
+    }
+}
+
+/// The amount of history written by [`Sensor::get_history`]:  `samples`
+/// recent values (as [`SensorReading`]s) followed by `buckets`
+/// [`HistoryBucket`]s, each oldest first.
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct HistoryCounts {
+    pub samples: u32,
+    pub buckets: u32,
+}
+
+/// Decodes the history written by [`Sensor::get_history`] into `buf`,
+/// returning iterators over the recent values and the buckets.
+pub fn decode_history(
+    buf: &[u8],
+    counts: HistoryCounts,
+) -> (
+    impl Iterator<Item = SensorReading> + '_,
+    impl Iterator<Item = HistoryBucket> + '_,
+) {
+    const SAMPLE_SIZE: usize = core::mem::size_of::<SensorReading>();
+    const BUCKET_SIZE: usize = core::mem::size_of::<HistoryBucket>();
+
+    let split = (counts.samples as usize * SAMPLE_SIZE).min(buf.len());
+    let (samples, buckets) = buf.split_at(split);
+
+    (
+        samples
+            .chunks_exact(SAMPLE_SIZE)
+            .filter_map(SensorReading::read_from),
+        buckets
+            .chunks_exact(BUCKET_SIZE)
+            .take(counts.buckets as usize)
+            .filter_map(HistoryBucket::read_from),
+    )
+}
+
+/// Alarm thresholds for a sensor.  A value at or beyond a threshold asserts
+/// the corresponding alarm; the alarm is cleared when the value recedes
+/// past the threshold by more than `hysteresis`.  An absent threshold is
+/// represented by an infinity of the appropriate sign.
+#[derive(zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug)]
+#[repr(C)]
+pub struct Thresholds {
+    pub lower_critical: f32,
+    pub lower_warning: f32,
+    pub upper_warning: f32,
+    pub upper_critical: f32,
+    pub hysteresis: f32,
+}
+
+impl Thresholds {
+    pub const NONE: Self = Self {
+        lower_critical: f32::NEG_INFINITY,
+        lower_warning: f32::NEG_INFINITY,
+        upper_warning: f32::INFINITY,
+        upper_critical: f32::INFINITY,
+        hysteresis: 0.0,
+    };
+
+    /// Returns true if the thresholds are correctly ordered and the
+    /// hysteresis is non-negative.  (This is false if any value is NaN.)
+    pub fn is_valid(&self) -> bool {
+        self.lower_critical <= self.lower_warning
+            && self.lower_warning < self.upper_warning
+            && self.upper_warning <= self.upper_critical
+            && self.hysteresis >= 0.0
+    }
+
+    /// Determines the alarms for `value`, given the alarms previously
+    /// asserted.
+    pub fn evaluate(&self, value: f32, current: Alarms) -> Alarms {
+        let h = self.hysteresis;
+
+        let lower = |threshold: f32, bit: u8| {
+            if value <= threshold
+                || (current.0 & bit != 0 && value <= threshold + h)
+            {
+                bit
+            } else {
+                0
+            }
+        };
+
+        let upper = |threshold: f32, bit: u8| {
+            if value >= threshold
+                || (current.0 & bit != 0 && value >= threshold - h)
+            {
+                bit
+            } else {
+                0
+            }
+        };
+
+        Alarms(
+            lower(self.lower_critical, Alarms::LOWER_CRITICAL)
+                | lower(self.lower_warning, Alarms::LOWER_WARNING)
+                | upper(self.upper_warning, Alarms::UPPER_WARNING)
+                | upper(self.upper_critical, Alarms::UPPER_CRITICAL),
+        )
+    }
+}
+
+/// The set of alarms asserted for a sensor.
+#[derive(
+    zerocopy::AsBytes, zerocopy::FromBytes, Copy, Clone, Debug, PartialEq,
+)]
+#[repr(transparent)]
+pub struct Alarms(pub u8);
+
+impl Alarms {
+    pub const NONE: Self = Alarms(0);
+
+    pub const LOWER_CRITICAL: u8 = 1 << 0;
+    pub const LOWER_WARNING: u8 = 1 << 1;
+    pub const UPPER_WARNING: u8 = 1 << 2;
+    pub const UPPER_CRITICAL: u8 = 1 << 3;
+
+    pub fn is_empty(&self) -> bool {
+        self.0 == 0
+    }
+
+    pub fn is_critical(&self) -> bool {
+        self.0 & (Self::LOWER_CRITICAL | Self::UPPER_CRITICAL) != 0
+    }
+}
+
+impl Sensor {
+    /// Reads sensors `start` onwards into `readings`, returning the number of
+    /// readings filled in (which is short if there are fewer sensors than
+    /// readings).
+    pub fn get_many(
+        &self,
+        start: SensorId,
+        readings: &mut [PackedReading],
+    ) -> Result<usize, SensorError> {
+        let n = self.bulk_get(
+            start,
+            readings.len() as u32,
+            readings.as_bytes_mut(),
+        )?;
+
+        Ok(n as usize)
+    }
+}
+
 impl From<SensorError> for u16 {
     fn from(rc: SensorError) -> Self {
         rc as u16
@@ -2,6 +2,48 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize, Default)]
+struct TaskConfig {
+    #[serde(default)]
+    history: HistoryConfig,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HistoryConfig {
+    /// number of recent values kept for each sensor
+    #[serde(default)]
+    depth: usize,
+
+    /// number of downsampled buckets kept for each sensor
+    #[serde(default)]
+    buckets: usize,
+
+    /// interval covered by each bucket, in milliseconds
+    #[serde(default = "default_interval")]
+    interval: u64,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            depth: 0,
+            buckets: 0,
+            interval: default_interval(),
+        }
+    }
+}
+
+fn default_interval() -> u64 {
+    10_000
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
     build_i2c::codegen(build_i2c::Disposition::Sensors)?;
@@ -12,5 +54,37 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         idol::server::ServerStyle::InOrder,
     )?;
 
+    //
+    // History is optional:  without any task configuration, none is kept.
+    //
+    let config = if env::var("HUBRIS_TASK_CONFIG").is_ok() {
+        build_util::task_config::<TaskConfig>()?
+    } else {
+        println!("cargo:rerun-if-env-changed=HUBRIS_TASK_CONFIG");
+        TaskConfig::default()
+    };
+
+    let history = config.history;
+
+    if history.buckets != 0 && history.interval == 0 {
+        return Err("sensor history interval must be non-zero".into());
+    }
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("history_config.rs");
+    let mut file = File::create(&dest_path)?;
+
+    writeln!(file, "pub const HISTORY_DEPTH: usize = {};", history.depth)?;
+    writeln!(
+        file,
+        "pub const HISTORY_BUCKETS: usize = {};",
+        history.buckets
+    )?;
+    writeln!(
+        file,
+        "pub const HISTORY_INTERVAL: u64 = {};",
+        history.interval
+    )?;
+
     Ok(())
 }
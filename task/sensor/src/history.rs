@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-sensor history
+//!
+//! For each sensor, we keep the most recent `depth` values in a ring, and a
+//! longer ring of `buckets` downsampled buckets, each summarizing the values
+//! posted over `interval` milliseconds; these are configured in the task's
+//! `[tasks.sensor.config.history]`.  The history for all sensors lives in a
+//! single static, [`HISTORY`], so that it can be found (and interpreted) in a
+//! dump.
+
+use core::sync::atomic::{AtomicBool, Ordering};
+use task_sensor_api::{HistoryBucket, SensorReading};
+
+use crate::sensors::NUM_SENSORS;
+
+include!(concat!(env!("OUT_DIR"), "/history_config.rs"));
+
+pub struct History {
+    /// Ring of recent values: `nsamples` of them, the oldest at `sample`
+    samples: [SensorReading; HISTORY_DEPTH],
+    sample: usize,
+    nsamples: usize,
+
+    /// Ring of completed buckets: `nbuckets` of them, the oldest at `bucket`
+    buckets: [HistoryBucket; HISTORY_BUCKETS],
+    bucket: usize,
+    nbuckets: usize,
+
+    /// The bucket currently being filled, if its count is non-zero
+    current: HistoryBucket,
+}
+
+static mut HISTORY: [History; NUM_SENSORS] = [History::EMPTY; NUM_SENSORS];
+
+/// Returns the history of all sensors.  This can only be called once.
+pub fn claim_history() -> &'static mut [History; NUM_SENSORS] {
+    static TAKEN: AtomicBool = AtomicBool::new(false);
+
+    if TAKEN.swap(true, Ordering::Relaxed) {
+        panic!()
+    }
+
+    // Safety: unsafe because of reference to mutable static; safe because
+    // the AtomicBool swap above means that this is the only reference that
+    // will ever be made to it.
+    unsafe { &mut HISTORY }
+}
+
+//
+// The ring arithmetic is generic over the size of the ring so that the
+// compiler doesn't object to our (unreachable) division by zero when history
+// is not configured.
+//
+
+/// Returns the index of entry `i` of a ring of `N` entries starting at
+/// `head`.
+fn ring_index<const N: usize>(head: usize, i: usize) -> usize {
+    (head + i) % N
+}
+
+/// Pushes into a ring of `N` entries holding `len` entries starting at
+/// `head`, returning the index to write, and overwriting the oldest entry if
+/// the ring is full.
+fn ring_push<const N: usize>(head: &mut usize, len: &mut usize) -> usize {
+    if *len == N {
+        let index = *head;
+        *head = ring_index::<N>(*head, 1);
+        index
+    } else {
+        *len += 1;
+        ring_index::<N>(*head, *len - 1)
+    }
+}
+
+impl History {
+    const EMPTY: Self = Self {
+        samples: [SensorReading {
+            value: 0.0,
+            timestamp: 0,
+        }; HISTORY_DEPTH],
+        sample: 0,
+        nsamples: 0,
+        buckets: [HistoryBucket::EMPTY; HISTORY_BUCKETS],
+        bucket: 0,
+        nbuckets: 0,
+        current: HistoryBucket::EMPTY,
+    };
+
+    /// Records `value`, posted at `now`.
+    pub fn record(&mut self, value: f32, now: u64) {
+        if HISTORY_DEPTH != 0 {
+            let index = ring_push::<HISTORY_DEPTH>(
+                &mut self.sample,
+                &mut self.nsamples,
+            );
+            self.samples[index] = SensorReading {
+                value,
+                timestamp: now,
+            };
+        }
+
+        if HISTORY_BUCKETS == 0 {
+            return;
+        }
+
+        if self.current.count != 0
+            && now >= self.current.start + HISTORY_INTERVAL
+        {
+            let index = ring_push::<HISTORY_BUCKETS>(
+                &mut self.bucket,
+                &mut self.nbuckets,
+            );
+            self.buckets[index] = self.current;
+            self.current = HistoryBucket::EMPTY;
+        }
+
+        if self.current.count == 0 {
+            self.current = HistoryBucket::new(value, now);
+        } else {
+            self.current.add(value);
+        }
+    }
+
+    /// Returns the recent values, oldest first.
+    pub fn samples(&self) -> impl Iterator<Item = &SensorReading> {
+        (0..self.nsamples).map(move |i| {
+            &self.samples[ring_index::<HISTORY_DEPTH>(self.sample, i)]
+        })
+    }
+
+    /// Returns the buckets, oldest first.  The last of these is the bucket
+    /// currently being filled, if any.
+    pub fn buckets(&self) -> impl Iterator<Item = &HistoryBucket> {
+        (0..self.nbuckets)
+            .map(move |i| {
+                &self.buckets[ring_index::<HISTORY_BUCKETS>(self.bucket, i)]
+            })
+            .chain(Some(&self.current).filter(|b| b.count != 0))
+    }
+
+    pub fn nsamples(&self) -> usize {
+        self.nsamples
+    }
+
+    pub fn nbuckets(&self) -> usize {
+        self.nbuckets + if self.current.count != 0 { 1 } else { 0 }
+    }
+}
@@ -7,11 +7,19 @@
 #![no_std]
 #![no_main]
 
-use idol_runtime::{NotificationHandler, RequestError};
-use task_sensor_api::{NoData, Reading, SensorError, SensorId};
+mod history;
+
+use history::History;
+use idol_runtime::{Leased, NotificationHandler, RequestError, W};
+use task_sensor_api::{
+    Alarms, HistoryBucket, HistoryCounts, NoData, PackedReading, Reading,
+    SensorError, SensorId, SensorInfo, SensorReading, SensorRecord, Thresholds,
+};
 use userlib::*;
+use zerocopy::AsBytes;
 
-// This is only included to determine the number of sensors
+// This is included to determine the number of sensors, along with their
+// default thresholds and metadata
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 
 use i2c_config::sensors;
@@ -19,12 +27,44 @@ use sensors::NUM_SENSORS;
 
 struct ServerImpl {
     data: [Reading; NUM_SENSORS],
+    records: [SensorRecord; NUM_SENSORS],
+    history: &'static mut [History; NUM_SENSORS],
+    thresholds: [Thresholds; NUM_SENSORS],
+    alarms: [Alarms; NUM_SENSORS],
+    subscribers: [Option<(TaskId, u32)>; MAX_ALARM_SUBSCRIBERS],
     deadline: u64,
 }
 
 const TIMER_MASK: u32 = 1 << 0;
 const TIMER_INTERVAL: u64 = 1000;
 
+/// Number of tasks that can subscribe to alarm notifications.
+const MAX_ALARM_SUBSCRIBERS: usize = 4;
+
+impl ServerImpl {
+    /// Re-evaluates the alarms for sensor `index` against `value`,
+    /// notifying subscribers if they have changed.
+    fn check_alarms(&mut self, index: usize, value: f32) {
+        let alarms = self.thresholds[index].evaluate(value, self.alarms[index]);
+
+        if alarms != self.alarms[index] {
+            self.alarms[index] = alarms;
+
+            for subscriber in self.subscribers.iter_mut() {
+                if let Some((task, notification)) = *subscriber {
+                    //
+                    // If the post fails, the subscriber has died; it must
+                    // subscribe again if it wants notifications.
+                    //
+                    if sys_post(task, notification) != 0 {
+                        *subscriber = None;
+                    }
+                }
+            }
+        }
+    }
+}
+
 impl idl::InOrderSensorImpl for ServerImpl {
     fn get(
         &mut self,
@@ -56,7 +96,11 @@ impl idl::InOrderSensorImpl for ServerImpl {
         let index = id.0;
 
         if index < NUM_SENSORS {
+            let now = sys_get_timer().now;
             self.data[index] = Reading::Value(value);
+            self.records[index].record_value(value, now);
+            self.history[index].record(value, now);
+            self.check_alarms(index, value);
             Ok(())
         } else {
             Err(SensorError::InvalidSensor.into())
@@ -73,11 +117,256 @@ impl idl::InOrderSensorImpl for ServerImpl {
 
         if index < NUM_SENSORS {
             self.data[index] = Reading::NoData(nodata);
+            self.records[index].record_error(nodata, sys_get_timer().now);
+            Ok(())
+        } else {
+            Err(SensorError::InvalidSensor.into())
+        }
+    }
+
+    fn get_reading(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<SensorReading, RequestError<SensorError>> {
+        let index = id.0;
+
+        if index < NUM_SENSORS {
+            match self.data[index] {
+                Reading::Absent => Err(SensorError::NoReading.into()),
+                Reading::NoData(nodata) => {
+                    let err: SensorError = nodata.into();
+                    Err(err.into())
+                }
+                Reading::Value(_) => Ok(self.records[index].reading().unwrap()),
+            }
+        } else {
+            Err(SensorError::InvalidSensor.into())
+        }
+    }
+
+    fn get_record(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<SensorRecord, RequestError<SensorError>> {
+        let index = id.0;
+
+        if index < NUM_SENSORS {
+            Ok(self.records[index])
+        } else {
+            Err(SensorError::InvalidSensor.into())
+        }
+    }
+
+    fn reset_stats(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<(), RequestError<SensorError>> {
+        let index = id.0;
+
+        if index < NUM_SENSORS {
+            self.records[index].reset_stats();
             Ok(())
         } else {
             Err(SensorError::InvalidSensor.into())
         }
     }
+
+    fn get_thresholds(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<Thresholds, RequestError<SensorError>> {
+        let index = id.0;
+
+        if index < NUM_SENSORS {
+            Ok(self.thresholds[index])
+        } else {
+            Err(SensorError::InvalidSensor.into())
+        }
+    }
+
+    fn set_thresholds(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+        thresholds: Thresholds,
+    ) -> Result<(), RequestError<SensorError>> {
+        let index = id.0;
+
+        if index >= NUM_SENSORS {
+            return Err(SensorError::InvalidSensor.into());
+        }
+
+        if !thresholds.is_valid() {
+            return Err(SensorError::BadThresholds.into());
+        }
+
+        self.thresholds[index] = thresholds;
+
+        if let Reading::Value(value) = self.data[index] {
+            self.check_alarms(index, value);
+        }
+
+        Ok(())
+    }
+
+    /// Subscribes the caller to alarm notifications, replacing any existing
+    /// subscription.  A `notification` of 0 unsubscribes.
+    fn subscribe_alarms(
+        &mut self,
+        msg: &RecvMessage,
+        notification: u32,
+    ) -> Result<(), RequestError<SensorError>> {
+        let sender = msg.sender;
+
+        //
+        // Any subscription held by this task (or a previous generation of
+        // it) is replaced.
+        //
+        for subscriber in self.subscribers.iter_mut() {
+            match subscriber {
+                Some((task, _)) if task.index() == sender.index() => {
+                    *subscriber = None;
+                }
+                _ => {}
+            }
+        }
+
+        if notification == 0 {
+            return Ok(());
+        }
+
+        let slot = self
+            .subscribers
+            .iter_mut()
+            .find(|s| s.is_none())
+            .ok_or(SensorError::TooManySubscribers)?;
+
+        *slot = Some((sender, notification));
+        Ok(())
+    }
+
+    fn get_alarms(
+        &mut self,
+        _: &RecvMessage,
+        alarms: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<SensorError>> {
+        let n = alarms.len().min(NUM_SENSORS);
+
+        alarms
+            .write_range(0..n, self.alarms[..n].as_bytes())
+            .map_err(|_| RequestError::went_away())?;
+
+        Ok(self.alarms.iter().filter(|a| !a.is_empty()).count() as u32)
+    }
+
+    fn bulk_get(
+        &mut self,
+        _: &RecvMessage,
+        start: SensorId,
+        count: u32,
+        readings: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<SensorError>> {
+        let start = start.0;
+
+        if start >= NUM_SENSORS {
+            return Err(SensorError::InvalidSensor.into());
+        }
+
+        const SIZE: usize = core::mem::size_of::<PackedReading>();
+
+        let n = (NUM_SENSORS - start)
+            .min(count as usize)
+            .min(readings.len() / SIZE);
+
+        for i in 0..n {
+            let index = start + i;
+            let reading = self.data[index];
+            let record = &self.records[index];
+
+            let timestamp = match reading {
+                Reading::Absent => 0,
+                Reading::Value(_) => {
+                    record.reading().map(|r| r.timestamp).unwrap_or(0)
+                }
+                Reading::NoData(_) => {
+                    record.last_error().map(|(_, t)| t).unwrap_or(0)
+                }
+            };
+
+            let packed = PackedReading::new(reading, timestamp);
+
+            readings
+                .write_range(i * SIZE..(i + 1) * SIZE, packed.as_bytes())
+                .map_err(|_| RequestError::went_away())?;
+        }
+
+        Ok(n as u32)
+    }
+
+    fn get_info(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+    ) -> Result<SensorInfo, RequestError<SensorError>> {
+        let index = id.0;
+
+        if index < NUM_SENSORS {
+            Ok(SensorInfo::from(&sensors::METADATA[index]))
+        } else {
+            Err(SensorError::InvalidSensor.into())
+        }
+    }
+
+    fn get_history(
+        &mut self,
+        _: &RecvMessage,
+        id: SensorId,
+        history: Leased<W, [u8]>,
+    ) -> Result<HistoryCounts, RequestError<SensorError>> {
+        let index = id.0;
+
+        if index >= NUM_SENSORS {
+            return Err(SensorError::InvalidSensor.into());
+        }
+
+        const SAMPLE_SIZE: usize = core::mem::size_of::<SensorReading>();
+        const BUCKET_SIZE: usize = core::mem::size_of::<HistoryBucket>();
+
+        //
+        // If the lease can't hold all of the history, we write as many of
+        // the most recent samples as will fit, followed by as many of the
+        // most recent buckets as will fit in what remains.
+        //
+        let h = &self.history[index];
+        let samples = h.nsamples().min(history.len() / SAMPLE_SIZE);
+        let remaining = history.len() - samples * SAMPLE_SIZE;
+        let buckets = h.nbuckets().min(remaining / BUCKET_SIZE);
+
+        let mut offset = 0;
+
+        for sample in h.samples().skip(h.nsamples() - samples) {
+            history
+                .write_range(offset..offset + SAMPLE_SIZE, sample.as_bytes())
+                .map_err(|_| RequestError::went_away())?;
+            offset += SAMPLE_SIZE;
+        }
+
+        for bucket in h.buckets().skip(h.nbuckets() - buckets) {
+            history
+                .write_range(offset..offset + BUCKET_SIZE, bucket.as_bytes())
+                .map_err(|_| RequestError::went_away())?;
+            offset += BUCKET_SIZE;
+        }
+
+        Ok(HistoryCounts {
+            samples: samples as u32,
+            buckets: buckets as u32,
+        })
+    }
 }
 
 impl NotificationHandler for ServerImpl {
@@ -102,6 +391,11 @@ fn main() -> ! {
 
     let mut server = ServerImpl {
         data: [Reading::Absent; NUM_SENSORS],
+        records: [SensorRecord::EMPTY; NUM_SENSORS],
+        history: history::claim_history(),
+        thresholds: sensors::THRESHOLDS,
+        alarms: [Alarms::NONE; NUM_SENSORS],
+        subscribers: [None; MAX_ALARM_SUBSCRIBERS],
         deadline,
     };
 
@@ -113,7 +407,10 @@ fn main() -> ! {
 }
 
 mod idl {
-    use super::{NoData, SensorError, SensorId};
+    use super::{
+        HistoryCounts, NoData, SensorError, SensorId, SensorInfo,
+        SensorReading, SensorRecord, Thresholds,
+    };
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -0,0 +1,86 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the SPD task.
+
+#![no_std]
+
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum SpdError {
+    InvalidDimm = 1,
+    NotPresent = 2,
+    InvalidPage = 3,
+
+    /// The DIMM's SPD is not that of a DDR4 module.
+    UnknownFormat = 4,
+    BadChecksum = 5,
+    Malformed = 6,
+}
+
+impl From<SpdError> for u16 {
+    fn from(rc: SpdError) -> Self {
+        rc as u16
+    }
+}
+
+impl From<SpdError> for u32 {
+    fn from(rc: SpdError) -> Self {
+        rc as u32
+    }
+}
+
+impl core::convert::TryFrom<u32> for SpdError {
+    type Error = ();
+    fn try_from(rc: u32) -> Result<Self, Self::Error> {
+        Self::from_u32(rc).ok_or(())
+    }
+}
+
+impl From<ddr4::Error> for SpdError {
+    fn from(err: ddr4::Error) -> Self {
+        match err {
+            ddr4::Error::UnknownFormat => SpdError::UnknownFormat,
+            ddr4::Error::BadChecksum => SpdError::BadChecksum,
+            ddr4::Error::Malformed => SpdError::Malformed,
+        }
+    }
+}
+
+/// A summary of a DIMM, as parsed from its DDR4 SPD
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct DimmSummary {
+    /// Capacity of the module, in MiB
+    pub size: u32,
+
+    /// Maximum data rate, in MT/s
+    pub speed: u16,
+
+    /// JEP-106 module manufacturer:  the number of continuation codes in the
+    /// low byte, and the code itself in the high byte (each with parity)
+    pub manufacturer: u16,
+
+    /// Module serial number
+    pub serial: u32,
+
+    /// Module part number, in ASCII padded with spaces
+    pub part: [u8; 20],
+}
+
+impl From<ddr4::Summary> for DimmSummary {
+    fn from(summary: ddr4::Summary) -> Self {
+        Self {
+            size: summary.size,
+            speed: summary.speed,
+            manufacturer: summary.manufacturer,
+            serial: summary.serial,
+            part: summary.part,
+        }
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -2,13 +2,120 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-fn main() {
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+//
+// The subset of the app-wide I2C configuration that we need to find DIMM
+// temperature sensors.  This is shared with other build-specific types, so
+// we must not set `deny_unknown_fields` here.
+//
+#[derive(Deserialize)]
+struct GlobalConfig {
+    i2c: I2cConfig,
+}
+
+#[derive(Deserialize)]
+struct I2cConfig {
+    devices: Option<Vec<I2cDevice>>,
+}
+
+#[derive(Deserialize)]
+struct I2cDevice {
+    device: String,
+    sensors: Option<I2cSensors>,
+}
+
+#[derive(Deserialize)]
+struct I2cSensors {
+    #[serde(default)]
+    temperature: usize,
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
+    build_i2c::codegen(build_i2c::Disposition::TargetSensors)?;
+
+    idol::server::build_server_support(
+        "../../idl/spd.idol",
+        "server_stub.rs",
+        idol::server::ServerStyle::InOrder,
+    )?;
+
+    let global = build_util::config::<GlobalConfig>()?;
+    let devices = global.i2c.devices.unwrap_or_default();
+
+    //
+    // Every DIMM temperature sensor must be configured as a sensor, as we
+    // exist to post its readings.
+    //
+    let tsods = devices
+        .iter()
+        .filter(|d| d.device == "tse2004av")
+        .collect::<Vec<_>>();
 
-    let disposition = build_i2c::Disposition::Target;
+    if tsods
+        .iter()
+        .any(|d| d.sensors.as_ref().map_or(0, |s| s.temperature) != 1)
+    {
+        return Err("each tse2004av must have one temperature sensor".into());
+    }
+
+    if !tsods.is_empty() && env::var("CARGO_FEATURE_SENSOR").is_err() {
+        return Err("tse2004av devices require the sensor feature".into());
+    }
+
+    //
+    // Reading DIMM sensors (and so our timer) is compiled in only if there
+    // are DIMM sensors to read.
+    //
+    if !tsods.is_empty() {
+        println!("cargo:rustc-cfg=dimm_sensors");
+    }
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("spd_config.rs");
+    let mut file = File::create(&dest_path)?;
 
-    if let Err(e) = build_i2c::codegen(disposition) {
-        println!("code generation failed: {}", e);
-        std::process::exit(1);
+    writeln!(file, "pub const NUM_DIMM_SENSORS: usize = {};", tsods.len())?;
+
+    writeln!(
+        file,
+        "\n/// Returns each DIMM temperature sensor, with its sensor ID.\n\
+        #[allow(dead_code, unused_variables)]\n\
+        fn dimm_sensors(task: TaskId) -> \
+        [(I2cDevice, SensorId); NUM_DIMM_SENSORS] {{"
+    )?;
+
+    match tsods.len() {
+        0 => writeln!(file, "    []")?,
+        1 => writeln!(
+            file,
+            "    [(\n        \
+                i2c_config::devices::tse2004av(task)[0],\n        \
+                i2c_config::sensors::TSE2004AV_TEMPERATURE_SENSOR,\n    \
+            )]"
+        )?,
+        _ => {
+            writeln!(
+                file,
+                "    let devices = i2c_config::devices::tse2004av(task);\n    \
+                let ids = \
+                i2c_config::sensors::TSE2004AV_TEMPERATURE_SENSORS;\n\n    ["
+            )?;
+
+            for i in 0..tsods.len() {
+                writeln!(file, "        (devices[{}], ids[{}]),", i, i)?;
+            }
+
+            writeln!(file, "    ]")?;
+        }
     }
+
+    writeln!(file, "}}")?;
+
+    Ok(())
 }
@@ -17,6 +17,11 @@
 //! use AMD's default of an LTC4306, but only implement two segments, as the
 //! limit of the proxy is 16 total DIMMs.
 //!
+//! Once the SPD data has been read, this task serves it to other tasks (both
+//! raw and summarized) while waiting for the SoC.  If the DIMMs' temperature
+//! sensors (TSODs) are configured as `tse2004av` devices, it also reads them
+//! periodically, posting their readings to the sensor task.
+//!
 
 #![no_std]
 #![no_main]
@@ -26,12 +31,25 @@ use core::cell::RefCell;
 use drv_i2c_api::*;
 use drv_stm32h7_i2c::*;
 use drv_stm32xx_sys_api::*;
+use idol_runtime::{ClientError, Leased, LenLimit, RequestError, W};
 use ringbuf::*;
+use task_sensor_api::SensorId;
+use task_spd_api::{DimmSummary, SpdError};
 use userlib::*;
 
 task_slot!(SYS, sys);
 task_slot!(I2C, i2c_driver);
 
+cfg_if::cfg_if! {
+    if #[cfg(dimm_sensors)] {
+        use drv_i2c_devices::tse2004av::Tse2004Av;
+        use drv_i2c_devices::TempSensor;
+        use task_sensor_api as sensor_api;
+
+        task_slot!(SENSOR, sensor);
+    }
+}
+
 mod ltc4306;
 
 fn configure_pins(pins: &[I2cPin]) {
@@ -50,13 +68,83 @@ fn configure_pins(pins: &[I2cPin]) {
     }
 }
 
+const LTC4306_ADDRESS: u8 = 0b1001_010;
+type Bank = (Controller, drv_i2c_api::PortIndex, Option<(Mux, Segment)>);
+
+include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
+include!(concat!(env!("OUT_DIR"), "/spd_config.rs"));
+
+use i2c_config::ports::*;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_board = "gemini-bu-1")] {
+        // These should be whatever ports the dimmlets are plugged into
+        const BANKS: [Bank; 2] = [
+            (Controller::I2C4, i2c4_d(), None),
+            (Controller::I2C4, i2c4_f(), Some((Mux::M1, Segment::S4))),
+        ];
+    } else if #[cfg(target_board = "gimletlet-2")] {
+        // These should be whatever ports the dimmlets are plugged into
+        const BANKS: [Bank; 2] = [
+            (Controller::I2C3, i2c3_c(), None),
+            (Controller::I2C4, i2c4_f(), None),
+        ];
+    } else if #[cfg(any(
+        target_board = "gimlet-a",
+        target_board = "gimlet-b",
+    ))] {
+        //
+        // On Gimlet, we have two banks of up to 8 DIMMs apiece:
+        //
+        // - ABCD DIMMs are on the mid bus (I2C3, port H)
+        // - EFGH DIMMS are on the read bus (I2C4, port F)
+        //
+        // It should go without saying that the ordering here is essential
+        // to assure that the SPD data that we return for a DIMM corresponds
+        // to the correct DIMM from the SoC's perspective.
+        //
+        const BANKS: [Bank; 2] = [
+            (Controller::I2C3, i2c3_h(), None),
+            (Controller::I2C4, i2c4_f(), None),
+        ];
+    } else {
+        compile_error!("I2C target unsupported for this board");
+    }
+}
+
+const NUM_DIMMS: usize = BANKS.len() * spd::MAX_DEVICES as usize;
+
 //
 // This is an excellent candidate to put into a non-DTCM memory region
 //
 static mut SPD_DATA: [u8; 8192] = [0; 8192];
 
-const LTC4306_ADDRESS: u8 = 0b1001_010;
-type Bank = (Controller, drv_i2c_api::PortIndex, Option<(Mux, Segment)>);
+// Boolean indicating that the DIMM is present
+static mut SPD_PRESENT: [bool; NUM_DIMMS] = [false; NUM_DIMMS];
+
+//
+// The SPD data and presence are only written before we begin to operate as
+// a target (and to serve requests); thereafter, they are only read.
+//
+fn spd_data() -> &'static [u8] {
+    unsafe { &SPD_DATA }
+}
+
+fn spd_present() -> &'static [bool] {
+    unsafe { &SPD_PRESENT }
+}
+
+//
+// We read one DIMM temperature sensor per interval, rather than all of them
+// at once, to limit how long we can delay a transaction from the SoC; the
+// interval is such that each sensor is read about once a second.
+//
+const TIMER_MASK: u32 = 1 << 4;
+
+#[cfg(dimm_sensors)]
+const SENSOR_INTERVAL: u64 = 1000 / NUM_DIMM_SENSORS as u64;
+
+const SPD_PAGE_SIZE: usize = 256;
 
 #[derive(Copy, Clone, PartialEq)]
 enum Trace {
@@ -73,13 +161,14 @@ enum Trace {
     MemInitiate(usize),
     MemSetOffset(usize, u8),
     MuxState(ltc4306::State, ltc4306::State),
+    SummaryError(u8, ddr4::Error),
+    #[cfg(dimm_sensors)]
+    SensorError(usize, ResponseCode),
     None,
 }
 
 ringbuf!(Trace, 16, Trace::None);
 
-include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
-
 fn read_spd_data(
     banks: &[Bank],
     present: &mut [bool],
@@ -190,56 +279,149 @@ fn read_spd_data(
     npresent
 }
 
+struct ServerImpl {
+    notification: u32,
+    interrupted: bool,
+}
+
+impl ServerImpl {
+    fn spd(&self, index: u8) -> Result<&'static [u8], SpdError> {
+        let ndx = index as usize;
+
+        match spd_present().get(ndx) {
+            Some(true) => {
+                let offs = ndx * spd::MAX_SIZE;
+                Ok(&spd_data()[offs..offs + spd::MAX_SIZE])
+            }
+            Some(false) => Err(SpdError::NotPresent),
+            None => Err(SpdError::InvalidDimm),
+        }
+    }
+}
+
+impl idl::InOrderSpdImpl for ServerImpl {
+    fn get_present(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<SpdError>> {
+        let mut mask = 0;
+
+        for (ndx, &present) in spd_present().iter().enumerate() {
+            if present {
+                mask |= 1 << ndx;
+            }
+        }
+
+        Ok(mask)
+    }
+
+    fn get_summary(
+        &mut self,
+        _: &RecvMessage,
+        index: u8,
+    ) -> Result<DimmSummary, RequestError<SpdError>> {
+        let spd = self.spd(index)?;
+
+        match ddr4::summarize(spd) {
+            Ok(summary) => Ok(summary.into()),
+            Err(err) => {
+                ringbuf_entry!(Trace::SummaryError(index, err));
+                Err(SpdError::from(err).into())
+            }
+        }
+    }
+
+    fn read_page(
+        &mut self,
+        _: &RecvMessage,
+        index: u8,
+        page: u8,
+        data: LenLimit<Leased<W, [u8]>, SPD_PAGE_SIZE>,
+    ) -> Result<(), RequestError<SpdError>> {
+        let spd = self.spd(index)?;
+
+        let page = spd
+            .chunks(SPD_PAGE_SIZE)
+            .nth(page as usize)
+            .ok_or(SpdError::InvalidPage)?;
+
+        data.write_range(0..data.len(), &page[..data.len()])
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        Ok(())
+    }
+}
+
+impl idol_runtime::NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        self.notification | TIMER_MASK
+    }
+
+    fn handle_notification(&mut self, bits: u32) {
+        if bits & TIMER_MASK != 0 {
+            read_next_dimm_sensor();
+        }
+
+        if bits & self.notification != 0 {
+            self.interrupted = true;
+        }
+    }
+}
+
+//
+// Reads the DIMM sensor whose turn it is, and sets our timer for the next.
+//
+#[cfg(dimm_sensors)]
+fn read_next_dimm_sensor() {
+    let tick = sys_get_timer().now / SENSOR_INTERVAL;
+    let index = (tick % NUM_DIMM_SENSORS as u64) as usize;
+    let (device, id) = dimm_sensors(I2C.get_task_id())[index];
+    let sensor = sensor_api::Sensor::from(SENSOR.get_task_id());
+
+    match Tse2004Av::new(&device).read_temperature() {
+        Ok(reading) => sensor.post(id, reading.0).unwrap(),
+        Err(err) => {
+            let code: ResponseCode = err.into();
+            ringbuf_entry!(Trace::SensorError(index, code));
+            sensor.nodata(id, code.into()).unwrap();
+        }
+    }
+
+    sys_set_timer(Some((tick + 1) * SENSOR_INTERVAL), TIMER_MASK);
+}
+
+#[cfg(not(dimm_sensors))]
+fn read_next_dimm_sensor() {
+    //
+    // Without DIMM sensors, we never set our timer.
+    //
+}
+
+//
+// While waiting for an interrupt as a target, we serve requests from other
+// tasks (and read our sensors).  Note that this must be a function rather
+// than a closure, so all of our state is in statics (or the timer).
+//
+fn wait_for_interrupt(notification: u32) {
+    let mut server = ServerImpl {
+        notification,
+        interrupted: false,
+    };
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+
+    while !server.interrupted {
+        idol_runtime::dispatch_n(&mut buffer, &mut server);
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let controller = &i2c_config::controllers()[0];
     let pins = i2c_config::pins();
-    use i2c_config::ports::*;
-
-    cfg_if::cfg_if! {
-        if #[cfg(target_board = "gemini-bu-1")] {
-            // These should be whatever ports the dimmlets are plugged into
-            const BANKS: [Bank; 2] = [
-                (Controller::I2C4, i2c4_d(), None),
-                (Controller::I2C4, i2c4_f(), Some((Mux::M1, Segment::S4))),
-            ];
-        } else if #[cfg(target_board = "gimletlet-2")] {
-            // These should be whatever ports the dimmlets are plugged into
-            const BANKS: [Bank; 2] = [
-                (Controller::I2C3, i2c3_c(), None),
-                (Controller::I2C4, i2c4_f(), None),
-            ];
-        } else if #[cfg(any(
-            target_board = "gimlet-a",
-            target_board = "gimlet-b",
-        ))] {
-            //
-            // On Gimlet, we have two banks of up to 8 DIMMs apiece:
-            //
-            // - ABCD DIMMs are on the mid bus (I2C3, port H)
-            // - EFGH DIMMS are on the read bus (I2C4, port F)
-            //
-            // It should go without saying that the ordering here is essential
-            // to assure that the SPD data that we return for a DIMM corresponds
-            // to the correct DIMM from the SoC's perspective.
-            //
-            const BANKS: [Bank; 2] = [
-                (Controller::I2C3, i2c3_h(), None),
-                (Controller::I2C4, i2c4_f(), None),
-            ];
-        } else {
-            compile_error!("I2C target unsupported for this board");
-        }
-    }
-
-    // Boolean indicating that the bank is present
-    let mut present = [false; BANKS.len() * spd::MAX_DEVICES as usize];
 
     // Virtual offset, per virtual DIMM
-    let mut voffs = [0u8; BANKS.len() * spd::MAX_DEVICES as usize];
-
-    // The actual SPD data itself
-    let spd_data = unsafe { &mut SPD_DATA };
+    let mut voffs = [0u8; NUM_DIMMS];
     let mut ndelay = 0;
 
     //
@@ -248,7 +430,8 @@ fn main() -> ! {
     // waiting on the sequencer, loop until we have found DIMMs.
     //
     loop {
-        let ndimms = read_spd_data(&BANKS, &mut present, &mut spd_data[..]);
+        let (present, spd_data) = unsafe { (&mut SPD_PRESENT, &mut SPD_DATA) };
+        let ndimms = read_spd_data(&BANKS, present, &mut spd_data[..]);
 
         ringbuf_entry!(Trace::Found(ndimms));
 
@@ -270,6 +453,9 @@ fn main() -> ! {
 
     ringbuf_entry!(Trace::Ready(ndelay));
 
+    let present = spd_present();
+    let spd_data = spd_data();
+
     //
     // Initialize our virtual state.  Note that we initialize with bank 0
     // visible.
@@ -406,10 +592,17 @@ fn main() -> ! {
         enable: |notification| {
             sys_irq_control(notification, true);
         },
-        wfi: |notification| {
-            let _ = sys_recv_closed(&mut [], notification, TaskId::KERNEL);
-        },
+        wfi: wait_for_interrupt,
     };
 
+    #[cfg(dimm_sensors)]
+    sys_set_timer(Some(sys_get_timer().now + SENSOR_INTERVAL), TIMER_MASK);
+
     controller.operate_as_target(&ctrl, &mut initiate, &mut rx, &mut tx);
 }
+
+mod idl {
+    use super::{DimmSummary, SpdError};
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
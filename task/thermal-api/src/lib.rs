@@ -7,12 +7,16 @@
 #![no_std]
 
 use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
 
 #[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
 pub enum ThermalError {
     InvalidFan = 1,
     InvalidPWM = 2,
     DeviceError = 3,
+    InvalidZone = 4,
+    InvalidParameter = 5,
+    NoRecord = 6,
 }
 
 impl From<ThermalError> for u16 {
@@ -34,4 +38,135 @@ impl core::convert::TryFrom<u32> for ThermalError {
     }
 }
 
+/// How the fan duty cycle is determined.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, AsBytes)]
+#[repr(u8)]
+pub enum ThermalMode {
+    /// The duty cycle is set by the control loop.
+    Auto = 1,
+
+    /// The duty cycle is set via `set_fan_pwm`; the task returns to
+    /// automatic control if not told otherwise within a configured timeout.
+    Manual = 2,
+
+    /// The fans run at the fallback duty cycle until the mode is changed.
+    Failsafe = 3,
+}
+
+/// The state of the controller for a thermal zone.  `measurement` (and
+/// hence `error`) is NaN if the zone's temperature couldn't be read, and
+/// `output` is NaN if the controller hasn't run since automatic control was
+/// (re)established.
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ZoneState {
+    /// Target temperature, in degrees Celsius
+    pub target: f32,
+
+    /// Hottest temperature in the zone, in degrees Celsius
+    pub measurement: f32,
+
+    /// `measurement` less `target`
+    pub error: f32,
+
+    /// Duty cycle demanded by the zone, in percent
+    pub output: f32,
+
+    /// Controller gains
+    pub kp: f32,
+    pub ki: f32,
+    pub kd: f32,
+}
+
+/// The health of a fan, as judged by its speed relative to its commanded duty
+/// cycle.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, AsBytes)]
+#[repr(u8)]
+pub enum FanState {
+    /// The fan's health has not yet been determined.
+    Unknown = 0,
+    Ok = 1,
+
+    /// The fan is spinning more slowly than its duty cycle warrants.
+    Underspeed = 2,
+
+    /// The fan has stopped spinning.
+    Stalled = 3,
+
+    /// The fan has never been seen to spin, and is presumed absent.
+    Missing = 4,
+}
+
+impl FanState {
+    pub fn is_fault(&self) -> bool {
+        match self {
+            FanState::Underspeed | FanState::Stalled | FanState::Missing => {
+                true
+            }
+            FanState::Unknown | FanState::Ok => false,
+        }
+    }
+}
+
+/// The status of a fan, as returned by `get_fan_status`.
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct FanStatus {
+    /// Most recently measured speed
+    pub rpm: u16,
+
+    /// Commanded duty cycle, in percent
+    pub duty: u8,
+
+    /// The fan's `FanState`
+    state: u8,
+
+    /// Number of times the fan has entered a faulted state
+    pub faults: u32,
+}
+
+impl FanStatus {
+    pub fn new(rpm: u16, duty: u8, state: FanState, faults: u32) -> Self {
+        Self {
+            rpm,
+            duty,
+            state: state as u8,
+            faults,
+        }
+    }
+
+    pub fn state(&self) -> FanState {
+        FanState::from_u8(self.state).unwrap_or(FanState::Unknown)
+    }
+}
+
+/// A record of the system having been powered down because a sensor
+/// exceeded its critical threshold.
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct ShutdownRecord {
+    /// Time of the shutdown, in kernel ticks since boot
+    pub timestamp: u64,
+
+    /// The sensor that exceeded its threshold
+    pub sensor: u32,
+
+    /// Its temperature and threshold, in degrees Celsius
+    pub temperature: f32,
+    pub threshold: f32,
+
+    /// The `SeqError` returned by the sequencer, or 0 on success
+    pub result: u32,
+}
+
+impl ShutdownRecord {
+    pub const EMPTY: Self = Self {
+        timestamp: 0,
+        sensor: 0,
+        temperature: 0.0,
+        threshold: 0.0,
+        result: 0,
+    };
+}
+
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
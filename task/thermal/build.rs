@@ -2,6 +2,193 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+use serde::Deserialize;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TaskConfig {
+    /// time after which manual mode reverts to automatic control, in
+    /// milliseconds; 0 disables the timeout
+    #[serde(default = "default_manual_timeout")]
+    manual_timeout: u64,
+
+    /// number of consecutive samples at or above a sensor's critical
+    /// threshold that cause the system to be powered down
+    #[serde(default = "default_critical_samples")]
+    critical_samples: u32,
+
+    /// thermal zones, in the order in which they are numbered
+    zones: Vec<ZoneConfig>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ZoneConfig {
+    /// name of the zone, for documentation only
+    name: String,
+
+    /// target temperature, in degrees Celsius
+    target: f32,
+
+    /// controller gains, if not the defaults
+    #[serde(default)]
+    gains: Gains,
+
+    /// temperature sensors whose hottest reading is the input to the zone
+    sensors: Vec<DeviceRef>,
+
+    /// fan controller that the zone drives
+    fans: DeviceRef,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Gains {
+    kp: f32,
+    ki: f32,
+    kd: f32,
+}
+
+impl Default for Gains {
+    fn default() -> Self {
+        Self {
+            kp: 4.0,
+            ki: 0.2,
+            kd: 0.0,
+        }
+    }
+}
+
+///
+/// A reference to a device in `config.i2c.devices`:  by part and name, or
+/// by part alone if there is only one such device.
+///
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct DeviceRef {
+    device: String,
+    name: Option<String>,
+}
+
+//
+// The subset of the app-wide I2C configuration that we need to resolve
+// device references.  This is shared with other build-specific types, so we
+// must not set `deny_unknown_fields` here.
+//
+#[derive(Deserialize)]
+struct GlobalConfig {
+    i2c: I2cConfig,
+}
+
+#[derive(Deserialize)]
+struct I2cConfig {
+    devices: Option<Vec<I2cDevice>>,
+}
+
+#[derive(Deserialize)]
+struct I2cDevice {
+    device: String,
+    name: Option<String>,
+    sensors: Option<I2cSensors>,
+}
+
+#[derive(Deserialize)]
+struct I2cSensors {
+    #[serde(default)]
+    temperature: usize,
+    #[serde(default)]
+    speed: usize,
+}
+
+fn default_manual_timeout() -> u64 {
+    5 * 60 * 1000
+}
+
+fn default_critical_samples() -> u32 {
+    3
+}
+
+/// Returns the name of the driver for a temperature sensor part, and any
+/// arguments to its constructor beyond the device.  (A TMP451 is used for
+/// its remote sensor.)
+fn sensor_driver(device: &str) -> Option<(&'static str, &'static str)> {
+    match device {
+        "lm75" => Some(("Lm75", "")),
+        "tmp116" | "tmp117" => Some(("Tmp116", "")),
+        "tmp451" => Some(("Tmp451", ", Target::Remote")),
+        "sbtsi" => Some(("SbTsi", "")),
+        _ => None,
+    }
+}
+
+/// Returns true if a temperature sensor part is read by another task (which
+/// posts its readings to the sensor task) rather than by us.
+fn sensor_posted(device: &str) -> bool {
+    matches!(device, "tse2004av")
+}
+
+/// Returns the name of the driver for a fan controller part, and the number
+/// of fans that it controls.
+fn fan_driver(device: &str) -> Option<(&'static str, usize)> {
+    match device {
+        "max31790" => Some(("Max31790", 6)),
+        _ => None,
+    }
+}
+
+impl DeviceRef {
+    ///
+    /// Finds the device in the I2C configuration, returning an expression
+    /// for the `I2cDevice` and the prefix of the names of its sensors.
+    ///
+    fn resolve<'a>(
+        &self,
+        devices: &'a [I2cDevice],
+    ) -> Result<(String, String, &'a I2cDevice), Box<dyn std::error::Error>>
+    {
+        let parts = devices
+            .iter()
+            .filter(|d| d.device == self.device)
+            .collect::<Vec<_>>();
+
+        match &self.name {
+            Some(name) => {
+                let d = parts
+                    .iter()
+                    .find(|d| d.name.as_ref() == Some(name))
+                    .ok_or_else(|| format!("no such device: {:?}", self))?;
+
+                Ok((
+                    format!(
+                        "devices::{}_{}(task)",
+                        self.device,
+                        name.to_lowercase()
+                    ),
+                    format!(
+                        "{}_{}",
+                        self.device.to_uppercase(),
+                        name.to_uppercase()
+                    ),
+                    d,
+                ))
+            }
+            None => match parts.len() {
+                1 => Ok((
+                    format!("devices::{}(task)[0]", self.device),
+                    self.device.to_uppercase(),
+                    parts[0],
+                )),
+                0 => Err(format!("no such device: {:?}", self).into()),
+                _ => Err(format!("{:?} is ambiguous; name it", self).into()),
+            },
+        }
+    }
+}
+
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     build_util::expose_target_board();
     build_i2c::codegen(build_i2c::Disposition::Sensors)?;
@@ -12,5 +199,190 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         idol::server::ServerStyle::InOrder,
     )?;
 
+    let config = build_util::task_config::<TaskConfig>()?;
+    let global = build_util::config::<GlobalConfig>()?;
+    let devices = global.i2c.devices.unwrap_or_default();
+
+    if config.critical_samples == 0 {
+        return Err("critical_samples must be non-zero".into());
+    }
+
+    if config.zones.is_empty() {
+        return Err("at least one thermal zone must be configured".into());
+    }
+
+    for zone in &config.zones {
+        let gains = &zone.gains;
+
+        if !zone.target.is_finite() {
+            return Err(format!("zone {}: bad target", zone.name).into());
+        }
+
+        if [gains.kp, gains.ki, gains.kd]
+            .iter()
+            .any(|&k| !(k >= 0.0 && k.is_finite()))
+        {
+            return Err(format!("zone {}: bad gains", zone.name).into());
+        }
+    }
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("thermal_config.rs");
+    let mut file = File::create(&dest_path)?;
+
+    writeln!(
+        file,
+        "pub const MANUAL_TIMEOUT: u64 = {};",
+        config.manual_timeout
+    )?;
+    writeln!(
+        file,
+        "pub const CRITICAL_SAMPLES: u32 = {};",
+        config.critical_samples
+    )?;
+
+    //
+    // Fan controllers are numbered in the order in which zones first refer
+    // to them.
+    //
+    let mut controllers: Vec<&DeviceRef> = vec![];
+    let mut zone_fans = vec![];
+
+    for zone in &config.zones {
+        let index = match controllers.iter().position(|&c| *c == zone.fans) {
+            Some(index) => index,
+            None => {
+                controllers.push(&zone.fans);
+                controllers.len() - 1
+            }
+        };
+
+        zone_fans.push(index);
+    }
+
+    writeln!(file, "pub const NUM_ZONES: usize = {};", config.zones.len())?;
+    writeln!(
+        file,
+        "pub const NUM_FAN_CONTROLLERS: usize = {};",
+        controllers.len()
+    )?;
+
+    writeln!(
+        file,
+        "\n/// Index of the fan controller driven by each zone\n\
+        pub const ZONE_FANS: [usize; NUM_ZONES] = {:?};",
+        zone_fans
+    )?;
+
+    writeln!(
+        file,
+        "\nfn fan_controllers(task: TaskId) -> \
+        [FanController; NUM_FAN_CONTROLLERS] {{\n    ["
+    )?;
+
+    for c in &controllers {
+        let (driver, nfans) = fan_driver(&c.device)
+            .ok_or_else(|| format!("{:?} is not a fan controller", c))?;
+        let (dev, prefix, d) = c.resolve(&devices)?;
+
+        if d.sensors.as_ref().map_or(0, |s| s.speed) != nfans {
+            return Err(
+                format!("{:?} must have {} speed sensors", c, nfans).into()
+            );
+        }
+
+        writeln!(
+            file,
+            "        FanController {{\n            \
+                device: {}::new(&{}),\n            \
+                ids: sensors::{}_SPEED_SENSORS,\n        }},",
+            driver, dev, prefix
+        )?;
+    }
+
+    writeln!(file, "    ]\n}}")?;
+
+    let mut sensors = vec![];
+
+    for (index, zone) in config.zones.iter().enumerate() {
+        for s in &zone.sensors {
+            sensors.push((index, s));
+        }
+    }
+
+    writeln!(
+        file,
+        "\npub const NUM_TEMPERATURE_SENSORS: usize = {};",
+        sensors.len()
+    )?;
+
+    writeln!(
+        file,
+        "\nfn temperature_sensors(task: TaskId) -> \
+        [Sensor; NUM_TEMPERATURE_SENSORS] {{\n    ["
+    )?;
+
+    for (zone, s) in &sensors {
+        let (dev, prefix, d) = s.resolve(&devices)?;
+
+        let device = if sensor_posted(&s.device) {
+            "Device::Posted".to_string()
+        } else {
+            let (driver, args) = sensor_driver(&s.device).ok_or_else(|| {
+                format!("{:?} is not a temperature sensor", s)
+            })?;
+
+            format!("Device::{}({}::new(&{}{}))", driver, driver, dev, args)
+        };
+
+        if d.sensors.as_ref().map_or(0, |s| s.temperature) != 1 {
+            return Err(format!(
+                "{:?} must have exactly one temperature sensor",
+                s
+            )
+            .into());
+        }
+
+        writeln!(
+            file,
+            "        Sensor {{\n            \
+                device: {},\n            \
+                zone: {},\n            \
+                id: sensors::{}_TEMPERATURE_SENSOR,\n        }},",
+            device, zone, prefix
+        )?;
+    }
+
+    writeln!(file, "    ]\n}}")?;
+
+    writeln!(
+        file,
+        "\n/// Returns the controllers for each zone, with their target\n\
+        /// temperatures in degrees Celsius.\n\
+        fn zone_controllers() -> [Controller; NUM_ZONES] {{\n    ["
+    )?;
+
+    for zone in &config.zones {
+        writeln!(
+            file,
+            "        // {}\n        \
+            Controller::new(\n            \
+                {:?},\n            \
+                PidConfig {{\n                \
+                    kp: {:?},\n                \
+                    ki: {:?},\n                \
+                    kd: {:?},\n                \
+                    min: MIN_DUTY,\n                \
+                    max: MAX_DUTY,\n            \
+                }},\n            \
+                RAMP,\n            \
+                FALLBACK_DUTY,\n        \
+            ),",
+            zone.name, zone.target, zone.gains.kp, zone.gains.ki, zone.gains.kd
+        )?;
+    }
+
+    writeln!(file, "    ]\n}}")?;
+
     Ok(())
 }
@@ -4,51 +4,173 @@
 
 //! Thermal loop
 //!
-//! This is a primordial thermal loop, which will ultimately reading temperature
-//! sensors and control fan duty cycles to actively manage thermals.  Right now,
-//! though it is merely reading every fan and temp sensor that it can find...
+//! This task reads every fan and temperature sensor that it has been
+//! configured with, posting the results to the sensor task, and controls the
+//! fan duty cycle to manage thermals.  Each thermal zone has its own PID
+//! controller, driving the hottest sensor in the zone toward the zone's
+//! target temperature; as a fan controller may be shared by several zones,
+//! its fans run at the highest duty cycle demanded by any of them.  If any
+//! sensor in a zone can't be read, that zone demands a fallback duty cycle
+//! until it can be again.  Zone targets and gains can be changed at runtime.
+//!
+//! Some sensors (e.g., the temperature sensors on DIMMs) are read by another
+//! task, which posts their readings to the sensor task; we take the latest
+//! such reading, treating one that is stale as a failure to read.  Such a
+//! sensor may be absent (e.g., an unpopulated DIMM slot), in which case it
+//! is ignored; a zone whose sensors are all absent demands nothing.  The
+//! CPU's SB-TSI sensor is similarly absent (and isn't read) while the host
+//! is out of A0, as it can't be read then; this requires the `gimlet-seq`
+//! feature, without which the host is assumed to be powered.
+//!
+//! The zones are configured in `[[tasks.thermal.config.zones]]`, numbered in
+//! the order in which they appear.  Each names its target temperature, its
+//! gains (if not the defaults), the temperature sensors whose hottest reading
+//! is its input, and the fan controller that it drives; sensors and fan
+//! controllers refer to devices in `config.i2c.devices` by `device` and (if
+//! there is more than one of that part) `name`.  Fans are numbered across
+//! fan controllers, in the order in which zones first refer to them.
+//!
+//! Setting a fan's duty cycle with `set_fan_pwm` enters manual mode, in which
+//! the control loop leaves the fans alone.  If manual mode isn't renewed
+//! (by another `set_fan_pwm` or `set_mode`) within the `manual_timeout`
+//! configured for the task, automatic control resumes.  In failsafe mode,
+//! the fans run at the fallback duty cycle until the mode is changed.
+//!
+//! The health of each fan is judged by comparing its speed to its commanded
+//! duty cycle, along with the fan controller's fault status.  Under automatic
+//! control, a faulted fan is driven at full speed (in case it can recover),
+//! and the duty cycle of the remaining fans is raised to compensate for it.
+//!
+//! A sensor's upper critical threshold is its shutdown limit:  if a sensor
+//! reads at or above it for `critical_samples` consecutive samples while the
+//! system is in A0, we instruct the sequencer to power down to A2, and record
+//! the event in a shutdown log.  The log is held in RAM that survives a
+//! restart of this task, but it is lost if the SP resets or loses power.  The
+//! threshold is that held by the sensor task:  we ask for every sensor's
+//! threshold at startup, and thereafter refresh one sensor's each second, so
+//! a change made with `set_thresholds` takes effect within as many seconds as
+//! there are temperature sensors.  If the sensor task can't be asked, we use
+//! the threshold configured in the I2C configuration.  Powering down requires
+//! the `gimlet-seq` feature; without it, critical readings are only traced.
 //!
 
 #![no_std]
 #![no_main]
 
+mod shutdown;
+
 use drv_i2c_api::ResponseCode;
+use drv_i2c_devices::lm75::*;
 use drv_i2c_devices::max31790::*;
 use drv_i2c_devices::sbtsi::*;
 use drv_i2c_devices::tmp116::*;
+use drv_i2c_devices::tmp451::*;
 use drv_i2c_devices::TempSensor;
 use idol_runtime::{NotificationHandler, RequestError};
+use pid::{Controller, PidConfig, Ramp};
+use ringbuf::*;
 use task_sensor_api as sensor_api;
-use task_thermal_api::ThermalError;
+use task_thermal_api::{
+    FanState, FanStatus, ShutdownRecord, ThermalError, ThermalMode, ZoneState,
+};
 use userlib::units::*;
 use userlib::*;
 
 use sensor_api::SensorId;
+use shutdown::ShutdownLog;
 
 task_slot!(I2C, i2c_driver);
 task_slot!(SENSOR, sensor);
 
+cfg_if::cfg_if! {
+    if #[cfg(feature = "gimlet-seq")] {
+        use drv_gimlet_seq_api as seq_api;
+        use seq_api::PowerState;
+
+        task_slot!(SEQUENCER, gimlet_seq);
+
+        /// Powers the system down to A2, returning `None` if it isn't in A0
+        /// (and so there's nothing to be done), and otherwise the result:
+        /// 0 on success or the sequencer's error code.
+        fn power_down() -> Option<u32> {
+            let sequencer = seq_api::Sequencer::from(SEQUENCER.get_task_id());
+
+            if sequencer.get_state() != Ok(PowerState::A0) {
+                return None;
+            }
+
+            match sequencer.set_state(PowerState::A2) {
+                Ok(()) => Some(0),
+                Err(e) => Some(u16::from(e).into()),
+            }
+        }
+
+        /// Returns true if the host is in A0, and so its CPU can be read.
+        fn host_powered() -> bool {
+            let sequencer = seq_api::Sequencer::from(SEQUENCER.get_task_id());
+            sequencer.get_state() == Ok(PowerState::A0)
+        }
+    } else {
+        /// Without a sequencer, we have no means of powering down.
+        fn power_down() -> Option<u32> {
+            None
+        }
+
+        /// Without a sequencer, we assume that the host is powered.
+        fn host_powered() -> bool {
+            true
+        }
+    }
+}
+
 include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
 use i2c_config::devices;
 use i2c_config::sensors;
 
-enum Zone {
-    East,
-    Central,
-    West,
-}
+include!(concat!(env!("OUT_DIR"), "/thermal_config.rs"));
 
+/// A temperature sensor.  (Not every board has every kind of sensor.)
+#[allow(dead_code)]
 enum Device {
-    North(Zone, Tmp116),
-    South(Zone, Tmp116),
-    CPU(SbTsi),
+    Lm75(Lm75),
+    Tmp116(Tmp116),
+    Tmp451(Tmp451),
+    SbTsi(SbTsi),
+
+    /// Read by another task, which posts its readings to the sensor task
+    Posted,
 }
 
 struct Sensor {
     device: Device,
+
+    /// Index of the zone to which the sensor belongs
+    zone: usize,
     id: SensorId,
 }
 
+struct FanController {
+    device: Max31790,
+
+    /// Speed sensor for each fan
+    ids: [SensorId; MAX_FANS as usize],
+}
+
+const NUM_FANS: usize = NUM_FAN_CONTROLLERS * MAX_FANS as usize;
+
+/// Returns the index of the fan controller for fan `ndx` (numbered across
+/// all fan controllers), and the fan on that controller.
+fn fan(ndx: usize) -> (usize, Fan) {
+    let nfans = MAX_FANS as usize;
+    (ndx / nfans, Fan::from((ndx % nfans) as u8))
+}
+
+/// Returns the fans on fan controller `c`.
+fn fan_range(c: usize) -> core::ops::Range<usize> {
+    let nfans = MAX_FANS as usize;
+    c * nfans..(c + 1) * nfans
+}
+
 fn temp_read<E, T: TempSensor<E>>(
     device: &mut T,
 ) -> Result<Celsius, ResponseCode>
@@ -64,98 +186,507 @@ where
     }
 }
 
+/// A reading posted by another task is considered failed if it is older than
+/// this, in milliseconds.
+const POSTED_TIMEOUT: u64 = 5000;
+
+/// The outcome of sampling a temperature sensor
+enum Sample {
+    Reading(Celsius),
+    Failed,
+
+    /// The sensor is posted by another task, which has found it to be absent
+    /// (or has yet to post it at all) -- or it is on the host CPU, which is
+    /// powered off.
+    Absent,
+}
+
 impl Sensor {
-    fn read_temp(&mut self) -> Result<Celsius, ResponseCode> {
-        match &mut self.device {
-            Device::North(_, dev) | Device::South(_, dev) => temp_read(dev),
-            Device::CPU(dev) => temp_read(dev),
+    /// Samples the sensor.  If we read it ourselves, we post the result.
+    /// `host_powered` indicates whether the host is in A0.
+    fn sample(
+        &mut self,
+        sensor: &sensor_api::Sensor,
+        host_powered: bool,
+    ) -> Sample {
+        let reading = match &mut self.device {
+            Device::Lm75(dev) => temp_read(dev),
+            Device::Tmp116(dev) => temp_read(dev),
+            Device::Tmp451(dev) => temp_read(dev),
+            Device::SbTsi(_) if !host_powered => {
+                sensor
+                    .nodata(self.id, sensor_api::NoData::DeviceOff)
+                    .unwrap();
+                return Sample::Absent;
+            }
+            Device::SbTsi(dev) => temp_read(dev),
+            Device::Posted => return self.sample_posted(sensor),
+        };
+
+        match reading {
+            Ok(reading) => {
+                sensor.post(self.id, reading.0).unwrap();
+                Sample::Reading(reading)
+            }
+            Err(e) => {
+                sensor.nodata(self.id, e.into()).unwrap();
+                Sample::Failed
+            }
+        }
+    }
+
+    fn sample_posted(&self, sensor: &sensor_api::Sensor) -> Sample {
+        use sensor_api::SensorError;
+
+        match sensor.get_reading(self.id) {
+            Ok(reading) => {
+                let age = sys_get_timer().now.saturating_sub(reading.timestamp);
+
+                if age <= POSTED_TIMEOUT {
+                    Sample::Reading(Celsius(reading.value))
+                } else {
+                    Sample::Failed
+                }
+            }
+            Err(SensorError::NoReading) | Err(SensorError::NotPresent) => {
+                Sample::Absent
+            }
+            Err(_) => Sample::Failed,
         }
     }
 }
 
-const NUM_TEMPERATURE_SENSORS: usize = sensors::NUM_TMP117_TEMPERATURE_SENSORS
-    + sensors::NUM_SBTSI_TEMPERATURE_SENSORS;
+/// Returns the shutdown limit of sensor `id`:  its upper critical threshold
+/// as held by the sensor task, or as configured if the sensor task can't be
+/// asked.
+fn shutdown_limit(sensor: &sensor_api::Sensor, id: SensorId) -> f32 {
+    match sensor.get_thresholds(id) {
+        Ok(thresholds) => thresholds.upper_critical,
+        Err(_) => sensors::THRESHOLDS[id.0].upper_critical,
+    }
+}
 
-fn temperature_sensors() -> [Sensor; NUM_TEMPERATURE_SENSORS] {
-    let task = I2C.get_task_id();
+/// Fan duty cycle limits, in percent.
+const MIN_DUTY: f32 = 20.0;
+const MAX_DUTY: f32 = 100.0;
+
+/// Duty cycle demanded by a zone whose temperature can't be read.
+const FALLBACK_DUTY: f32 = MAX_DUTY;
+
+/// Limits on the rate at which the duty cycle may change, in percent per
+/// second.  Slowing the fans gradually keeps the loop from hunting.
+const RAMP: Ramp = Ramp {
+    up: 10.0,
+    down: 2.0,
+};
+
+/// A fan is expected to turn at least this fast for each percent of its duty
+/// cycle; this is deliberately conservative.
+const MIN_RPM_PER_PERCENT: u32 = 50;
 
-    [
-        // North and south zones are inverted with respect to one another;
-        // see Gimlet issue #1302 for details.
-        Sensor {
-            device: Device::North(
-                Zone::East,
-                Tmp116::new(&devices::tmp117_northeast(task)),
-            ),
-            id: sensors::TMP117_NORTHEAST_TEMPERATURE_SENSOR,
-        },
-        Sensor {
-            device: Device::North(
-                Zone::Central,
-                Tmp116::new(&devices::tmp117_north(task)),
-            ),
-            id: sensors::TMP117_NORTH_TEMPERATURE_SENSOR,
-        },
-        Sensor {
-            device: Device::North(
-                Zone::West,
-                Tmp116::new(&devices::tmp117_northwest(task)),
-            ),
-            id: sensors::TMP117_NORTHWEST_TEMPERATURE_SENSOR,
-        },
-        Sensor {
-            device: Device::South(
-                Zone::East,
-                Tmp116::new(&devices::tmp117_southeast(task)),
-            ),
-            id: sensors::TMP117_SOUTHEAST_TEMPERATURE_SENSOR,
-        },
-        Sensor {
-            device: Device::South(
-                Zone::Central,
-                Tmp116::new(&devices::tmp117_south(task)),
-            ),
-            id: sensors::TMP117_SOUTH_TEMPERATURE_SENSOR,
-        },
-        Sensor {
-            device: Device::South(
-                Zone::West,
-                Tmp116::new(&devices::tmp117_southwest(task)),
-            ),
-            id: sensors::TMP117_SOUTHWEST_TEMPERATURE_SENSOR,
-        },
-        Sensor {
-            device: Device::CPU(SbTsi::new(&devices::sbtsi(task)[0])),
-            id: sensors::SBTSI_TEMPERATURE_SENSOR,
-        },
-    ]
+/// Number of consecutive readings that must agree before we believe that the
+/// health of a fan has changed.
+const FAN_DEBOUNCE: u8 = 3;
+
+/// Duty cycle added to the remaining fans for each faulted fan, in percent.
+const FAN_COMPENSATION: f32 = 20.0;
+
+#[derive(Copy, Clone)]
+struct FanHealth {
+    state: FanState,
+
+    /// A state that differs from `state`, and the number of consecutive
+    /// readings in which it has been observed
+    pending: FanState,
+    count: u8,
+
+    rpm: u16,
+    duty: u8,
+
+    /// True if the fan has ever been seen to spin
+    spun: bool,
+    faults: u32,
 }
 
+impl FanHealth {
+    const EMPTY: Self = Self {
+        state: FanState::Unknown,
+        pending: FanState::Unknown,
+        count: 0,
+        rpm: 0,
+        duty: 0,
+        spun: false,
+        faults: 0,
+    };
+
+    /// Judges a reading of `rpm` (`faulted` if the controller indicates a
+    /// fault), returning the new state if it has changed.
+    fn update(&mut self, rpm: Rpm, faulted: bool) -> Option<FanState> {
+        let rpm = rpm.0;
+        let min = u32::from(self.duty) * MIN_RPM_PER_PERCENT;
+
+        self.rpm = rpm;
+        self.spun |= rpm != 0;
+
+        let observed = if self.duty == 0 {
+            FanState::Ok
+        } else if rpm == 0 {
+            if self.spun {
+                FanState::Stalled
+            } else {
+                FanState::Missing
+            }
+        } else if faulted || u32::from(rpm) < min {
+            FanState::Underspeed
+        } else {
+            FanState::Ok
+        };
+
+        if observed == self.state {
+            self.count = 0;
+            return None;
+        }
+
+        if observed == self.pending {
+            self.count += 1;
+        } else {
+            self.pending = observed;
+            self.count = 1;
+        }
+
+        if self.count < FAN_DEBOUNCE {
+            return None;
+        }
+
+        self.state = observed;
+        self.count = 0;
+
+        if observed.is_fault() {
+            self.faults = self.faults.saturating_add(1);
+        }
+
+        Some(observed)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    Mode(ThermalMode),
+    ManualTimeout,
+    Duty(u8, u8),
+    FanError(u8, ResponseCode),
+    FanState(u8, FanState),
+    Critical(SensorId, f32),
+    Shutdown(SensorId, u32),
+    None,
+}
+
+ringbuf!(Trace, 16, Trace::None);
+
 struct ServerImpl {
     sensor: sensor_api::Sensor,
     sensors: [Sensor; NUM_TEMPERATURE_SENSORS],
-    fctrl: Max31790,
+
+    /// Number of consecutive samples for which each sensor has been at or
+    /// above its critical threshold
+    critical: [u32; NUM_TEMPERATURE_SENSORS],
+
+    /// Each sensor's shutdown limit, and the next limit to be refreshed
+    limits: [f32; NUM_TEMPERATURE_SENSORS],
+    refresh: usize,
+    log: &'static mut ShutdownLog,
+    controllers: [FanController; NUM_FAN_CONTROLLERS],
+    zones: [Controller; NUM_ZONES],
+    inputs: [Input; NUM_ZONES],
+    fans: [FanHealth; NUM_FANS],
+    mode: ThermalMode,
+
+    /// Time at which manual mode reverts to automatic control
+    manual_deadline: u64,
     deadline: u64,
 }
 
 const TIMER_MASK: u32 = 1 << 0;
 const TIMER_INTERVAL: u64 = 1000;
 
+/// A temperature input to a controller:  the hottest of its readings, unless
+/// any of them has failed.  An input with no readings at all (because every
+/// sensor in its zone is absent) is empty.
+#[derive(Copy, Clone)]
+struct Input {
+    hottest: Option<f32>,
+    failed: bool,
+}
+
+impl Input {
+    const EMPTY: Self = Self {
+        hottest: None,
+        failed: false,
+    };
+
+    fn add(&mut self, reading: Option<Celsius>) {
+        match reading {
+            Some(Celsius(t)) => {
+                self.hottest = Some(self.hottest.map_or(t, |h| h.max(t)));
+            }
+            None => self.failed = true,
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.hottest.is_none() && !self.failed
+    }
+
+    fn get(&self) -> Option<f32> {
+        if self.failed {
+            None
+        } else {
+            self.hottest
+        }
+    }
+}
+
 impl ServerImpl {
-    fn read_fans(&self) {
-        let ids = &sensors::MAX31790_SPEED_SENSORS;
+    /// Reads every temperature sensor, posting the results, and records the
+    /// inputs to the zone controllers.
+    fn read_temps(&mut self) {
+        let mut zones = [Input::EMPTY; NUM_ZONES];
+        let mut shutdown = None;
+        let powered = host_powered();
+
+        if let Some(s) = self.sensors.get(self.refresh) {
+            self.limits[self.refresh] = shutdown_limit(&self.sensor, s.id);
+            self.refresh = if self.refresh + 1 < NUM_TEMPERATURE_SENSORS {
+                self.refresh + 1
+            } else {
+                0
+            };
+        }
+
+        for ((s, critical), &threshold) in self
+            .sensors
+            .iter_mut()
+            .zip(&mut self.critical)
+            .zip(&self.limits)
+        {
+            let reading = match s.sample(&self.sensor, powered) {
+                Sample::Reading(reading) => Some(reading),
+                Sample::Failed => None,
+                Sample::Absent => {
+                    *critical = 0;
+                    continue;
+                }
+            };
+
+            zones[s.zone].add(reading);
+
+            //
+            // A failed read neither advances nor resets the count of
+            // critical samples.
+            //
+            if let Some(Celsius(t)) = reading {
+                if t >= threshold {
+                    ringbuf_entry!(Trace::Critical(s.id, t));
+                    *critical = critical.saturating_add(1);
+
+                    if *critical >= CRITICAL_SAMPLES {
+                        shutdown = Some((s.id, t, threshold));
+                    }
+                } else {
+                    *critical = 0;
+                }
+            }
+        }
+
+        self.inputs = zones;
+
+        if let Some((id, temperature, threshold)) = shutdown {
+            self.shutdown(id, temperature, threshold);
+        }
+    }
+
+    /// Powers the system down to A2 because sensor `id` has exceeded its
+    /// critical threshold, recording the event.  This does nothing if the
+    /// system isn't in A0.
+    fn shutdown(&mut self, id: SensorId, temperature: f32, threshold: f32) {
+        let result = match power_down() {
+            Some(result) => result,
+            None => return,
+        };
+
+        ringbuf_entry!(Trace::Shutdown(id, result));
+
+        self.log.record(ShutdownRecord {
+            timestamp: sys_get_timer().now,
+            sensor: id.0 as u32,
+            temperature,
+            threshold,
+            result,
+        });
+    }
+
+    /// Updates the controllers, and sets every healthy fan to the highest
+    /// duty cycle that any zone driving it demands (plus compensation for
+    /// any faulted fans on the same controller); faulted fans are set to run
+    /// at full speed.  A zone with an empty input demands nothing, and its
+    /// controller is reset.
+    fn control(&mut self) {
+        let dt = TIMER_INTERVAL as f32 / 1000.0;
+        let mut duty = [MIN_DUTY; NUM_FAN_CONTROLLERS];
+
+        for ((controller, input), &c) in
+            self.zones.iter_mut().zip(self.inputs).zip(ZONE_FANS.iter())
+        {
+            if input.is_empty() {
+                controller.reset();
+                continue;
+            }
+
+            duty[c] = duty[c].max(controller.update(input.get(), dt));
+        }
+
+        for (c, &duty) in duty.iter().enumerate() {
+            let fans = fan_range(c);
+            let faulted = self.fans[fans.clone()]
+                .iter()
+                .filter(|f| f.state.is_fault())
+                .count();
+
+            if faulted == 0 {
+                self.set_duty(c, duty);
+                continue;
+            }
+
+            let duty = DutyCycle::from(Percent(
+                duty + faulted as f32 * FAN_COMPENSATION,
+            ));
+            ringbuf_entry!(Trace::Duty(c as u8, duty.percent()));
+
+            for ndx in fans {
+                //
+                // Failure to set a fan is recorded by set_fan_duty; there's
+                // nothing more to be done about it here.
+                //
+                let _ = if self.fans[ndx].state.is_fault() {
+                    self.set_fan_duty(ndx, DutyCycle::MAX)
+                } else {
+                    self.set_fan_duty(ndx, duty)
+                };
+            }
+        }
+    }
+
+    /// Sets every fan on fan controller `c` to `duty`, in percent.
+    fn set_duty(&mut self, c: usize, duty: f32) {
+        let duty = DutyCycle::from(Percent(duty));
+        ringbuf_entry!(Trace::Duty(c as u8, duty.percent()));
+
+        for ndx in fan_range(c) {
+            let _ = self.set_fan_duty(ndx, duty);
+        }
+    }
+
+    /// Sets every fan on every fan controller to `duty`, in percent.
+    fn set_all_duty(&mut self, duty: f32) {
+        for c in 0..NUM_FAN_CONTROLLERS {
+            self.set_duty(c, duty);
+        }
+    }
+
+    fn set_fan_duty(
+        &mut self,
+        ndx: usize,
+        duty: DutyCycle,
+    ) -> Result<(), ResponseCode> {
+        let (c, fan) = fan(ndx);
+        let pwm = PWMDuty(duty.percent());
+
+        match self.controllers[c].device.set_pwm(fan, pwm) {
+            Ok(()) => {
+                self.fans[ndx].duty = duty.percent();
+                Ok(())
+            }
+            Err(e) => {
+                ringbuf_entry!(Trace::FanError(ndx as u8, e));
+                Err(e)
+            }
+        }
+    }
 
-        for ndx in 0..MAX_FANS {
-            let fan = Fan::from(ndx);
+    fn enter_mode(&mut self, mode: ThermalMode) {
+        if mode != self.mode {
+            ringbuf_entry!(Trace::Mode(mode));
+        }
 
-            match self.fctrl.fan_rpm(fan) {
-                Ok(reading) => {
-                    self.sensor
-                        .post(ids[ndx as usize], reading.0.into())
-                        .unwrap();
+        match mode {
+            ThermalMode::Auto => {
+                //
+                // The controllers haven't been driving the fans, so any
+                // state that they have accumulated is stale.
+                //
+                if self.mode != ThermalMode::Auto {
+                    for controller in self.zones.iter_mut() {
+                        controller.reset();
+                    }
                 }
-                Err(e) => {
-                    self.sensor.nodata(ids[ndx as usize], e.into()).unwrap()
+            }
+            ThermalMode::Manual => {
+                self.manual_deadline = sys_get_timer().now + MANUAL_TIMEOUT;
+            }
+            ThermalMode::Failsafe => {
+                self.set_all_duty(FALLBACK_DUTY);
+            }
+        }
+
+        self.mode = mode;
+    }
+
+    fn zone(&self, zone: u8) -> Result<usize, ThermalError> {
+        let zone = usize::from(zone);
+
+        if zone < NUM_ZONES {
+            Ok(zone)
+        } else {
+            Err(ThermalError::InvalidZone)
+        }
+    }
+
+    /// Reads every fan, posting the results, and judges its health.
+    fn read_fans(&mut self) {
+        for c in 0..NUM_FAN_CONTROLLERS {
+            let controller = &self.controllers[c];
+
+            //
+            // The fault status bits are latched, so we clear them once read
+            // to get a fresh indication on the next pass.
+            //
+            let faults = match controller.device.fan_faults() {
+                Ok(faults) => {
+                    if !faults.is_empty() {
+                        let _ = controller.device.clear_fan_faults();
+                    }
+                    faults
+                }
+                Err(_) => FanFaults(0),
+            };
+
+            for ndx in fan_range(c) {
+                let (_, fan) = fan(ndx);
+                let id = controller.ids[fan.index() as usize];
+
+                match controller.device.fan_rpm(fan) {
+                    Ok(reading) => {
+                        self.sensor.post(id, reading.0.into()).unwrap();
+
+                        let health = &mut self.fans[ndx];
+
+                        if let Some(state) =
+                            health.update(reading, faults.is_faulted(fan))
+                        {
+                            ringbuf_entry!(Trace::FanState(ndx as u8, state));
+                        }
+                    }
+                    Err(e) => self.sensor.nodata(id, e.into()).unwrap(),
                 }
             }
         }
@@ -169,11 +700,13 @@ impl idl::InOrderThermalImpl for ServerImpl {
         index: u8,
         pwm: u8,
     ) -> Result<(), RequestError<ThermalError>> {
-        if index < MAX_FANS {
-            let fan = Fan::from(index);
+        let index = usize::from(index);
 
-            if pwm <= 100 {
-                match self.fctrl.set_pwm(fan, PWMDuty(pwm)) {
+        if index < NUM_FANS {
+            if let Some(duty) = DutyCycle::new(pwm) {
+                self.enter_mode(ThermalMode::Manual);
+
+                match self.set_fan_duty(index, duty) {
                     Ok(_) => Ok(()),
                     Err(_) => Err(ThermalError::DeviceError.into()),
                 }
@@ -184,6 +717,112 @@ impl idl::InOrderThermalImpl for ServerImpl {
             Err(ThermalError::InvalidFan.into())
         }
     }
+
+    fn get_fan_status(
+        &mut self,
+        _: &RecvMessage,
+        index: u8,
+    ) -> Result<FanStatus, RequestError<ThermalError>> {
+        if let Some(fan) = self.fans.get(usize::from(index)) {
+            Ok(FanStatus::new(fan.rpm, fan.duty, fan.state, fan.faults))
+        } else {
+            Err(ThermalError::InvalidFan.into())
+        }
+    }
+
+    fn get_shutdown_record(
+        &mut self,
+        _: &RecvMessage,
+        index: u32,
+    ) -> Result<ShutdownRecord, RequestError<ThermalError>> {
+        match self.log.get(index as usize) {
+            Some(record) => Ok(*record),
+            None => Err(ThermalError::NoRecord.into()),
+        }
+    }
+
+    fn get_mode(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<ThermalMode, RequestError<ThermalError>> {
+        Ok(self.mode)
+    }
+
+    fn set_mode(
+        &mut self,
+        _: &RecvMessage,
+        mode: ThermalMode,
+    ) -> Result<(), RequestError<ThermalError>> {
+        self.enter_mode(mode);
+        Ok(())
+    }
+
+    fn get_zone_state(
+        &mut self,
+        _: &RecvMessage,
+        zone: u8,
+    ) -> Result<ZoneState, RequestError<ThermalError>> {
+        let zone = self.zone(zone)?;
+        let measurement = self.inputs[zone].get().unwrap_or(f32::NAN);
+        let controller = &mut self.zones[zone];
+        let target = controller.setpoint;
+        let config = *controller.pid().config();
+
+        Ok(ZoneState {
+            target,
+            measurement,
+            error: measurement - target,
+            output: controller.output().unwrap_or(f32::NAN),
+            kp: config.kp,
+            ki: config.ki,
+            kd: config.kd,
+        })
+    }
+
+    fn set_zone_target(
+        &mut self,
+        _: &RecvMessage,
+        zone: u8,
+        target: f32,
+    ) -> Result<(), RequestError<ThermalError>> {
+        let zone = self.zone(zone)?;
+
+        if !target.is_finite() {
+            return Err(ThermalError::InvalidParameter.into());
+        }
+
+        self.zones[zone].setpoint = target;
+        Ok(())
+    }
+
+    fn set_zone_gains(
+        &mut self,
+        _: &RecvMessage,
+        zone: u8,
+        kp: f32,
+        ki: f32,
+        kd: f32,
+    ) -> Result<(), RequestError<ThermalError>> {
+        let zone = self.zone(zone)?;
+
+        //
+        // Negative gains would have the fans slow as things get hotter.
+        //
+        if [kp, ki, kd].iter().any(|&k| !(k >= 0.0 && k.is_finite())) {
+            return Err(ThermalError::InvalidParameter.into());
+        }
+
+        let pid = self.zones[zone].pid();
+
+        pid.set_config(PidConfig {
+            kp,
+            ki,
+            kd,
+            ..*pid.config()
+        });
+
+        Ok(())
+    }
 }
 
 impl NotificationHandler for ServerImpl {
@@ -196,14 +835,20 @@ impl NotificationHandler for ServerImpl {
         sys_set_timer(Some(self.deadline), TIMER_MASK);
 
         self.read_fans();
+        self.read_temps();
+
+        if self.mode == ThermalMode::Manual
+            && MANUAL_TIMEOUT != 0
+            && sys_get_timer().now >= self.manual_deadline
+        {
+            ringbuf_entry!(Trace::ManualTimeout);
+            self.enter_mode(ThermalMode::Auto);
+        }
 
-        for s in &mut self.sensors {
-            match s.read_temp() {
-                Ok(reading) => {
-                    self.sensor.post(s.id, reading.0).unwrap();
-                }
-                Err(e) => self.sensor.nodata(s.id, e.into()).unwrap(),
-            };
+        match self.mode {
+            ThermalMode::Auto => self.control(),
+            ThermalMode::Manual => {}
+            ThermalMode::Failsafe => self.set_all_duty(FALLBACK_DUTY),
         }
     }
 }
@@ -212,18 +857,11 @@ impl NotificationHandler for ServerImpl {
 fn main() -> ! {
     let task = I2C.get_task_id();
 
-    cfg_if::cfg_if! {
-        if #[cfg(any(
-            target_board = "gimlet-a",
-            target_board = "gimlet-b",
-        ))] {
-            let fctrl = Max31790::new(&devices::max31790(task)[0]);
-        } else {
-            compile_error!("unknown board");
-        }
-    }
+    let controllers = fan_controllers(task);
 
-    fctrl.initialize().unwrap();
+    for controller in &controllers {
+        controller.device.initialize().unwrap();
+    }
 
     let deadline = sys_get_timer().now;
 
@@ -232,10 +870,27 @@ fn main() -> ! {
     //
     sys_set_timer(Some(deadline), TIMER_MASK);
 
+    let sensor = sensor_api::Sensor::from(SENSOR.get_task_id());
+    let sensors = temperature_sensors(task);
+    let mut limits = [0.0; NUM_TEMPERATURE_SENSORS];
+
+    for (limit, s) in limits.iter_mut().zip(&sensors) {
+        *limit = shutdown_limit(&sensor, s.id);
+    }
+
     let mut server = ServerImpl {
-        sensor: sensor_api::Sensor::from(SENSOR.get_task_id()),
-        sensors: temperature_sensors(),
-        fctrl: fctrl,
+        sensor,
+        sensors,
+        critical: [0; NUM_TEMPERATURE_SENSORS],
+        limits,
+        refresh: 0,
+        log: shutdown::claim_log(),
+        controllers,
+        zones: zone_controllers(),
+        inputs: [Input::EMPTY; NUM_ZONES],
+        fans: [FanHealth::EMPTY; NUM_FANS],
+        mode: ThermalMode::Auto,
+        manual_deadline: 0,
         deadline,
     };
 
@@ -247,7 +902,9 @@ fn main() -> ! {
 }
 
 mod idl {
-    use super::ThermalError;
+    use super::{
+        FanStatus, ShutdownRecord, ThermalError, ThermalMode, ZoneState,
+    };
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -0,0 +1,88 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Log of thermal shutdowns
+//!
+//! Each time the thermal task powers the system down because a sensor has
+//! exceeded its critical threshold, it records the event in [`SHUTDOWN_LOG`].
+//! This is placed in `.uninit`, which is not initialized when the task
+//! starts, so the log survives a restart of the task; it is validated by a
+//! magic number, and initialized afresh if that is absent.  It is *not*
+//! persistent:  it is lost if the SP resets or loses power.
+
+use core::mem::MaybeUninit;
+use core::sync::atomic::{AtomicBool, Ordering};
+use task_thermal_api::ShutdownRecord;
+
+/// Number of records retained
+pub const LOG_DEPTH: usize = 8;
+
+const LOG_MAGIC: u32 = 0x7e4d_10c5;
+
+#[repr(C)]
+pub struct ShutdownLog {
+    magic: u32,
+
+    /// Number of records ever made; the most recent is at `(count - 1) %
+    /// LOG_DEPTH`
+    count: u32,
+    records: [ShutdownRecord; LOG_DEPTH],
+}
+
+#[link_section = ".uninit"]
+static mut SHUTDOWN_LOG: MaybeUninit<ShutdownLog> = MaybeUninit::uninit();
+
+/// Returns the shutdown log, initializing it if it isn't valid.  This can
+/// only be called once.
+pub fn claim_log() -> &'static mut ShutdownLog {
+    static TAKEN: AtomicBool = AtomicBool::new(false);
+
+    if TAKEN.swap(true, Ordering::Relaxed) {
+        panic!()
+    }
+
+    // Safety: unsafe because of reference to mutable static; safe because
+    // the AtomicBool swap above means that this is the only reference that
+    // will ever be made to it.
+    let log = unsafe { &mut SHUTDOWN_LOG };
+    let ptr = log.as_mut_ptr();
+
+    //
+    // The contents of the log are whatever was left in memory; we check the
+    // magic (and that the count is sane) with a volatile read, as the
+    // compiler is otherwise entitled to assume that it is uninitialized.
+    //
+    // Safety: the pointer is valid and aligned, and every bit pattern is a
+    // valid u32.
+    let magic = unsafe { core::ptr::read_volatile(&(*ptr).magic) };
+
+    if magic != LOG_MAGIC {
+        log.write(ShutdownLog {
+            magic: LOG_MAGIC,
+            count: 0,
+            records: [ShutdownRecord::EMPTY; LOG_DEPTH],
+        });
+    }
+
+    // Safety: we have either validated the log or initialized it, and every
+    // bit pattern is valid for its remaining fields.
+    unsafe { &mut *ptr }
+}
+
+impl ShutdownLog {
+    pub fn record(&mut self, record: ShutdownRecord) {
+        self.records[self.count as usize % LOG_DEPTH] = record;
+        self.count = self.count.wrapping_add(1);
+    }
+
+    /// Returns the `index`th most recent record, if any.
+    pub fn get(&self, index: usize) -> Option<&ShutdownRecord> {
+        if index < LOG_DEPTH && index < self.count as usize {
+            let slot = (self.count as usize - 1 - index) % LOG_DEPTH;
+            Some(&self.records[slot])
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,77 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the VPD task.
+
+#![no_std]
+
+use userlib::*;
+use zerocopy::{AsBytes, FromBytes};
+
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum VpdError {
+    InvalidEeprom = 1,
+    OutOfRange = 2,
+    DeviceError = 3,
+
+    /// The EEPROM's contents are in no format that we understand (e.g., it
+    /// is blank).
+    UnknownFormat = 4,
+    BadChecksum = 5,
+    Malformed = 6,
+    NoField = 7,
+}
+
+impl From<VpdError> for u16 {
+    fn from(rc: VpdError) -> Self {
+        rc as u16
+    }
+}
+
+impl From<VpdError> for u32 {
+    fn from(rc: VpdError) -> Self {
+        rc as u32
+    }
+}
+
+impl core::convert::TryFrom<u32> for VpdError {
+    type Error = ();
+    fn try_from(rc: u32) -> Result<Self, Self::Error> {
+        Self::from_u32(rc).ok_or(())
+    }
+}
+
+impl From<vpd::Error> for VpdError {
+    fn from(err: vpd::Error) -> Self {
+        match err {
+            vpd::Error::UnknownFormat => VpdError::UnknownFormat,
+            vpd::Error::BadChecksum => VpdError::BadChecksum,
+            vpd::Error::Malformed => VpdError::Malformed,
+            vpd::Error::NoField => VpdError::NoField,
+        }
+    }
+}
+
+/// A field of a board's identity
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq, AsBytes)]
+#[repr(u8)]
+pub enum VpdField {
+    Manufacturer = 1,
+    Product = 2,
+    PartNumber = 3,
+    Revision = 4,
+    SerialNumber = 5,
+}
+
+/// A block of MAC addresses:  `count` addresses from `base`, each `stride`
+/// from the last.
+#[derive(Copy, Clone, Debug, AsBytes, FromBytes)]
+#[repr(C)]
+pub struct MacBlock {
+    pub base: [u8; 6],
+    pub count: u16,
+    pub stride: u16,
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
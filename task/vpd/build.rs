@@ -0,0 +1,108 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+
+//
+// The subset of the app-wide I2C configuration that we need to find the
+// EEPROMs.  This is shared with other build-specific types, so we must not
+// set `deny_unknown_fields` here.
+//
+#[derive(Deserialize)]
+struct GlobalConfig {
+    i2c: I2cConfig,
+}
+
+#[derive(Deserialize)]
+struct I2cConfig {
+    devices: Option<Vec<I2cDevice>>,
+}
+
+#[derive(Deserialize)]
+struct I2cDevice {
+    device: String,
+    description: String,
+}
+
+/// Returns the `At24Part` variant for an EEPROM part.
+fn eeprom_part(device: &str) -> Option<&'static str> {
+    match device {
+        "at24c01" => Some("At24c01"),
+        "at24c02" => Some("At24c02"),
+        "at24c04" => Some("At24c04"),
+        "at24c08" => Some("At24c08"),
+        "at24c16" => Some("At24c16"),
+        "at24c32" => Some("At24c32"),
+        "at24c64" => Some("At24c64"),
+        "at24c128" => Some("At24c128"),
+        "at24c256" => Some("At24c256"),
+        "at24c512" => Some("At24c512"),
+        _ => None,
+    }
+}
+
+fn main() -> Result<(), Box<dyn std::error::Error>> {
+    build_util::expose_target_board();
+    build_i2c::codegen(build_i2c::Disposition::Devices)?;
+
+    idol::server::build_server_support(
+        "../../idl/vpd.idol",
+        "server_stub.rs",
+        idol::server::ServerStyle::InOrder,
+    )?;
+
+    let global = build_util::config::<GlobalConfig>()?;
+    let devices = global.i2c.devices.unwrap_or_default();
+
+    let out_dir = env::var("OUT_DIR")?;
+    let dest_path = Path::new(&out_dir).join("vpd_config.rs");
+    let mut file = File::create(&dest_path)?;
+
+    //
+    // Every EEPROM in the I2C configuration is a VPD EEPROM; they are
+    // numbered in the order in which they appear.  Each is the nth device of
+    // its part in the configuration.
+    //
+    let mut eeproms = vec![];
+    let mut nth = HashMap::new();
+
+    for d in &devices {
+        let index = nth.entry(&d.device).or_insert(0);
+
+        if let Some(part) = eeprom_part(&d.device) {
+            eeproms.push((d, part, *index));
+        }
+
+        *index += 1;
+    }
+
+    if eeproms.is_empty() {
+        return Err("no EEPROMs are configured".into());
+    }
+
+    writeln!(file, "pub const NUM_EEPROMS: usize = {};", eeproms.len())?;
+
+    writeln!(
+        file,
+        "\nfn eeproms(task: TaskId) -> [At24; NUM_EEPROMS] {{\n    ["
+    )?;
+
+    for (d, part, index) in &eeproms {
+        writeln!(
+            file,
+            "        // {}\n        \
+            At24::new(&devices::{}(task)[{}], At24Part::{}),",
+            d.description, d.device, index, part
+        )?;
+    }
+
+    writeln!(file, "    ]\n}}")?;
+
+    Ok(())
+}
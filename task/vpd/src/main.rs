@@ -0,0 +1,188 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Vital product data
+//!
+//! This task reads the vital product data (VPD) -- the identity of a board,
+//! and the MAC addresses allocated to it -- from EEPROMs.  Every AT24-class
+//! EEPROM in `config.i2c.devices` (e.g., `device = "at24c02"`) is a VPD
+//! EEPROM; they are numbered in the order in which they appear.  The VPD may
+//! be in either the IPMI FRU format or the TLV format, as understood by the
+//! `vpd` crate.
+//!
+//! The contents of an EEPROM are read afresh for each request, so that a
+//! removable device (or one that has been reprogrammed) is never stale.
+//!
+
+#![no_std]
+#![no_main]
+
+use drv_i2c_api::ResponseCode;
+use drv_i2c_devices::at24::*;
+use idol_runtime::{ClientError, Leased, LenLimit, RequestError, W};
+use ringbuf::*;
+use task_vpd_api::{MacBlock, VpdError, VpdField};
+use userlib::*;
+
+task_slot!(I2C, i2c_driver);
+
+include!(concat!(env!("OUT_DIR"), "/i2c_config.rs"));
+use i2c_config::devices;
+
+include!(concat!(env!("OUT_DIR"), "/vpd_config.rs"));
+
+/// Largest amount of an EEPROM that we read for its VPD
+const MAX_VPD: usize = 1024;
+
+/// Largest read via `read`
+const MAX_READ: usize = 256;
+
+/// Largest field returned by `read_field`
+const MAX_FIELD: usize = 64;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    ReadError(u8, ResponseCode),
+    ParseError(u8, vpd::Error),
+    None,
+}
+
+ringbuf!(Trace, 16, Trace::None);
+
+struct ServerImpl {
+    eeproms: [At24; NUM_EEPROMS],
+    buf: [u8; MAX_VPD],
+}
+
+impl ServerImpl {
+    fn eeprom(&self, index: u8) -> Result<&At24, VpdError> {
+        self.eeproms
+            .get(index as usize)
+            .ok_or(VpdError::InvalidEeprom)
+    }
+
+    /// Reads the VPD of EEPROM `index`.
+    fn vpd(&mut self, index: u8) -> Result<&[u8], VpdError> {
+        let eeprom = self
+            .eeproms
+            .get(index as usize)
+            .ok_or(VpdError::InvalidEeprom)?;
+        let len = eeprom.part().size().min(MAX_VPD);
+        let buf = &mut self.buf[..len];
+
+        match eeprom.read(0, buf) {
+            Ok(()) => Ok(buf),
+            Err(err) => {
+                ringbuf_entry!(Trace::ReadError(index, err.into()));
+                Err(VpdError::DeviceError)
+            }
+        }
+    }
+}
+
+impl idl::InOrderVpdImpl for ServerImpl {
+    fn num_eeproms(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<u32, RequestError<VpdError>> {
+        Ok(NUM_EEPROMS as u32)
+    }
+
+    fn read(
+        &mut self,
+        _: &RecvMessage,
+        index: u8,
+        offset: u16,
+        data: LenLimit<Leased<W, [u8]>, MAX_READ>,
+    ) -> Result<(), RequestError<VpdError>> {
+        let eeprom = self.eeprom(index)?;
+        let mut buf = [0u8; MAX_READ];
+        let buf = &mut buf[..data.len()];
+
+        match eeprom.read(offset as usize, buf) {
+            Ok(()) => {}
+            Err(Error::OutOfRange) => return Err(VpdError::OutOfRange.into()),
+            Err(err) => {
+                ringbuf_entry!(Trace::ReadError(index, err.into()));
+                return Err(VpdError::DeviceError.into());
+            }
+        }
+
+        data.write_range(0..buf.len(), buf)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        Ok(())
+    }
+
+    fn read_field(
+        &mut self,
+        _: &RecvMessage,
+        index: u8,
+        field: VpdField,
+        value: LenLimit<Leased<W, [u8]>, MAX_FIELD>,
+    ) -> Result<u32, RequestError<VpdError>> {
+        let field = match field {
+            VpdField::Manufacturer => vpd::Field::Manufacturer,
+            VpdField::Product => vpd::Field::Product,
+            VpdField::PartNumber => vpd::Field::PartNumber,
+            VpdField::Revision => vpd::Field::Revision,
+            VpdField::SerialNumber => vpd::Field::SerialNumber,
+        };
+
+        let mut out = [0u8; MAX_FIELD];
+        let data = self.vpd(index)?;
+
+        let len = vpd::read_field(data, field, &mut out).map_err(|err| {
+            ringbuf_entry!(Trace::ParseError(index, err));
+            VpdError::from(err)
+        })?;
+
+        let n = len.min(value.len());
+
+        value
+            .write_range(0..n, &out[..n])
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        Ok(len as u32)
+    }
+
+    fn get_mac_block(
+        &mut self,
+        _: &RecvMessage,
+        index: u8,
+    ) -> Result<MacBlock, RequestError<VpdError>> {
+        let data = self.vpd(index)?;
+
+        let block = vpd::read_mac_block(data).map_err(|err| {
+            ringbuf_entry!(Trace::ParseError(index, err));
+            VpdError::from(err)
+        })?;
+
+        Ok(MacBlock {
+            base: block.base,
+            count: block.count,
+            stride: block.stride.into(),
+        })
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut server = ServerImpl {
+        eeproms: eeproms(I2C.get_task_id()),
+        buf: [0; MAX_VPD],
+    };
+
+    let mut buffer = [0; idl::INCOMING_SIZE];
+
+    loop {
+        idol_runtime::dispatch(&mut buffer, &mut server);
+    }
+}
+
+mod idl {
+    use super::{MacBlock, VpdError, VpdField};
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
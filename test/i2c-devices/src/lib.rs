@@ -0,0 +1,897 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! I2C device driver tests.
+//!
+//! These test cases exercise the drivers of `drv-i2c-devices` against
+//! simulated devices:  each scripts the registers of a simulated device, and
+//! then checks the driver's interpretation of them -- and what the driver
+//! wrote back.  See `test-i2c-mock-api` for the semantics of simulated
+//! devices.
+//!
+//! On target, the test cases are run by the test suite against the mock I2C
+//! server (which must be included in the image with the name `i2c_mock`); on
+//! the host, they are run by this crate's own tests against the in-process
+//! mock of `drv_i2c_api::mock`.  The margining test cases are included only
+//! with the `margining` feature.
+
+#![no_std]
+
+use drv_i2c_api::*;
+use drv_i2c_devices::*;
+use units::*;
+
+#[cfg(target_os = "none")]
+use test_i2c_mock_api::I2cMock;
+#[cfg(target_os = "none")]
+use userlib::*;
+
+#[cfg(not(target_os = "none"))]
+use drv_i2c_api::mock::I2cMock;
+
+#[cfg(target_os = "none")]
+task_slot!(I2C_MOCK, i2c_mock);
+
+//
+// PMBus command codes, for scripting devices that aren't driven via the
+// `pmbus` crate's command definitions.
+//
+mod cmd {
+    pub const PAGE: u8 = 0x00;
+    pub const OPERATION: u8 = 0x01;
+    pub const CLEAR_FAULTS: u8 = 0x03;
+    pub const VOUT_MODE: u8 = 0x20;
+    pub const COEFFICIENTS: u8 = 0x30;
+    pub const STATUS_WORD: u8 = 0x79;
+    pub const STATUS_VOUT: u8 = 0x7a;
+    pub const STATUS_IOUT: u8 = 0x7b;
+    pub const STATUS_INPUT: u8 = 0x7c;
+    pub const STATUS_TEMPERATURE: u8 = 0x7d;
+    pub const READ_EIN: u8 = 0x86;
+    pub const READ_VIN: u8 = 0x88;
+    pub const READ_VOUT: u8 = 0x8b;
+    pub const READ_IOUT: u8 = 0x8c;
+    pub const READ_TEMPERATURE_1: u8 = 0x8d;
+    pub const DMASEQ: u8 = 0xc6;
+    pub const DMAADDR: u8 = 0xc7;
+}
+
+/// Returns the mock, having reset it.
+fn mock() -> I2cMock {
+    #[cfg(target_os = "none")]
+    let mock = I2cMock::from(I2C_MOCK.get_task_id());
+    #[cfg(not(target_os = "none"))]
+    let mock = I2cMock::new();
+
+    mock.reset();
+    mock
+}
+
+/// Asserts that `val` is within a small tolerance of `expected`.
+fn assert_near(val: f32, expected: f32) {
+    let delta = if val > expected {
+        val - expected
+    } else {
+        expected - val
+    };
+
+    if delta > 0.001 {
+        panic!("expected {}, found {}", expected as f64, val as f64);
+    }
+}
+
+/// Returns the contents of register `reg` of `device`.
+fn register(mock: &I2cMock, device: &I2cDevice, reg: &[u8]) -> [u8; 4] {
+    let mut buf = [0u8; 4];
+    mock.get_register(device, reg, &mut buf).unwrap();
+    buf
+}
+
+/// Returns the last write to `device`.
+fn last_write(mock: &I2cMock, device: &I2cDevice) -> ([u8; 4], usize) {
+    let mut buf = [0u8; 4];
+    let len = mock.get_last_write(device, &mut buf).unwrap();
+    (buf, len)
+}
+
+/// Tests that a device that hasn't been scripted doesn't acknowledge.
+pub fn test_mock_nack() {
+    let mock = mock();
+    let mut tmp116 = tmp116::Tmp116::new(&mock.device(0x48));
+
+    match tmp116.read_temperature() {
+        Err(tmp116::Error::BadRegisterRead { code, .. }) => {
+            assert_eq!(code, ResponseCode::NoDevice);
+        }
+        _ => panic!("read of absent device succeeded"),
+    }
+}
+
+/// Tests that injected errors fail only as many transactions as specified.
+pub fn test_mock_inject_error() {
+    let mock = mock();
+    let dev = mock.device(0x48);
+    mock.set_register(&dev, &[0x00], &[0x0c, 0x80]).unwrap();
+
+    let mut tmp116 = tmp116::Tmp116::new(&dev);
+
+    mock.inject_error(&dev, ResponseCode::BusLocked, 1).unwrap();
+
+    match tmp116.read_temperature() {
+        Err(tmp116::Error::BadRegisterRead { code, .. }) => {
+            assert_eq!(code, ResponseCode::BusLocked);
+        }
+        _ => panic!("injected error not returned"),
+    }
+
+    assert_near(tmp116.read_temperature().unwrap().0, 25.0);
+
+    mock.inject_error(&dev, ResponseCode::SegmentDisconnected, 2)
+        .unwrap();
+
+    for _ in 0..2 {
+        match tmp116.read_temperature() {
+            Err(tmp116::Error::BadRegisterRead { code, .. }) => {
+                assert_eq!(code, ResponseCode::SegmentDisconnected);
+            }
+            _ => panic!("injected error not returned"),
+        }
+    }
+
+    assert_near(tmp116.read_temperature().unwrap().0, 25.0);
+}
+
+/// Tests packet error checking:  the PEC byte that ends a write alone, the
+/// checking of the PEC byte that ends a read, and the counting of the PEC
+/// byte against the limit of 255 bytes in either direction.
+pub fn test_mock_pec() {
+    let mock = mock();
+    let dev = mock.device(0x5a).with_pec();
+    mock.set_register(&dev, &[0x8b], &[0x34, 0x12]).unwrap();
+    mock.set_register(&dev, &[0x99], b"ABC").unwrap();
+
+    // The PEC of a Write Byte of 0x80 to OPERATION at 0x5a is 0xdd.
+    let mut buf = [0u8; 4];
+    dev.write(&[0x01, 0x80]).unwrap();
+    assert_eq!(mock.get_last_write(&dev, &mut buf), Ok(3));
+    assert_eq!(buf[..3], [0x01, 0x80, 0xdd]);
+
+    // A write followed by a read has no PEC byte of its own.
+    assert_eq!(dev.read_reg::<u8, u16>(0x8b), Ok(0x1234));
+    assert_eq!(mock.get_last_write(&dev, &mut buf), Ok(1));
+
+    let mut block = [0u8; 8];
+    assert_eq!(dev.read_block(0x99u8, &mut block), Ok(3));
+    assert_eq!(&block[..3], b"ABC");
+
+    //
+    // A corrupted PEC byte fails a read (but not a write, which has no PEC
+    // byte from the device), and then only the one.
+    //
+    mock.inject_error(&dev, ResponseCode::BadChecksum, 1)
+        .unwrap();
+    dev.write(&[0x01, 0x00]).unwrap();
+    assert_eq!(
+        dev.read_reg::<u8, u16>(0x8b),
+        Err(ResponseCode::BadChecksum)
+    );
+    assert_eq!(dev.read_reg::<u8, u16>(0x8b), Ok(0x1234));
+
+    mock.inject_error(&dev, ResponseCode::BadChecksum, 1)
+        .unwrap();
+    assert_eq!(
+        dev.read_block(0x99u8, &mut block),
+        Err(ResponseCode::BadChecksum)
+    );
+
+    //
+    // The PEC byte follows a read if there is one, and otherwise the write;
+    // it counts against the limit on whichever it follows.
+    //
+    let mut reg = [0u8; 255];
+    reg[0] = 0x8b;
+    assert_eq!(dev.write(&reg), Err(ResponseCode::BadArg));
+    assert_eq!(dev.read_reg_into(reg, &mut buf), Ok(2));
+
+    let mut big = [0u8; 255];
+    assert_eq!(dev.read_into(&mut big), Err(ResponseCode::BadArg));
+    assert_eq!(dev.read_into(&mut big[..254]), Ok(2));
+    assert_eq!(mock.device(0x5a).read_into(&mut big), Ok(2));
+}
+
+/// Returns the bytes of an ADM1272 `PMON_CONFIG` with sampling disabled.
+fn adm1272_config(
+    vrange: pmbus::commands::adm1272::PMON_CONFIG::VRange,
+    irange: pmbus::commands::adm1272::PMON_CONFIG::IRange,
+) -> [u8; 2] {
+    use pmbus::commands::adm1272::PMON_CONFIG::*;
+
+    let mut config = CommandData::from_slice(&[0, 0]).unwrap();
+    config.set_v_range(vrange);
+    config.set_i_range(irange);
+    config.set_v_in_enable(VInEnable::Disabled);
+    config.set_v_out_enable(VOutEnable::Disabled);
+    config.set_temp_1_enable(Temp1Enable::Disabled);
+
+    let mut bytes = [0u8; 2];
+    config.to_slice(&mut bytes);
+    bytes
+}
+
+/// Tests the ADM1272's mode-dependent coefficients in its 30 mV/60 V mode.
+pub fn test_adm1272_30mv_60v() {
+    use pmbus::commands::adm1272::*;
+
+    let mock = mock();
+    let dev = mock.device(0x10);
+    let config = adm1272_config(
+        PMON_CONFIG::VRange::Range60V,
+        PMON_CONFIG::IRange::Range30mV,
+    );
+
+    let code = |c: u8| [c];
+
+    mock.set_register(&dev, &code(PMON_CONFIG::CommandData::code()), &config)
+        .unwrap();
+
+    //
+    // With a 2 mOhm sense resistor, the current coefficients are m = 1326,
+    // b = 20480 and R = -1, and the voltage coefficients m = 6770, b = 0
+    // and R = -2.
+    //
+    mock.set_register(
+        &dev,
+        &code(READ_IOUT::CommandData::code()),
+        &3374u16.to_le_bytes(),
+    )
+    .unwrap();
+    mock.set_register(
+        &dev,
+        &code(PEAK_IOUT::CommandData::code()),
+        &3374u16.to_le_bytes(),
+    )
+    .unwrap();
+    mock.set_register(
+        &dev,
+        &code(READ_VOUT::CommandData::code()),
+        &1354u16.to_le_bytes(),
+    )
+    .unwrap();
+
+    let mut adm1272 = adm1272::Adm1272::new(&dev, Ohms(0.002));
+
+    assert_near(adm1272.read_iout().unwrap().0, 10.0);
+    assert_near(adm1272.peak_iout().unwrap().0, 10.0);
+    assert_near(adm1272.read_vout().unwrap().0, 20.0);
+    assert_near(adm1272.read_power().unwrap().0, 200.0);
+
+    //
+    // Reading VOUT must have enabled its sampling.
+    //
+    let reg = register(&mock, &dev, &code(PMON_CONFIG::CommandData::code()));
+    let config = PMON_CONFIG::CommandData::from_slice(&reg[..2]).unwrap();
+
+    assert!(matches!(
+        config.get_v_out_enable(),
+        Some(PMON_CONFIG::VOutEnable::Enabled)
+    ));
+
+    //
+    // The power coefficients are m = 35122, b = 0 and R = -3.
+    //
+    assert_near(adm1272.accumulated_power(351.22).unwrap().0, 10.0);
+}
+
+/// Tests the ADM1272's mode-dependent coefficients in its 15 mV/100 V mode.
+pub fn test_adm1272_15mv_100v() {
+    use pmbus::commands::adm1272::*;
+
+    let mock = mock();
+    let dev = mock.device(0x10);
+    let config = adm1272_config(
+        PMON_CONFIG::VRange::Range100V,
+        PMON_CONFIG::IRange::Range15mV,
+    );
+
+    let code = |c: u8| [c];
+
+    mock.set_register(&dev, &code(PMON_CONFIG::CommandData::code()), &config)
+        .unwrap();
+    mock.set_register(
+        &dev,
+        &code(READ_IOUT::CommandData::code()),
+        &3374u16.to_le_bytes(),
+    )
+    .unwrap();
+    mock.set_register(
+        &dev,
+        &code(READ_VOUT::CommandData::code()),
+        &2031u16.to_le_bytes(),
+    )
+    .unwrap();
+
+    let mut adm1272 = adm1272::Adm1272::new(&dev, Ohms(0.002));
+
+    assert_near(adm1272.read_iout().unwrap().0, 5.0);
+    assert_near(adm1272.read_vout().unwrap().0, 50.0);
+}
+
+/// Tests the ADT7420's ID check and temperature conversion.
+pub fn test_adt7420() {
+    let mock = mock();
+    let dev = mock.device(0x48);
+    mock.set_register(&dev, &[0x0b], &[0xcb]).unwrap();
+    mock.set_register(&dev, &[0x00], &[0x0c, 0x80]).unwrap();
+
+    let mut adt7420 = adt7420::Adt7420::new(&dev);
+    adt7420.validate().unwrap();
+    assert_near(adt7420.read_temperature().unwrap().0, 25.0);
+
+    mock.set_register(&dev, &[0x0b], &[0xc0]).unwrap();
+    assert!(matches!(
+        adt7420.validate(),
+        Err(adt7420::Error::BadID { id: 0xc0 })
+    ));
+}
+
+/// Tests the LM75's temperature conversion, including below zero.
+pub fn test_lm75() {
+    let mock = mock();
+    let dev = mock.device(0x48);
+    mock.set_register(&dev, &[0x00], &[0x19, 0x80]).unwrap();
+
+    let mut lm75 = lm75::Lm75::new(&dev);
+    assert_near(lm75.read_temperature().unwrap().0, 25.5);
+
+    mock.set_register(&dev, &[0x00], &[0xe7, 0x00]).unwrap();
+    assert_near(lm75.read_temperature().unwrap().0, -25.0);
+}
+
+/// Tests the MAX6634's temperature conversion.
+pub fn test_max6634() {
+    let mock = mock();
+    let dev = mock.device(0x48);
+    mock.set_register(&dev, &[0x00], &[0x0c, 0x80]).unwrap();
+
+    let mut max6634 = max6634::Max6634::new(&dev);
+    assert_near(max6634.read_temperature().unwrap().0, 25.0);
+}
+
+/// Tests the MCP9808's temperature conversion.
+pub fn test_mcp9808() {
+    let mock = mock();
+    let dev = mock.device(0x18);
+    mock.set_register(&dev, &[0x05], &[0x01, 0x91]).unwrap();
+
+    let mut mcp9808 = mcp9808::Mcp9808::new(&dev);
+    assert_near(mcp9808.read_temperature().unwrap().0, 25.0625);
+}
+
+/// Tests the PCT2075's temperature conversion.
+pub fn test_pct2075() {
+    let mock = mock();
+    let dev = mock.device(0x37);
+    mock.set_register(&dev, &[0x00], &[0x19, 0x60]).unwrap();
+
+    let mut pct2075 = pct2075::Pct2075::new(&dev);
+    assert_near(pct2075.read_temperature().unwrap().0, 25.375);
+}
+
+/// Tests the SB-TSI's integer and decimal temperature registers.
+pub fn test_sbtsi() {
+    let mock = mock();
+    let dev = mock.device(0x4c);
+    mock.set_register(&dev, &[0x01], &[45]).unwrap();
+    mock.set_register(&dev, &[0x10], &[0xa0]).unwrap();
+
+    let mut sbtsi = sbtsi::SbTsi::new(&dev);
+    assert_near(sbtsi.read_temperature().unwrap().0, 45.625);
+}
+
+/// Tests the TMP116's temperature conversion.
+pub fn test_tmp116() {
+    let mock = mock();
+    let dev = mock.device(0x48);
+    mock.set_register(&dev, &[0x00], &[0x0c, 0x80]).unwrap();
+
+    let mut tmp116 = tmp116::Tmp116::new(&dev);
+    assert_near(tmp116.read_temperature().unwrap().0, 25.0);
+}
+
+/// Tests the TMP451's local and remote targets, and its extended range.
+pub fn test_tmp451() {
+    let mock = mock();
+    let dev = mock.device(0x4c);
+    mock.set_register(&dev, &[0x03], &[0x00]).unwrap();
+    mock.set_register(&dev, &[0x00], &[30]).unwrap();
+    mock.set_register(&dev, &[0x15], &[0x40]).unwrap();
+    mock.set_register(&dev, &[0x01], &[85]).unwrap();
+    mock.set_register(&dev, &[0x10], &[0x80]).unwrap();
+
+    let mut local = tmp451::Tmp451::new(&dev, tmp451::Target::Local);
+    let mut remote = tmp451::Tmp451::new(&dev, tmp451::Target::Remote);
+
+    assert_near(local.read_temperature().unwrap().0, 30.25);
+    assert_near(remote.read_temperature().unwrap().0, 85.5);
+
+    //
+    // In the extended range, temperatures are offset by 64 degrees.  (The
+    // range is read once, so we need a new driver to see it.)
+    //
+    mock.set_register(&dev, &[0x03], &[0x04]).unwrap();
+    mock.set_register(&dev, &[0x01], &[149]).unwrap();
+    mock.set_register(&dev, &[0x10], &[0x00]).unwrap();
+
+    let mut remote = tmp451::Tmp451::new(&dev, tmp451::Target::Remote);
+    assert_near(remote.read_temperature().unwrap().0, 85.0);
+}
+
+/// Tests the TSE2004av's temperature conversion, ignoring its alarm flags.
+pub fn test_tse2004av() {
+    let mock = mock();
+    let dev = mock.device(0x18);
+    mock.set_register(&dev, &[0x05], &[0x01, 0x91]).unwrap();
+
+    let mut tse2004av = tse2004av::Tse2004Av::new(&dev);
+    assert_near(tse2004av.read_temperature().unwrap().0, 25.0625);
+
+    // With all three alarm flags set, and below zero
+    mock.set_register(&dev, &[0x05], &[0xe1, 0x91]).unwrap();
+    assert_near(tse2004av.read_temperature().unwrap().0, 25.0625);
+
+    mock.set_register(&dev, &[0x05], &[0xff, 0xc0]).unwrap();
+    assert_near(tse2004av.read_temperature().unwrap().0, -4.0);
+}
+
+/// Tests AT24 reads and page-split writes with single-byte addressing.
+pub fn test_at24() {
+    let mock = mock();
+    let dev = mock.device(0x50);
+    mock.set_register(&dev, &[0x10], &[1, 2, 3, 4]).unwrap();
+
+    let at24 = at24::At24::new(&dev, at24::At24Part::At24c02);
+
+    let mut buf = [0u8; 4];
+    at24.read(0x10, &mut buf).unwrap();
+    assert_eq!(buf, [1, 2, 3, 4]);
+
+    //
+    // A write of ten bytes at 0x06 spans the end of the first (8-byte) page,
+    // and must therefore be split into writes at 0x06 and 0x08.
+    //
+    let data = [0xa0, 0xa1, 0xa2, 0xa3, 0xa4, 0xa5, 0xa6, 0xa7, 0xa8, 0xa9];
+    at24.write(0x06, &data).unwrap();
+
+    let mut buf = [0u8; 8];
+    assert_eq!(mock.get_register(&dev, &[0x06], &mut buf), Ok(2));
+    assert_eq!(buf[..2], data[..2]);
+    assert_eq!(mock.get_register(&dev, &[0x08], &mut buf), Ok(8));
+    assert_eq!(buf, data[2..]);
+
+    let mut buf = [0u8; 4];
+    assert!(matches!(
+        at24.read(0xfe, &mut buf),
+        Err(at24::Error::OutOfRange)
+    ));
+}
+
+/// Tests that AT24 offsets beyond 256 bytes select the device address.
+pub fn test_at24_block_address() {
+    let mock = mock();
+    let dev = mock.device(0x50);
+    let upper = mock.device(0x51);
+    mock.set_register(&upper, &[0x10], &[0x55, 0xaa]).unwrap();
+
+    let at24 = at24::At24::new(&dev, at24::At24Part::At24c04);
+
+    let mut buf = [0u8; 2];
+    at24.read(0x110, &mut buf).unwrap();
+    assert_eq!(buf, [0x55, 0xaa]);
+}
+
+/// Tests DS2482 register reads and 1-wire byte operations.
+pub fn test_ds2482() {
+    let mock = mock();
+    let dev = mock.device(0x18);
+
+    //
+    // Registers are read by setting the read pointer, so are keyed by the
+    // command and the register.
+    //
+    mock.set_register(&dev, &[0xe1, 0xf0], &[0x00]).unwrap();
+    mock.set_register(&dev, &[0xe1, 0xe1], &[0x5a]).unwrap();
+
+    let ds2482 = ds2482::Ds2482::new(&dev);
+    assert_eq!(ds2482.read_byte().unwrap(), 0x5a);
+
+    ds2482.write_byte(0x3c).unwrap();
+    let (buf, len) = last_write(&mock, &dev);
+    assert_eq!(&buf[..len], &[0xa5, 0x3c]);
+
+    mock.inject_error(&dev, ResponseCode::SegmentDisconnected, 1)
+        .unwrap();
+
+    match ds2482.read_byte() {
+        Err(ds2482::Error::BadRegisterRead { code, .. }) => {
+            assert_eq!(code, ResponseCode::SegmentDisconnected);
+        }
+        _ => panic!("injected error not returned"),
+    }
+}
+
+/// Tests EMC2305 initialization, tach conversion, PWM and fault status.
+pub fn test_emc2305() {
+    let mock = mock();
+    let dev = mock.device(0x2e);
+    mock.set_register(&dev, &[0xfd], &[0x34]).unwrap();
+
+    let emc2305 = emc2305::Emc2305::new(&dev);
+    emc2305.initialize().unwrap();
+    assert_eq!(register(&mock, &dev, &[0x30])[0], 0);
+
+    let fan = emc2305::Fan::from(1);
+    mock.set_register(&dev, &[0x4e], &[0x3d]).unwrap();
+    mock.set_register(&dev, &[0x4f], &[0x70]).unwrap();
+    assert_eq!(emc2305.fan_rpm(fan).unwrap().0, 4000);
+
+    mock.set_register(&dev, &[0x4e], &[0xff]).unwrap();
+    mock.set_register(&dev, &[0x4f], &[0xf8]).unwrap();
+    assert_eq!(emc2305.fan_rpm(fan).unwrap().0, 0);
+
+    emc2305.set_pwm(fan, emc2305::PWMDuty(50)).unwrap();
+    assert_eq!(register(&mock, &dev, &[0x40])[0], 127);
+
+    mock.set_register(&dev, &[0x25], &[0b001]).unwrap();
+    mock.set_register(&dev, &[0x26], &[0b000]).unwrap();
+    mock.set_register(&dev, &[0x27], &[0b100]).unwrap();
+    assert_eq!(emc2305.fan_faults().unwrap(), emc2305::FanFaults(0b101));
+
+    mock.set_register(&dev, &[0xfd], &[0x35]).unwrap();
+    assert_eq!(emc2305.initialize(), Err(ResponseCode::BadResponse));
+}
+
+/// Tests MAX31790 initialization, tach conversion, PWM and fault status.
+pub fn test_max31790() {
+    let mock = mock();
+    let dev = mock.device(0x20);
+    mock.set_register(&dev, &[0x00], &[0x00]).unwrap();
+
+    for reg in 0x02..=0x07 {
+        mock.set_register(&dev, &[reg], &[0x00]).unwrap();
+    }
+
+    let max31790 = max31790::Max31790::new(&dev);
+    max31790.initialize().unwrap();
+
+    for reg in 0x02..=0x07 {
+        assert_eq!(register(&mock, &dev, &[reg])[0], 1 << 3);
+    }
+
+    mock.set_register(&dev, &[0x18], &[30, 0]).unwrap();
+    let fan = max31790::Fan::from(0);
+    assert_eq!(max31790.fan_rpm(fan).unwrap().0, 4096);
+
+    mock.set_register(&dev, &[0x18], &[0xff, 0xe0]).unwrap();
+    assert_eq!(max31790.fan_rpm(fan).unwrap().0, 0);
+
+    let fan = max31790::Fan::from(1);
+    max31790.set_pwm(fan, max31790::PWMDuty(50)).unwrap();
+    assert_eq!(register(&mock, &dev, &[0x42])[..2], [0x7f, 0x80]);
+
+    mock.set_register(&dev, &[0x11], &[0b10]).unwrap();
+    mock.set_register(&dev, &[0x10], &[0b01]).unwrap();
+
+    let faults = max31790.fan_faults().unwrap();
+    assert_eq!(faults, max31790::FanFaults((1 << 6) | 0b10));
+    assert!(faults.is_faulted(fan));
+
+    max31790.clear_fan_faults().unwrap();
+    assert_eq!(register(&mock, &dev, &[0x10])[0], 0);
+    assert_eq!(register(&mock, &dev, &[0x11])[0], 0);
+}
+
+/// Tests the INA226's shunt and bus voltage conversions.
+pub fn test_ina226() {
+    let mock = mock();
+    let dev = mock.device(0x40);
+    mock.set_register(&dev, &[0x01], &[0x07, 0xd0]).unwrap();
+    mock.set_register(&dev, &[0x02], &[0x25, 0x80]).unwrap();
+
+    let mut ina226 = ina226::Ina226::new(&dev, Ohms(0.002));
+
+    assert_near(ina226.read_shunt_voltage().unwrap().0, 0.005);
+    assert_near(ina226.read_iout().unwrap().0, 2.5);
+    assert_near(ina226.read_vout().unwrap().0, 12.0);
+    assert_near(ina226.read_power().unwrap().0, 30.0);
+
+    mock.set_register(&dev, &[0x01], &[0xf8, 0x30]).unwrap();
+    assert_near(ina226.read_iout().unwrap().0, -2.5);
+}
+
+/// Tests the generic PMBus driver in the linear format on a given page.
+pub fn test_pmbus_device_linear() {
+    let mock = mock();
+    let dev = mock.device(0x60);
+    mock.set_register(&dev, &[cmd::VOUT_MODE], &[0x17]).unwrap();
+    mock.set_register(&dev, &[cmd::READ_VOUT], &[0x00, 0x02])
+        .unwrap();
+    mock.set_register(&dev, &[cmd::READ_IOUT], &[0x19, 0xf8])
+        .unwrap();
+
+    let mut pmbus = pmbus_device::PmbusDevice::new(&dev, Some(2));
+
+    assert_near(pmbus.read_vout().unwrap().0, 1.0);
+    assert_near(pmbus.read_iout().unwrap().0, 12.5);
+    assert_eq!(register(&mock, &dev, &[cmd::PAGE])[0], 2);
+
+    //
+    // READ_POUT is optional, so we don't read it (and it isn't scripted).
+    //
+    assert_near(pmbus.read_power().unwrap().0, 12.5);
+}
+
+/// Tests the generic PMBus driver in the direct format.
+pub fn test_pmbus_device_direct() {
+    let mock = mock();
+    let dev = mock.device(0x60);
+    mock.set_register(&dev, &[cmd::VOUT_MODE], &[0x40]).unwrap();
+    mock.set_register(&dev, &[cmd::READ_VOUT], &1200u16.to_le_bytes())
+        .unwrap();
+    mock.set_register(&dev, &[cmd::READ_IOUT], &250u16.to_le_bytes())
+        .unwrap();
+
+    let c = |m, b, r| pmbus::Coefficients { m, b, R: r };
+
+    let coefficients = pmbus_device::DirectCoefficients {
+        voltage: c(1, 0, 2),
+        current: c(10, 0, 0),
+        temperature: c(1, 0, 0),
+    };
+
+    let mut pmbus =
+        pmbus_device::PmbusDevice::with_coefficients(&dev, None, coefficients);
+
+    assert_near(pmbus.read_vout().unwrap().0, 12.0);
+    assert_near(pmbus.read_iout().unwrap().0, 25.0);
+
+    //
+    // Without coefficients, the direct format is unsupported.
+    //
+    let mut pmbus = pmbus_device::PmbusDevice::new(&dev, None);
+
+    assert_eq!(
+        pmbus.read_vout(),
+        Err(pmbus_device::Error::UnsupportedMode { mode: 0x40 })
+    );
+}
+
+/// Scripts the telemetry common to the PMBus regulator tests.
+fn pmbus_telemetry(mock: &I2cMock, dev: &I2cDevice, vout: u16) {
+    mock.set_register(dev, &[cmd::VOUT_MODE], &[0x17]).unwrap();
+    mock.set_register(dev, &[cmd::READ_VOUT], &vout.to_le_bytes())
+        .unwrap();
+    mock.set_register(dev, &[cmd::READ_IOUT], &[0x19, 0xf8])
+        .unwrap();
+    mock.set_register(dev, &[cmd::READ_TEMPERATURE_1], &[0x28, 0x00])
+        .unwrap();
+    mock.set_register(dev, &[cmd::READ_VIN], &[0x36, 0x00])
+        .unwrap();
+    mock.set_register(dev, &[cmd::OPERATION], &[0x80]).unwrap();
+    mock.set_register(dev, &[cmd::STATUS_WORD], &[0x42, 0x08])
+        .unwrap();
+    mock.set_register(dev, &[cmd::STATUS_VOUT], &[0x80])
+        .unwrap();
+    mock.set_register(dev, &[cmd::STATUS_IOUT], &[0x00])
+        .unwrap();
+    mock.set_register(dev, &[cmd::STATUS_INPUT], &[0x10])
+        .unwrap();
+    mock.set_register(dev, &[cmd::STATUS_TEMPERATURE], &[0x00])
+        .unwrap();
+}
+
+/// Checks the status scripted by [`pmbus_telemetry`].
+fn check_status(status: PmbusStatus) {
+    assert_eq!(status.word, 0x0842);
+    assert_eq!(status.vout, 0x80);
+    assert_eq!(status.iout, 0x00);
+    assert_eq!(status.input, 0x10);
+    assert_eq!(status.temperature, 0x00);
+}
+
+/// Tests the ISL68224's telemetry, margining and black box.
+pub fn test_isl68224() {
+    let mock = mock();
+    let dev = mock.device(0x60);
+    pmbus_telemetry(&mock, &dev, 0x0180);
+
+    let mut rail1 = isl68224::Isl68224::new(&dev, 1);
+
+    assert_near(rail1.read_vout().unwrap().0, 0.75);
+    assert_near(rail1.read_iout().unwrap().0, 12.5);
+    assert_eq!(register(&mock, &dev, &[cmd::PAGE])[0], 1);
+    check_status(rail1.read_status().unwrap());
+
+    #[cfg(feature = "margining")]
+    {
+        rail1.set_margin(Margin::High).unwrap();
+        assert_eq!(register(&mock, &dev, &[cmd::OPERATION])[0], 0xa8);
+    }
+
+    mock.set_register(&dev, &[cmd::DMASEQ], &[0x78, 0x56, 0x34, 0x12])
+        .unwrap();
+
+    //
+    // The black box is read only if its address is given...
+    //
+    let mut rail0 = isl68224::Isl68224::new(&dev, 0);
+    assert!(rail0.read_blackbox().unwrap().is_none());
+
+    //
+    // ...and is reported only via the first rail.
+    //
+    let mut rail1 = isl68224::Isl68224::with_blackbox(&dev, 1, 0xab00);
+    assert!(rail1.read_blackbox().unwrap().is_none());
+
+    let mut rail0 = isl68224::Isl68224::with_blackbox(&dev, 0, 0xab00);
+    let blackbox = rail0.read_blackbox().unwrap().unwrap();
+
+    assert!(blackbox.words.iter().all(|&w| w == 0x1234_5678));
+    assert_eq!(register(&mock, &dev, &[cmd::DMAADDR])[..2], [0x00, 0xab]);
+}
+
+/// Tests the RAA229618's telemetry, status and energy accumulator.
+pub fn test_raa229618() {
+    let mock = mock();
+    let dev = mock.device(0x60);
+    pmbus_telemetry(&mock, &dev, 0x0180);
+    mock.set_register(&dev, &[cmd::READ_EIN], &[0x34, 0x12, 2, 100, 0, 0])
+        .unwrap();
+
+    // m = 2, b = 0, R = -1:  each LSB of READ_EIN is 5 W.
+    mock.set_register(&dev, &[cmd::COEFFICIENTS], &[2, 0, 0, 0, 0xff])
+        .unwrap();
+
+    let mut raa229618 = raa229618::Raa229618::new(&dev, 1);
+
+    assert_near(raa229618.read_vout().unwrap().0, 0.75);
+    assert_near(raa229618.read_temperature().unwrap().0, 40.0);
+    check_status(raa229618.read_status().unwrap());
+
+    let ein = raa229618.read_ein().unwrap();
+    assert_eq!(ein.energy, 2 * EnergyCount::ACCUMULATOR_MODULUS + 0x1234);
+    assert_eq!(ein.samples, 100);
+    assert_eq!(register(&mock, &dev, &[cmd::PAGE])[0], 1);
+
+    assert_near(raa229618.accumulated_power(3.0).unwrap().0, 15.0);
+    let (buf, len) = last_write(&mock, &dev);
+    assert_eq!(&buf[..len], &[cmd::COEFFICIENTS, 2, cmd::READ_EIN, 1]);
+
+    // The coefficients are cached...
+    raa229618.read_status().unwrap();
+    assert_near(raa229618.accumulated_power(2.0).unwrap().0, 10.0);
+    assert_eq!(last_write(&mock, &dev).0[0], cmd::STATUS_TEMPERATURE);
+
+    // ...and an m of zero is refused.
+    mock.set_register(&dev, &[cmd::COEFFICIENTS], &[0, 0, 0, 0, 0])
+        .unwrap();
+
+    let mut fresh = raa229618::Raa229618::new(&dev, 1);
+
+    match fresh.accumulated_power(2.0) {
+        Err(err) => {
+            assert_eq!(ResponseCode::from(err), ResponseCode::BadResponse)
+        }
+        Ok(_) => panic!("expected coefficients to be refused"),
+    }
+
+    raa229618.clear_faults().unwrap();
+    let (buf, len) = last_write(&mock, &dev);
+    assert_eq!(&buf[..len], &[cmd::CLEAR_FAULTS]);
+}
+
+/// Tests the decoding of energy accumulator readings, and the differencing
+/// of readings across the rollover of either sum.
+pub fn test_energy_count() {
+    let max = EnergyCount::from_block(&[0xff, 0x7f, 0xff, 0xff, 0xff, 0xff]);
+    assert_eq!(max.energy, EnergyCount::ENERGY_MODULUS - 1);
+    assert_eq!(max.samples, EnergyCount::SAMPLES_MODULUS - 1);
+
+    let count = EnergyCount::from_block(&[0x10, 0x00, 0x01, 0x20, 0x00, 0x00]);
+    assert_eq!(count.energy, EnergyCount::ACCUMULATOR_MODULUS + 0x10);
+    assert_eq!(count.samples, 0x20);
+
+    let prev = EnergyCount {
+        energy: 1000,
+        samples: 10,
+    };
+
+    let now = EnergyCount {
+        energy: 1600,
+        samples: 40,
+    };
+
+    assert_near(now.average_since(&prev).unwrap(), 20.0);
+
+    //
+    // The energy sum rolling over (accumulator and rollover count both)...
+    //
+    let prev = EnergyCount {
+        energy: EnergyCount::ENERGY_MODULUS - 100,
+        samples: 10,
+    };
+
+    let now = EnergyCount {
+        energy: 200,
+        samples: 15,
+    };
+
+    assert_near(now.average_since(&prev).unwrap(), 60.0);
+
+    //
+    // ...as well as the sample count...
+    //
+    let prev = EnergyCount {
+        energy: 100,
+        samples: EnergyCount::SAMPLES_MODULUS - 2,
+    };
+
+    let now = EnergyCount {
+        energy: 400,
+        samples: 3,
+    };
+
+    assert_near(now.average_since(&prev).unwrap(), 60.0);
+
+    //
+    // ...and both of them together.
+    //
+    assert_near(
+        count.average_since(&max).unwrap(),
+        0x8011 as f32 / 0x21 as f32,
+    );
+
+    // No samples means no average.
+    assert_eq!(now.average_since(&now), None);
+}
+
+/// Tests the BMR491's telemetry and status.
+pub fn test_bmr491() {
+    let mock = mock();
+    let dev = mock.device(0x67);
+    pmbus_telemetry(&mock, &dev, 0x1800);
+
+    let mut bmr491 = bmr491::Bmr491::new(&dev, 0);
+
+    assert_near(bmr491.read_vout().unwrap().0, 12.0);
+    assert_near(bmr491.read_iout().unwrap().0, 12.5);
+    assert_near(bmr491.read_temperature().unwrap().0, 40.0);
+    assert_near(bmr491.read_vin().unwrap().0, 54.0);
+    assert_near(bmr491.read_power().unwrap().0, 150.0);
+    check_status(bmr491.read_status().unwrap());
+
+    bmr491.clear_faults().unwrap();
+    let (buf, len) = last_write(&mock, &dev);
+    assert_eq!(&buf[..len], &[cmd::CLEAR_FAULTS]);
+}
+
+/// Tests the TPS546B24A's telemetry, status and margining.
+pub fn test_tps546b24a() {
+    let mock = mock();
+    let dev = mock.device(0x24);
+    pmbus_telemetry(&mock, &dev, 0x0300);
+
+    let mut tps546b24a = tps546b24a::Tps546b24a::new(&dev, 0);
+
+    assert_near(tps546b24a.read_vout().unwrap().0, 1.5);
+    assert_near(tps546b24a.read_iout().unwrap().0, 12.5);
+    assert_near(tps546b24a.read_temperature().unwrap().0, 40.0);
+    assert_near(tps546b24a.read_vin().unwrap().0, 54.0);
+    check_status(tps546b24a.read_status().unwrap());
+
+    #[cfg(feature = "margining")]
+    {
+        tps546b24a.set_margin(Margin::Low).unwrap();
+        assert_eq!(register(&mock, &dev, &[cmd::OPERATION])[0], 0x98);
+    }
+}
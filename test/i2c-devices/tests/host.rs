@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Host tests of the drivers against the in-process mock of
+//! `drv_i2c_api::mock`.  The test cases are those that the test suite runs
+//! on target against the mock I2C server; run with `--features margining`
+//! to include the margining cases.
+
+#![cfg(not(target_os = "none"))]
+
+macro_rules! host_tests {
+    ($($name:ident,)*) => {
+        $(
+            #[test]
+            fn $name() {
+                test_i2c_devices::$name();
+            }
+        )*
+    };
+}
+
+host_tests! {
+    test_mock_nack,
+    test_mock_inject_error,
+    test_mock_pec,
+    test_adm1272_30mv_60v,
+    test_adm1272_15mv_100v,
+    test_adt7420,
+    test_lm75,
+    test_max6634,
+    test_mcp9808,
+    test_pct2075,
+    test_sbtsi,
+    test_tmp116,
+    test_tmp451,
+    test_tse2004av,
+    test_at24,
+    test_at24_block_address,
+    test_ds2482,
+    test_emc2305,
+    test_max31790,
+    test_ina226,
+    test_pmbus_device_linear,
+    test_pmbus_device_direct,
+    test_isl68224,
+    test_raa229618,
+    test_energy_count,
+    test_bmr491,
+    test_tps546b24a,
+}
@@ -0,0 +1,208 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for the mock I2C server
+//!
+//! The mock I2C server speaks the protocol of an I2C server -- an
+//! [`I2cDevice`] whose task is the mock is used exactly as any other -- but
+//! its devices are simulated.  Each device is a map of registers, keyed by
+//! the bytes written to select them (all of the same width for a given
+//! device).  A test scripts the registers ahead of time; thereafter:
+//!
+//! - A write of a register address selects that register (e.g., for a
+//!   subsequent read without a write).
+//! - A write of a register address followed by data replaces the contents of
+//!   that register with the data, creating it if needed -- unless the
+//!   transaction also reads, in which case (as in an SMBus process call) the
+//!   data are arguments, and the register is left alone.
+//! - A read returns the contents of the selected register; a register that
+//!   has not been scripted (or written) results in
+//!   [`ResponseCode::NoRegister`].
+//! - A transaction with an address at which no device has been scripted
+//!   results in [`ResponseCode::NoDevice`], just as a NACK would.
+//!
+//! Registers are not paged:  a write to a PMBus device's `PAGE` merely sets
+//! the `PAGE` register.  A test may also arrange for a device's next
+//! transactions to fail with a given [`ResponseCode`] (e.g.,
+//! [`ResponseCode::BusLocked`]), and may examine the last write to a device.
+//!
+//! A device used with packet error checking (see [`I2cDevice::with_pec`]) is
+//! the same device as without it, but its transactions carry a PEC byte as
+//! they would on the bus:  the PEC byte of a transaction that only writes is
+//! part of the device's last write, and the device sends a PEC byte at the
+//! end of a transaction that reads, which is checked.  Injecting
+//! [`ResponseCode::BadChecksum`] corrupts that PEC byte (rather than failing
+//! the transaction outright) for the device's next reads with PEC.
+//!
+//! The simulated devices are modelled by `drv_i2c_api::sim`.  On the host,
+//! `drv_i2c_api::mock` simulates devices with the same model (and offers the
+//! same interface as this crate), so that the driver tests of
+//! `test-i2c-devices` also run there.
+
+#![no_std]
+
+use drv_i2c_api::sim::device_key;
+use drv_i2c_api::*;
+use userlib::*;
+use zerocopy::AsBytes;
+
+/// Operations understood by the mock I2C server.  The first two are those of
+/// the I2C server itself (see [`drv_i2c_api::Op`]); the remainder are for
+/// scripting devices.
+#[derive(Copy, Clone, Debug, FromPrimitive, PartialEq)]
+pub enum MockOp {
+    WriteRead = 1,
+    WriteReadBlock = 2,
+
+    /// Forgets all devices (`() -> ()`).
+    Reset = 0x80,
+
+    /// Sets a register (`[u8; 4]`, leasing the register address and its
+    /// contents, `-> ()`).
+    SetRegister = 0x81,
+
+    /// Gets a register (`[u8; 4]`, leasing the register address and a buffer
+    /// for its contents, `-> usize`).
+    GetRegister = 0x82,
+
+    /// Gets the last write to a device (`[u8; 4]`, leasing a buffer for it,
+    /// `-> usize`).
+    GetLastWrite = 0x83,
+
+    /// Fails a device's next transactions (`[u8; 12] -> ()`):  the device,
+    /// the response code and the number of transactions, the latter two as
+    /// little-endian `u32`s.
+    InjectError = 0x84,
+}
+
+pub use drv_i2c_api::sim::{MAX_REGISTER, MAX_VALUE, MAX_WRITE};
+
+pub struct I2cMock(TaskId);
+
+impl From<TaskId> for I2cMock {
+    fn from(task: TaskId) -> Self {
+        Self(task)
+    }
+}
+
+fn check(code: u32) -> Result<(), ResponseCode> {
+    if code != 0 {
+        Err(ResponseCode::from_u32(code).ok_or(ResponseCode::BadResponse)?)
+    } else {
+        Ok(())
+    }
+}
+
+impl I2cMock {
+    ///
+    /// Returns a device on the mock controller at `address`, whose I2C
+    /// server is the mock.
+    ///
+    pub fn device(&self, address: u8) -> I2cDevice {
+        I2cDevice::new(self.0, Controller::Mock, PortIndex(0), None, address)
+    }
+
+    /// Forgets all devices, their registers and any injected errors.
+    pub fn reset(&self) {
+        let (code, _) =
+            sys_send(self.0, MockOp::Reset as u16, &[], &mut [], &[]);
+        check(code).unwrap();
+    }
+
+    ///
+    /// Sets register `reg` of `device` to `value`, creating the device if
+    /// needed.  The width of `reg` must match that of any other register of
+    /// the device.
+    ///
+    pub fn set_register(
+        &self,
+        device: &I2cDevice,
+        reg: &[u8],
+        value: &[u8],
+    ) -> Result<(), ResponseCode> {
+        let (code, _) = sys_send(
+            self.0,
+            MockOp::SetRegister as u16,
+            &device_key(device),
+            &mut [],
+            &[Lease::from(reg), Lease::from(value)],
+        );
+
+        check(code)
+    }
+
+    ///
+    /// Reads register `reg` of `device` into `buf`, returning the size of
+    /// its contents.
+    ///
+    pub fn get_register(
+        &self,
+        device: &I2cDevice,
+        reg: &[u8],
+        buf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        let mut response = 0_usize;
+
+        let (code, _) = sys_send(
+            self.0,
+            MockOp::GetRegister as u16,
+            &device_key(device),
+            response.as_bytes_mut(),
+            &[Lease::from(reg), Lease::from(buf)],
+        );
+
+        check(code).map(|_| response)
+    }
+
+    ///
+    /// Reads the last write to `device` (register address included) into
+    /// `buf`, returning its size; a write of more than [`MAX_WRITE`] bytes is
+    /// truncated.
+    ///
+    pub fn get_last_write(
+        &self,
+        device: &I2cDevice,
+        buf: &mut [u8],
+    ) -> Result<usize, ResponseCode> {
+        let mut response = 0_usize;
+
+        let (code, _) = sys_send(
+            self.0,
+            MockOp::GetLastWrite as u16,
+            &device_key(device),
+            response.as_bytes_mut(),
+            &[Lease::from(buf)],
+        );
+
+        check(code).map(|_| response)
+    }
+
+    ///
+    /// Fails the next `count` transactions with `device` with `code` (e.g.,
+    /// [`ResponseCode::NoDevice`] to simulate a NACK).  With
+    /// [`ResponseCode::BadChecksum`], it is instead the PEC byte sent by the
+    /// device in its next `count` reads with PEC that is corrupted.
+    ///
+    pub fn inject_error(
+        &self,
+        device: &I2cDevice,
+        code: ResponseCode,
+        count: u32,
+    ) -> Result<(), ResponseCode> {
+        let mut payload = [0u8; 12];
+        payload[..4].copy_from_slice(&device_key(device));
+        payload[4..8].copy_from_slice(&(code as u32).to_le_bytes());
+        payload[8..].copy_from_slice(&count.to_le_bytes());
+
+        let (code, _) = sys_send(
+            self.0,
+            MockOp::InjectError as u16,
+            &payload,
+            &mut [],
+            &[],
+        );
+
+        check(code)
+    }
+}
@@ -0,0 +1,178 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Mock I2C server
+//!
+//! This task serves the I2C server's protocol from simulated devices rather
+//! than from a bus, allowing device drivers to be exercised without their
+//! devices.  Devices are scripted (and examined) via the additional
+//! operations of `test_i2c_mock_api::MockOp`; see that crate for the
+//! semantics of the simulated devices, which are modelled by
+//! `drv_i2c_api::sim` (as they are by the in-process mock on the host).
+//!
+//! Transactions are validated just as the I2C server validates them (e.g.,
+//! a transaction may not exceed 255 bytes in either direction), so that
+//! a driver that works against the mock doesn't fall foul of the server.
+
+#![no_std]
+#![no_main]
+
+use drv_i2c_api::sim::*;
+use drv_i2c_api::*;
+use test_i2c_mock_api::MockOp;
+use userlib::*;
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut mock = Devices::new();
+    let mut buffer = [0; 12];
+
+    loop {
+        hl::recv_without_notification(&mut buffer, |op, msg| match op {
+            MockOp::WriteRead | MockOp::WriteReadBlock => {
+                let (payload, caller) = msg
+                    .fixed_with_leases::<[u8; 5], usize>(2)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                let block = op == MockOp::WriteReadBlock;
+
+                let wbuf = caller.borrow(0);
+                let winfo = wbuf.info().ok_or(ResponseCode::BadArg)?;
+
+                if !winfo.attributes.contains(LeaseAttributes::READ) {
+                    return Err(ResponseCode::BadArg);
+                }
+
+                let rbuf = caller.borrow(1);
+                let rinfo = rbuf.info().ok_or(ResponseCode::BadArg)?;
+
+                let (key, pec) =
+                    check_transaction(payload, block, winfo.len, rinfo.len)?;
+
+                let mut w = [0u8; MAX_TRANSFER];
+                let w = &mut w[..winfo.len];
+                wbuf.read_fully_at(0, w).ok_or(ResponseCode::BadArg)?;
+
+                let mut r = [0u8; MAX_TRANSFER];
+                let r = &mut r[..rinfo.len];
+
+                let n = mock.write_read(key, pec, block, w, r)?;
+
+                rbuf.write_fully_at(0, &r[..n])
+                    .ok_or(ResponseCode::BadArg)?;
+                caller.reply(n);
+                Ok(())
+            }
+
+            MockOp::Reset => {
+                let (_, caller) =
+                    msg.fixed::<(), ()>().ok_or(ResponseCode::BadArg)?;
+
+                mock = Devices::new();
+                caller.reply(());
+                Ok(())
+            }
+
+            MockOp::SetRegister => {
+                let (&key, caller) = msg
+                    .fixed_with_leases::<DeviceKey, ()>(2)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                let mut reg = [0u8; MAX_REGISTER];
+                let mut val = [0u8; MAX_VALUE];
+
+                let reg = read_lease(&caller.borrow(0), &mut reg)?;
+                let val = read_lease(&caller.borrow(1), &mut val)?;
+
+                mock.set_register(key, reg, val)?;
+                caller.reply(());
+                Ok(())
+            }
+
+            MockOp::GetRegister => {
+                let (&key, caller) = msg
+                    .fixed_with_leases::<DeviceKey, usize>(2)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                let mut reg = [0u8; MAX_REGISTER];
+                let reg = read_lease(&caller.borrow(0), &mut reg)?;
+
+                let mut value = [0u8; MAX_VALUE];
+                let len = mock.get_register(key, reg, &mut value)?;
+
+                write_lease(&caller.borrow(1), &value[..len])?;
+                caller.reply(len);
+                Ok(())
+            }
+
+            MockOp::GetLastWrite => {
+                let (&key, caller) = msg
+                    .fixed_with_leases::<DeviceKey, usize>(1)
+                    .ok_or(ResponseCode::BadArg)?;
+
+                let mut last = [0u8; MAX_WRITE];
+                let len = mock.get_last_write(key, &mut last)?;
+
+                write_lease(&caller.borrow(0), &last[..len])?;
+                caller.reply(len);
+                Ok(())
+            }
+
+            MockOp::InjectError => {
+                let (payload, caller) =
+                    msg.fixed::<[u8; 12], ()>().ok_or(ResponseCode::BadArg)?;
+
+                let mut key = [0u8; 4];
+                key.copy_from_slice(&payload[..4]);
+
+                let mut word = [0u8; 4];
+                word.copy_from_slice(&payload[4..8]);
+                let code = ResponseCode::from_u32(u32::from_le_bytes(word))
+                    .ok_or(ResponseCode::BadArg)?;
+
+                word.copy_from_slice(&payload[8..]);
+                let count = u32::from_le_bytes(word);
+
+                mock.inject_error(key, code, count)?;
+
+                caller.reply(());
+                Ok(())
+            }
+        });
+    }
+}
+
+/// Reads a lease into `buf`, returning the portion of `buf` that it filled.
+fn read_lease<'a>(
+    lease: &hl::Borrow<'_>,
+    buf: &'a mut [u8],
+) -> Result<&'a [u8], ResponseCode> {
+    let info = lease.info().ok_or(ResponseCode::BadArg)?;
+
+    if !info.attributes.contains(LeaseAttributes::READ) {
+        return Err(ResponseCode::BadArg);
+    }
+
+    let buf = buf.get_mut(..info.len).ok_or(ResponseCode::BadArg)?;
+    lease.read_fully_at(0, buf).ok_or(ResponseCode::BadArg)?;
+
+    Ok(buf)
+}
+
+/// Writes as much of `data` into a lease as it will hold.
+fn write_lease(
+    lease: &hl::Borrow<'_>,
+    data: &[u8],
+) -> Result<(), ResponseCode> {
+    let info = lease.info().ok_or(ResponseCode::BadArg)?;
+
+    if !info.attributes.contains(LeaseAttributes::WRITE) {
+        return Err(ResponseCode::BadArg);
+    }
+
+    let n = data.len().min(info.len);
+    lease
+        .write_fully_at(0, &data[..n])
+        .ok_or(ResponseCode::BadArg)
+}
@@ -24,6 +24,9 @@ use test_api::*;
 use userlib::*;
 use zerocopy::AsBytes;
 
+#[cfg(feature = "i2c-devices")]
+use test_i2c_devices as i2c_devices;
+
 /// Helper macro for building a list of functions with their names.
 macro_rules! test_cases {
     ($($(#[$attr:meta])* $name:path,)*) => {
@@ -84,6 +87,60 @@ test_cases! {
     test_refresh_task_id_off_by_many,
     test_lpc55_flash_write,
     test_post,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_mock_nack,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_mock_inject_error,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_mock_pec,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_adm1272_30mv_60v,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_adm1272_15mv_100v,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_adt7420,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_lm75,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_max6634,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_mcp9808,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_pct2075,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_sbtsi,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_tmp116,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_tmp451,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_tse2004av,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_at24,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_at24_block_address,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_ds2482,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_emc2305,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_max31790,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_ina226,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_pmbus_device_linear,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_pmbus_device_direct,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_isl68224,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_raa229618,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_energy_count,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_bmr491,
+    #[cfg(feature = "i2c-devices")]
+    i2c_devices::test_tps546b24a,
 }
 
 #[cfg(feature = "lpc55")]